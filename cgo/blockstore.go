@@ -10,6 +10,7 @@ import (
 
 /*
 #include <stdint.h>
+#include <stdlib.h>
 typedef const uint8_t* buf_t;
 */
 import "C"
@@ -54,6 +55,67 @@ func cgo_blockstore_get(handle C.uint64_t, k C.buf_t, kLen C.int32_t, block **C.
 	}
 }
 
+//export cgo_blockstore_get_many
+func cgo_blockstore_get_many(handle C.uint64_t, kLengths *C.int32_t, kLengthsLen C.int32_t, ks C.buf_t, blocks **C.uint8_t, sizes *C.int32_t) (res C.int32_t) {
+	var blocksGo []*C.uint8_t
+	var sizesGo []C.int32_t
+	// The caller only takes ownership of the blocks on success, so free the ones we've already
+	// fetched if we fail (or panic) part way through. This runs after the panic handler below.
+	defer func() {
+		if res == 0 {
+			return
+		}
+		for i, block := range blocksGo {
+			if block != nil {
+				C.free(unsafe.Pointer(block))
+				blocksGo[i] = nil
+				sizesGo[i] = 0
+			}
+		}
+	}()
+	defer func() {
+		if rerr := recover(); rerr != nil {
+			logPanic(rerr)
+			res = ErrPanic
+		}
+	}()
+	externs, ctx := Lookup(uint64(handle))
+	if externs == nil {
+		return ErrInvalidHandle
+	}
+	// Get a reference to the input/output vectors without copying.
+	const MAX_LEN = 1 << 30
+	if kLengthsLen > MAX_LEN {
+		return ErrInvalidArgument
+	}
+
+	lengthsGo := (*[MAX_LEN]C.int32_t)(unsafe.Pointer(kLengths))[:kLengthsLen:kLengthsLen]
+	blocksGo = (*[MAX_LEN]*C.uint8_t)(unsafe.Pointer(blocks))[:kLengthsLen:kLengthsLen]
+	sizesGo = (*[MAX_LEN]C.int32_t)(unsafe.Pointer(sizes))[:kLengthsLen:kLengthsLen]
+	for i, length := range lengthsGo {
+		if length > MAX_LEN {
+			return ErrInvalidArgument
+		}
+		c := toCid(ks, length)
+
+		// Missing blocks are left as null pointers.
+		err := externs.View(ctx, c, func(data []byte) error {
+			blocksGo[i] = (C.buf_t)(C.CBytes(data))
+			sizesGo[i] = C.int32_t(len(data))
+			return nil
+		})
+		switch err {
+		case nil, blockstore.ErrNotFound:
+		default:
+			return ErrIO
+		}
+
+		// Advance the key buffer.
+		ks = (C.buf_t)(unsafe.Pointer(uintptr(unsafe.Pointer(ks)) + uintptr(length)))
+	}
+	return 0
+}
+
 //export cgo_blockstore_put
 func cgo_blockstore_put(handle C.uint64_t, k C.buf_t, kLen C.int32_t, block C.buf_t, blockLen C.int32_t) (res C.int32_t) {
 	defer func() {
@@ -52,3 +52,53 @@ const (
 	RegisteredUpdateProofStackedDrg32GiBV1  = C.REGISTERED_UPDATE_PROOF_STACKED_DRG32_GI_B_V1
 	RegisteredUpdateProofStackedDrg64GiBV1  = C.REGISTERED_UPDATE_PROOF_STACKED_DRG64_GI_B_V1
 )
+
+const (
+	FvmMessageSyntaxErrorNoError               = C.FVM_MESSAGE_SYNTAX_ERROR_NO_ERROR
+	FvmMessageSyntaxErrorInvalidEncoding       = C.FVM_MESSAGE_SYNTAX_ERROR_INVALID_ENCODING
+	FvmMessageSyntaxErrorInvalidVersion        = C.FVM_MESSAGE_SYNTAX_ERROR_INVALID_VERSION
+	FvmMessageSyntaxErrorInvalidToAddress      = C.FVM_MESSAGE_SYNTAX_ERROR_INVALID_TO_ADDRESS
+	FvmMessageSyntaxErrorInvalidFromAddress    = C.FVM_MESSAGE_SYNTAX_ERROR_INVALID_FROM_ADDRESS
+	FvmMessageSyntaxErrorNegativeValue         = C.FVM_MESSAGE_SYNTAX_ERROR_NEGATIVE_VALUE
+	FvmMessageSyntaxErrorValueTooLarge         = C.FVM_MESSAGE_SYNTAX_ERROR_VALUE_TOO_LARGE
+	FvmMessageSyntaxErrorNegativeGasFeeCap     = C.FVM_MESSAGE_SYNTAX_ERROR_NEGATIVE_GAS_FEE_CAP
+	FvmMessageSyntaxErrorNegativeGasPremium    = C.FVM_MESSAGE_SYNTAX_ERROR_NEGATIVE_GAS_PREMIUM
+	FvmMessageSyntaxErrorGasPremiumAboveFeeCap = C.FVM_MESSAGE_SYNTAX_ERROR_GAS_PREMIUM_ABOVE_FEE_CAP
+	FvmMessageSyntaxErrorGasLimitNotPositive   = C.FVM_MESSAGE_SYNTAX_ERROR_GAS_LIMIT_NOT_POSITIVE
+	FvmMessageSyntaxErrorGasLimitTooLarge      = C.FVM_MESSAGE_SYNTAX_ERROR_GAS_LIMIT_TOO_LARGE
+)
+
+const (
+	FvmTraceFieldNoDivergence = C.FVM_TRACE_FIELD_NO_DIVERGENCE
+	FvmTraceFieldMessage      = C.FVM_TRACE_FIELD_MESSAGE
+	FvmTraceFieldExitCode     = C.FVM_TRACE_FIELD_EXIT_CODE
+	FvmTraceFieldGasUsed      = C.FVM_TRACE_FIELD_GAS_USED
+	FvmTraceFieldReturnData   = C.FVM_TRACE_FIELD_RETURN_DATA
+	FvmTraceFieldError        = C.FVM_TRACE_FIELD_ERROR
+	FvmTraceFieldSubcalls     = C.FVM_TRACE_FIELD_SUBCALLS
+)
+
+const (
+	FvmReceiverKindUnknown     = C.FVM_RECEIVER_KIND_UNKNOWN
+	FvmReceiverKindAccount     = C.FVM_RECEIVER_KIND_ACCOUNT
+	FvmReceiverKindPlaceholder = C.FVM_RECEIVER_KIND_PLACEHOLDER
+	FvmReceiverKindEvm         = C.FVM_RECEIVER_KIND_EVM
+	FvmReceiverKindBuiltin     = C.FVM_RECEIVER_KIND_BUILTIN
+)
+
+const (
+	FvmExitCodeClassNoError = C.FVM_EXIT_CODE_CLASS_NO_ERROR
+	FvmExitCodeClassSystem  = C.FVM_EXIT_CODE_CLASS_SYSTEM
+	FvmExitCodeClassUser    = C.FVM_EXIT_CODE_CLASS_USER
+)
+
+const (
+	FvmActorSourceBuiltin   = C.FVM_ACTOR_SOURCE_BUILTIN
+	FvmActorSourceOverride  = C.FVM_ACTOR_SOURCE_OVERRIDE
+	FvmActorSourceFromState = C.FVM_ACTOR_SOURCE_FROM_STATE
+)
+
+const (
+	FvmLotusTraceFormatFvm    = C.FVM_LOTUS_TRACE_FORMAT_FVM
+	FvmLotusTraceFormatLegacy = C.FVM_LOTUS_TRACE_FORMAT_LEGACY
+)
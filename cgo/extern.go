@@ -116,3 +116,31 @@ func cgo_extern_verify_consensus_fault(
 
 	return 0
 }
+
+//export cgo_extern_report_execution
+func cgo_extern_report_execution(
+	handle C.uint64_t,
+	execId, exitCode, gasUsed, numSubcalls C.uint64_t,
+) (res C.int32_t) {
+	defer func() {
+		if rerr := recover(); rerr != nil {
+			logPanic(rerr)
+			res = ErrPanic
+		}
+	}()
+
+	externs, ctx := Lookup(uint64(handle))
+	if externs == nil {
+		return ErrInvalidHandle
+	}
+
+	if receiver, ok := externs.(ExecutionSummaryReceiver); ok {
+		receiver.OnExecution(ctx, ExecutionSummary{
+			ExecID:      uint64(execId),
+			ExitCode:    uint64(exitCode),
+			GasUsed:     uint64(gasUsed),
+			NumSubcalls: uint64(numSubcalls),
+		})
+	}
+	return 0
+}
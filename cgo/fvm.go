@@ -8,7 +8,7 @@ package cgo
 */
 import "C"
 
-func CreateFvmMachine(fvmVersion FvmRegisteredVersion, chainEpoch, baseFeeHi, baseFeeLo, baseCircSupplyHi, baseCircSupplyLo, networkVersion uint64, stateRoot SliceRefUint8, manifestCid SliceRefUint8, tracing bool, blockstoreId, externsId uint64) (*FvmMachine, error) {
+func CreateFvmMachine(fvmVersion FvmRegisteredVersion, chainEpoch, baseFeeHi, baseFeeLo, baseCircSupplyHi, baseCircSupplyLo, networkVersion uint64, stateRoot SliceRefUint8, manifestCid SliceRefUint8, options FvmMachineOptionsGo, blockstoreId, externsId uint64) (*FvmMachine, bool, FvmActorSource, error) {
 	resp := C.create_fvm_machine(
 		fvmVersion,
 		C.uint64_t(chainEpoch),
@@ -19,28 +19,29 @@ func CreateFvmMachine(fvmVersion FvmRegisteredVersion, chainEpoch, baseFeeHi, ba
 		C.uint64_t(networkVersion),
 		stateRoot,
 		manifestCid,
-		C.bool(tracing),
+		options.toC(),
 		C.uint64_t(blockstoreId),
 		C.uint64_t(externsId),
 	)
 	// take out the pointer from the result to ensure it doesn't get freed
-	executor := resp.value
-	resp.value = nil
+	executor := resp.value.machine
+	resp.value.machine = nil
 	defer resp.destroy()
 
 	if err := CheckErr(resp); err != nil {
-		return nil, err
+		return nil, false, 0, err
 	}
 
-	return executor, nil
+	return executor, bool(resp.value.forced_manifest), resp.value.actor_source, nil
 }
 
-func FvmMachineExecuteMessage(executor *FvmMachine, message SliceRefUint8, chainLen, applyKind uint64) (FvmMachineExecuteResponseGo, error) {
+func FvmMachineExecuteMessage(executor *FvmMachine, message SliceRefUint8, chainLen, applyKind uint64, options FvmExecuteOptionsGo) (FvmMachineExecuteResponseGo, error) {
 	resp := C.fvm_machine_execute_message(
 		executor,
 		message,
 		C.uint64_t(chainLen),
 		C.uint64_t(applyKind),
+		options.toC(),
 	)
 	defer resp.destroy()
 
@@ -51,12 +52,411 @@ func FvmMachineExecuteMessage(executor *FvmMachine, message SliceRefUint8, chain
 	return resp.value.copy(), nil
 }
 
-func FvmMachineFlush(executor *FvmMachine) ([]byte, error) {
+func FvmMachineExecuteSignedMessage(executor *FvmMachine, signedMessage SliceRefUint8, chainLen uint64, options FvmExecuteOptionsGo) (FvmMachineExecuteResponseGo, error) {
+	resp := C.fvm_machine_execute_signed_message(
+		executor,
+		signedMessage,
+		C.uint64_t(chainLen),
+		options.toC(),
+	)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return FvmMachineExecuteResponseGo{}, err
+	}
+
+	return resp.value.copy(), nil
+}
+
+// FvmMachineExecuteAndFlush applies an explicit message and flushes the machine under a single
+// lock, returning the execution result and the new state root.
+func FvmMachineExecuteAndFlush(executor *FvmMachine, message SliceRefUint8, chainLen uint64) (FvmMachineExecuteResponseGo, []byte, error) {
+	resp := C.fvm_machine_execute_and_flush(executor, message, C.uint64_t(chainLen))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return FvmMachineExecuteResponseGo{}, nil, err
+	}
+	return resp.value.execution.copy(), resp.value.state_root.copy(), nil
+}
+
+// FvmMachineExecuteForRoot applies an explicit message and flushes the machine, returning only the
+// exit code and the new state root.
+func FvmMachineExecuteForRoot(executor *FvmMachine, message SliceRefUint8, chainLen uint64) (uint64, []byte, error) {
+	resp := C.fvm_machine_execute_for_root(executor, message, C.uint64_t(chainLen))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, nil, err
+	}
+	return uint64(resp.value.exit_code), resp.value.state_root.copy(), nil
+}
+
+// FvmMachineExecuteWithAccessList applies an explicit message and flushes the machine, returning
+// the exit code, the new state root, and the CBOR encoded access list of the message.
+func FvmMachineExecuteWithAccessList(executor *FvmMachine, message SliceRefUint8, chainLen uint64) (uint64, []byte, []byte, error) {
+	resp := C.fvm_machine_execute_with_access_list(executor, message, C.uint64_t(chainLen))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, nil, nil, err
+	}
+	return uint64(resp.value.exit_code), resp.value.state_root.copy(), resp.value.access_list.copy(), nil
+}
+
+// FvmMachineExecuteBatch applies explicit messages in order under a single lock, chainLens holding
+// the length on chain of every message.
+func FvmMachineExecuteBatch(executor *FvmMachine, messages SliceRefSliceBoxedUint8, chainLens SliceRefUint64, options FvmBatchOptionsGo) (FvmMachineExecuteBatchResponseGo, error) {
+	resp := C.fvm_machine_execute_batch(executor, messages, chainLens, options.toC())
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return FvmMachineExecuteBatchResponseGo{}, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmMachineFlush flushes the machine, returning the state root and whether the flush was skipped
+// because nothing changed since the last one.
+func FvmMachineFlush(executor *FvmMachine) ([]byte, bool, error) {
 	resp := C.fvm_machine_flush(executor)
 	defer resp.destroy()
 
+	if err := CheckErr(resp); err != nil {
+		return nil, false, err
+	}
+	return resp.value.state_root.copy(), bool(resp.value.was_noop), nil
+}
+
+func FvmMachineStateSize(executor *FvmMachine, root SliceRefUint8) (uint64, uint64, error) {
+	resp := C.fvm_machine_state_size(executor, root)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, 0, err
+	}
+	return uint64(resp.value.blocks), uint64(resp.value.bytes), nil
+}
+
+// FvmMachineVerifyCarRoundtrip returns whether the state rooted at root survives an export to CAR
+// and a reimport unchanged.
+func FvmMachineVerifyCarRoundtrip(executor *FvmMachine, root SliceRefUint8) (bool, error) {
+	resp := C.fvm_machine_verify_car_roundtrip(executor, root)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return false, err
+	}
+	return bool(resp.value), nil
+}
+
+// FvmMachineLastFlushWrites returns the CBOR encoded list of (cid, block) pairs the last flush wrote
+// to the blockstore.
+func FvmMachineLastFlushWrites(executor *FvmMachine) ([]byte, error) {
+	resp := C.fvm_machine_last_flush_writes(executor)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+func FvmMachineDumpStateJson(executor *FvmMachine, root SliceRefUint8) ([]byte, error) {
+	resp := C.fvm_machine_dump_state_json(executor, root)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmMachineGetBlock reads a block from the machine's blockstore, including unflushed writes.
+func FvmMachineGetBlock(executor *FvmMachine, cid SliceRefUint8) ([]byte, bool, error) {
+	resp := C.fvm_machine_get_block(executor, cid)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, false, err
+	}
+	if !bool(resp.value.found) {
+		return nil, false, nil
+	}
+	return resp.value.block.copy(), true, nil
+}
+
+func FvmMachineComputeReceiptsRoot(executor *FvmMachine, exitCodes SliceRefUint64, returnValues SliceRefSliceBoxedUint8, gasUsed SliceRefUint64) ([]byte, error) {
+	resp := C.fvm_machine_compute_receipts_root(executor, exitCodes, returnValues, gasUsed)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmMachineResolveAddresses resolves the addresses to actor IDs, in order. Addresses that don't
+// resolve are reported as math.MaxUint64.
+func FvmMachineResolveAddresses(executor *FvmMachine, addresses SliceRefSliceBoxedUint8) ([]uint64, error) {
+	resp := C.fvm_machine_resolve_addresses(executor, addresses)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmMachineInclusionGasForSize returns the on-chain inclusion gas of a message of the given
+// serialized size.
+func FvmMachineInclusionGasForSize(executor *FvmMachine, size uint64) (uint64, error) {
+	resp := C.fvm_machine_inclusion_gas_for_size(executor, C.uint64_t(size))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, err
+	}
+	return uint64(resp.value), nil
+}
+
+// FvmMachineActorNonce returns the sequence of the actor at the address, and whether it exists.
+func FvmMachineActorNonce(executor *FvmMachine, address SliceRefUint8) (uint64, bool, error) {
+	resp := C.fvm_machine_actor_nonce(executor, address)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, false, err
+	}
+	return uint64(resp.value.nonce), bool(resp.value.found), nil
+}
+
+// FvmMachineManifestCid returns the manifest CID used by the machine, or nil if it has none.
+func FvmMachineManifestCid(executor *FvmMachine) ([]byte, error) {
+	resp := C.fvm_machine_manifest_cid(executor)
+	defer resp.destroy()
+
 	if err := CheckErr(resp); err != nil {
 		return nil, err
 	}
 	return resp.value.copy(), nil
 }
+
+// FvmMachineVerifyManifest returns whether the machine uses the expected manifest.
+func FvmMachineVerifyManifest(executor *FvmMachine, expected SliceRefUint8) (bool, error) {
+	resp := C.fvm_machine_verify_manifest(executor, expected)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return false, err
+	}
+	return bool(resp.value), nil
+}
+
+func FvmMachineRecentExecutions(executor *FvmMachine) ([]byte, error) {
+	resp := C.fvm_machine_recent_executions(executor)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+func FvmMachinePrefetchState(executor *FvmMachine) (uint64, error) {
+	resp := C.fvm_machine_prefetch_state(executor)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, err
+	}
+	return uint64(resp.value), nil
+}
+
+// FvmSupportedNetworkVersions lists the network versions machines can be created for.
+func FvmSupportedNetworkVersions() ([]FvmSupportedNetworkVersionGo, error) {
+	resp := C.fvm_supported_network_versions()
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+func FvmSelfcheck() error {
+	resp := C.fvm_selfcheck()
+	defer resp.destroy()
+	return CheckErr(resp)
+}
+
+// FvmBenchmarkBlockstore times the given number of puts, gets, and has checks against the
+// blockstore. The blocks are never deleted.
+func FvmBenchmarkBlockstore(operations uint64, blockstoreId uint64) (FvmBlockstoreBenchmarkResponseGo, error) {
+	resp := C.fvm_benchmark_blockstore(C.uint64_t(operations), C.uint64_t(blockstoreId))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return FvmBlockstoreBenchmarkResponseGo{}, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmCompareTraces returns the subcall path to the first frame in which the two CBOR encoded
+// Lotus traces differ, and the first field that differs in that frame.
+func FvmCompareTraces(a, b SliceRefUint8) ([]uint64, FvmTraceField, error) {
+	resp := C.fvm_compare_traces(a, b)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, 0, err
+	}
+	return resp.value.path.copy(), resp.value.field, nil
+}
+
+// FvmTraceDetectReentrancy returns the CBOR encoded list of (address, path) entries for the frames
+// of the CBOR encoded Lotus trace that re-enter an actor already in their call chain.
+func FvmTraceDetectReentrancy(trace SliceRefUint8) ([]byte, error) {
+	resp := C.fvm_trace_detect_reentrancy(trace)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+func FvmEstimateGasPremium(nblocksIncl, blocks uint64, premiums, gasLimits SliceRefUint64) (uint64, error) {
+	resp := C.fvm_estimate_gas_premium(C.uint64_t(nblocksIncl), C.uint64_t(blocks), premiums, gasLimits)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, err
+	}
+	return uint64(resp.value), nil
+}
+
+func FvmSerializeExecutionResult(exitCode uint64, returnVal SliceRefUint8, gasUsed uint64, execTrace, stateRoot SliceRefUint8) ([]byte, error) {
+	resp := C.fvm_serialize_execution_result(C.uint64_t(exitCode), returnVal, C.uint64_t(gasUsed), execTrace, stateRoot)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+func FvmDeserializeExecutionResult(result SliceRefUint8) (FvmExecutionResultGo, error) {
+	resp := C.fvm_deserialize_execution_result(result)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return FvmExecutionResultGo{}, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmValidateCar returns whether the CAR is well-formed and single-rooted, and its roots.
+func FvmValidateCar(car SliceRefUint8) (bool, [][]byte, error) {
+	resp := C.fvm_validate_car(car)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return false, nil, err
+	}
+	return bool(resp.value.valid), resp.value.roots.copyAsBytes(), nil
+}
+
+func FvmMessageSize(message SliceRefUint8) (uint64, error) {
+	resp := C.fvm_message_size(message)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return 0, err
+	}
+	return uint64(resp.value), nil
+}
+
+// FvmGasParams returns the CBOR encoded key parameters of the network version's gas price list.
+func FvmGasParams(networkVersion uint64) ([]byte, error) {
+	resp := C.fvm_gas_params(C.uint64_t(networkVersion))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmExitCodeName returns the symbolic name of the exit code, or "" if it isn't a known one.
+func FvmExitCodeName(exitCode uint64) (string, error) {
+	resp := C.fvm_exit_code_name(C.uint64_t(exitCode))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return "", err
+	}
+	return string(resp.value.copy()), nil
+}
+
+func FvmEncodeMessage(from, to SliceRefUint8, sequence, valueHi, valueLo, methodNum uint64, params SliceRefUint8, gasLimit, gasFeeCapHi, gasFeeCapLo, gasPremiumHi, gasPremiumLo uint64) ([]byte, error) {
+	resp := C.fvm_encode_message(
+		from,
+		to,
+		C.uint64_t(sequence),
+		C.uint64_t(valueHi),
+		C.uint64_t(valueLo),
+		C.uint64_t(methodNum),
+		params,
+		C.uint64_t(gasLimit),
+		C.uint64_t(gasFeeCapHi),
+		C.uint64_t(gasFeeCapLo),
+		C.uint64_t(gasPremiumHi),
+		C.uint64_t(gasPremiumLo),
+	)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmEmptyStateRoot writes a state tree holding only the system and init actors to the
+// blockstore, and returns its root. An empty manifest selects the builtin actors.
+func FvmEmptyStateRoot(networkVersion uint64, manifestCid, networkName SliceRefUint8, blockstoreId uint64) ([]byte, error) {
+	resp := C.fvm_empty_state_root(
+		C.uint64_t(networkVersion),
+		manifestCid,
+		networkName,
+		C.uint64_t(blockstoreId),
+	)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return nil, err
+	}
+	return resp.value.copy(), nil
+}
+
+// FvmValidateBatchGas returns whether the gas limits of the messages add up to at most the block
+// gas limit, and by how much they exceed it otherwise.
+func FvmValidateBatchGas(messages SliceRefSliceBoxedUint8, blockGasLimit uint64) (bool, uint64, error) {
+	resp := C.fvm_validate_batch_gas(messages, C.uint64_t(blockGasLimit))
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return false, 0, err
+	}
+	return bool(resp.value.fits), uint64(resp.value.overage), nil
+}
+
+func FvmValidateMessageSyntax(message SliceRefUint8) (bool, FvmMessageSyntaxError, error) {
+	resp := C.fvm_validate_message_syntax(message)
+	defer resp.destroy()
+
+	if err := CheckErr(resp); err != nil {
+		return false, 0, err
+	}
+	return bool(resp.value.valid), resp.value.reason, nil
+}
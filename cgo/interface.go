@@ -27,6 +27,22 @@ const (
 	ConsensusFaultTimeOffsetMining ConsensusFaultType = 3
 )
 
+// ExecutionSummary is the fixed-size summary of an applied message, streamed to Externs
+// implementing ExecutionSummaryReceiver.
+type ExecutionSummary struct {
+	ExecID   uint64
+	ExitCode uint64
+	GasUsed  uint64
+	// NumSubcalls is the number of subcalls made by the message, zero if the FVM isn't tracing.
+	NumSubcalls uint64
+}
+
+// ExecutionSummaryReceiver can be implemented by Externs to be handed a summary of every message
+// applied by an FVM created with FVMOpts.StreamSummaries, right after it's applied.
+type ExecutionSummaryReceiver interface {
+	OnExecution(ctx context.Context, summary ExecutionSummary)
+}
+
 type Externs interface {
 	GetChainRandomness(ctx context.Context, personalization crypto.DomainSeparationTag, epoch abi.ChainEpoch, entropy []byte) ([]byte, error)
 	GetBeaconRandomness(ctx context.Context, personalization crypto.DomainSeparationTag, epoch abi.ChainEpoch, entropy []byte) ([]byte, error)
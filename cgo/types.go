@@ -19,6 +19,17 @@ type RegisteredPoStProof = C.RegisteredPoStProof_t
 type RegisteredUpdateProof = C.RegisteredUpdateProof_t
 
 type FvmRegisteredVersion = C.FvmRegisteredVersion_t
+type FvmMessageSyntaxError = C.FvmMessageSyntaxError_t
+type FvmTraceField = C.FvmTraceField_t
+type FvmReceiverKind = C.FvmReceiverKind_t
+type FvmExitCodeClass = C.FvmExitCodeClass_t
+type FvmActorSource = C.FvmActorSource_t
+type FvmLotusTraceFormat = C.FvmLotusTraceFormat_t
+type FvmSupportedNetworkVersion = C.FvmSupportedNetworkVersion_t
+type FvmReceiverGas = C.FvmReceiverGas_t
+type FvmMachineOptions = C.FvmMachineOptions_t
+type FvmExecuteOptions = C.FvmExecuteOptions_t
+type FvmBatchOptions = C.FvmBatchOptions_t
 
 type AggregationInputs = C.AggregationInputs_t
 
@@ -40,6 +51,9 @@ type SliceRefUint = C.slice_ref_size_t
 type SliceRefAggregationInputs = C.slice_ref_AggregationInputs_t
 
 type SliceBoxedPoStProof = C.struct_slice_boxed_PoStProof
+type SliceBoxedFvmSupportedNetworkVersion = C.struct_slice_boxed_FvmSupportedNetworkVersion
+type SliceBoxedFvmMachineExecuteResponse = C.struct_slice_boxed_FvmMachineExecuteResponse
+type SliceBoxedFvmReceiverGas = C.struct_slice_boxed_FvmReceiverGas
 type SliceBoxedUint64 = C.struct_slice_boxed_uint64
 type SliceBoxedSliceBoxedUint8 = C.slice_boxed_slice_boxed_uint8_t
 type SliceBoxedSliceBoxedUint64 = C.slice_boxed_slice_boxed_uint64_t
@@ -51,6 +65,9 @@ type ByteArray96 = C.uint8_96_array_t
 
 type FvmMachine = C.InnerFvmMachine_t
 type FvmMachineExecuteResponse = C.FvmMachineExecuteResponse_t
+type FvmMachineExecuteBatchResponse = C.FvmMachineExecuteBatchResponse_t
+type FvmExecutionResult = C.FvmExecutionResult_t
+type FvmBlockstoreBenchmarkResponse = C.FvmBlockstoreBenchmarkResponse_t
 
 type resultBool = C.Result_bool_t
 type resultGeneratePieceCommitment = C.Result_GeneratePieceCommitment_t
@@ -63,6 +80,7 @@ type resultSliceBoxedUint8 = C.Result_slice_boxed_uint8_t
 type resultSliceBoxedPoStProof = C.Result_slice_boxed_PoStProof_t
 type resultSliceBoxedUint64 = C.Result_slice_boxed_uint64_t
 type resultUint = C.Result_size_t
+type resultUint64 = C.Result_uint64_t
 type resultSliceBoxedSliceBoxedUint8 = C.Result_slice_boxed_slice_boxed_uint8_t
 type resultGenerateWindowPoSt = C.Result_GenerateWindowPoSt_t
 type resultEmptySectorUpdateEncodeInto = C.Result_EmptySectorUpdateEncodeInto_t
@@ -70,8 +88,23 @@ type resultGenerateFallbackSectorChallenges = C.Result_GenerateFallbackSectorCha
 type resultGenerateSingleWindowPoStWithVanilla = C.Result_GenerateSingleWindowPoStWithVanilla_t
 type resultPoStProof = C.Result_PoStProof_t
 
-type resultFvmMachine = C.Result_InnerFvmMachine_ptr_t
+type resultFvmMachine = C.Result_FvmMachineCreateResponse_t
 type resultFvmMachineExecuteResponse = C.Result_FvmMachineExecuteResponse_t
+type resultFvmMachineFlushResponse = C.Result_FvmMachineFlushResponse_t
+type resultFvmMachineExecuteAndFlushResponse = C.Result_FvmMachineExecuteAndFlushResponse_t
+type resultFvmMachineExecuteForRootResponse = C.Result_FvmMachineExecuteForRootResponse_t
+type resultFvmMachineExecuteWithAccessListResponse = C.Result_FvmMachineExecuteWithAccessListResponse_t
+type resultFvmMachineExecuteBatchResponse = C.Result_FvmMachineExecuteBatchResponse_t
+type resultFvmMachineStateSizeResponse = C.Result_FvmMachineStateSizeResponse_t
+type resultFvmValidateMessageSyntaxResponse = C.Result_FvmValidateMessageSyntaxResponse_t
+type resultFvmValidateBatchGasResponse = C.Result_FvmValidateBatchGasResponse_t
+type resultFvmCompareTracesResponse = C.Result_FvmCompareTracesResponse_t
+type resultFvmMachineGetBlockResponse = C.Result_FvmMachineGetBlockResponse_t
+type resultFvmExecutionResult = C.Result_FvmExecutionResult_t
+type resultFvmValidateCarResponse = C.Result_FvmValidateCarResponse_t
+type resultFvmMachineActorNonceResponse = C.Result_FvmMachineActorNonceResponse_t
+type resultFvmBlockstoreBenchmarkResponse = C.Result_FvmBlockstoreBenchmarkResponse_t
+type resultSliceBoxedFvmSupportedNetworkVersion = C.Result_slice_boxed_FvmSupportedNetworkVersion_t
 
 type result interface {
 	statusCode() FCPResponseStatus
@@ -91,25 +124,96 @@ type PoStProofGo struct {
 	Proof           []byte
 }
 
+/// FvmMachineOptionsGo is a go allocated version of `FvmMachineOptions`.
+type FvmMachineOptionsGo struct {
+	Tracing          bool
+	CompactTrace     bool
+	LotusTraceFormat FvmLotusTraceFormat
+	StreamSummaries  bool
+	MaxReturnSize    uint64
+	RecentExecutions uint64
+	ReadCacheSize    uint64
+	ReadOnly         bool
+	FlushWriteLog    uint64
+}
+
+/// FvmExecuteOptionsGo is a go allocated version of `FvmExecuteOptions`.
+type FvmExecuteOptionsGo struct {
+	GasCeiling       uint64
+	Strict           bool
+	StateRoots       bool
+	AllocationBudget uint64
+	GasFree          bool
+	StateProofs      bool
+	MaxSubcalls      uint64
+	ReceiverState    bool
+	GCCandidates     uint64
+	TraceDepthLimit  uint64
+	InvokedMethods   bool
+	ReceiverInfo     bool
+	BalanceMargin    bool
+	ActorsTouched    bool
+	VerifySignature  bool
+}
+
+/// FvmBatchOptionsGo is a go allocated version of `FvmBatchOptions`.
+type FvmBatchOptionsGo struct {
+	Execute       FvmExecuteOptionsGo
+	CreatedActors bool
+	AllOrNothing  bool
+	ReceiverGas   bool
+}
+
 /// FvmMachineExecuteResponse is a go allocated version of `FvmMachineExecuteResponse`.
 type FvmMachineExecuteResponseGo struct {
-	ExitCode             uint64
-	ReturnVal            []byte
-	GasUsed              uint64
-	PenaltyHi            uint64
-	PenaltyLo            uint64
-	MinerTipHi           uint64
-	MinerTipLo           uint64
-	BaseFeeBurnHi        uint64
-	BaseFeeBurnLo        uint64
-	OverEstimationBurnHi uint64
-	OverEstimationBurnLo uint64
-	RefundHi             uint64
-	RefundLo             uint64
-	GasRefund            int64
-	GasBurned            int64
-	ExecTrace            []byte
-	FailureInfo          string
+	ExecID                uint64
+	ExitCode              uint64
+	ExitCodeClass         FvmExitCodeClass
+	ReturnVal             []byte
+	ReturnTruncated       bool
+	ReturnLen             uint64
+	ReceiverKind          FvmReceiverKind
+	ActorCreated          bool
+	CreatedActorID        uint64
+	MethodName            string
+	ParamsLen             uint64
+	ParamsCodec           uint64
+	ParamsDecodeFailed    bool
+	ActorsTouched         uint64
+	GasUsed               uint64
+	DirectGas             uint64
+	SubcallGas            uint64
+	LockWaitNs            uint64
+	PenaltyHi             uint64
+	PenaltyLo             uint64
+	MinerTipHi            uint64
+	MinerTipLo            uint64
+	BaseFeeBurnHi         uint64
+	BaseFeeBurnLo         uint64
+	OverEstimationBurnHi  uint64
+	OverEstimationBurnLo  uint64
+	RefundHi              uint64
+	RefundLo              uint64
+	EffectiveBaseFeeHi    uint64
+	EffectiveBaseFeeLo    uint64
+	EffectivePremiumHi    uint64
+	EffectivePremiumLo    uint64
+	BalanceMarginNegative bool
+	BalanceMarginHi       uint64
+	BalanceMarginLo       uint64
+	GasRefund             int64
+	GasBurned             int64
+	ExecTrace             []byte
+	TraceTruncated        bool
+	FailureInfo           string
+	RandomnessRequests    []byte
+	PenaltyBreakdown      []byte
+	PreStateRoot          []byte
+	PostStateRoot         []byte
+	StateProofs           []byte
+	ReceiverState         []byte
+	GCCandidates          [][]byte
+	InvokedMethods        []byte
 }
 
 func (ptr SliceBoxedUint8) slice() []byte {
@@ -500,6 +604,22 @@ func (ptr *resultUint) destroy() {
 	}
 }
 
+func (ptr *resultUint64) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultUint64) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultUint64) destroy() {
+	if ptr != nil {
+		// TODO: correct naming
+		C.destroy_fvm_message_size_response(ptr)
+		ptr = nil
+	}
+}
+
 func (ptr *resultEmptySectorUpdateEncodeInto) statusCode() FCPResponseStatus {
 	return FCPResponseStatus(ptr.status_code)
 }
@@ -620,6 +740,216 @@ func (ptr *resultFvmMachineExecuteResponse) destroy() {
 	}
 }
 
+func (ptr *resultFvmMachineFlushResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineFlushResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineFlushResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_flush_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineExecuteAndFlushResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineExecuteAndFlushResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineExecuteAndFlushResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_execute_and_flush_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineExecuteForRootResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineExecuteForRootResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineExecuteForRootResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_execute_for_root_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineExecuteWithAccessListResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineExecuteWithAccessListResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineExecuteWithAccessListResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_execute_with_access_list_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineExecuteBatchResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineExecuteBatchResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineExecuteBatchResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_execute_batch_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineStateSizeResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineStateSizeResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineStateSizeResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_state_size_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmValidateMessageSyntaxResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmValidateMessageSyntaxResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmValidateMessageSyntaxResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_validate_message_syntax_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmValidateBatchGasResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmValidateBatchGasResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmValidateBatchGasResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_validate_batch_gas_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineGetBlockResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineGetBlockResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineGetBlockResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_get_block_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmCompareTracesResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmCompareTracesResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmCompareTracesResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_compare_traces_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmExecutionResult) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmExecutionResult) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmExecutionResult) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_deserialize_execution_result_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmValidateCarResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmValidateCarResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmValidateCarResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_validate_car_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmMachineActorNonceResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmMachineActorNonceResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmMachineActorNonceResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_machine_actor_nonce_response(ptr)
+		ptr = nil
+	}
+}
+
+func (ptr *resultFvmBlockstoreBenchmarkResponse) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultFvmBlockstoreBenchmarkResponse) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultFvmBlockstoreBenchmarkResponse) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_benchmark_blockstore_response(ptr)
+		ptr = nil
+	}
+}
+
 func (ptr *resultFvmMachine) statusCode() FCPResponseStatus {
 	return FCPResponseStatus(ptr.status_code)
 }
@@ -642,24 +972,255 @@ func (ptr *FvmMachine) Destroy() {
 	}
 }
 
+func (o FvmMachineOptionsGo) toC() FvmMachineOptions {
+	return FvmMachineOptions{
+		tracing:            C.bool(o.Tracing),
+		compact_trace:      C.bool(o.CompactTrace),
+		lotus_trace_format: o.LotusTraceFormat,
+		stream_summaries:   C.bool(o.StreamSummaries),
+		max_return_size:    C.uint64_t(o.MaxReturnSize),
+		recent_executions:  C.uint64_t(o.RecentExecutions),
+		read_cache_size:    C.uint64_t(o.ReadCacheSize),
+		read_only:          C.bool(o.ReadOnly),
+		flush_write_log:    C.uint64_t(o.FlushWriteLog),
+	}
+}
+
+func (o FvmExecuteOptionsGo) toC() FvmExecuteOptions {
+	return FvmExecuteOptions{
+		gas_ceiling:       C.uint64_t(o.GasCeiling),
+		strict:            C.bool(o.Strict),
+		state_roots:       C.bool(o.StateRoots),
+		allocation_budget: C.uint64_t(o.AllocationBudget),
+		gas_free:          C.bool(o.GasFree),
+		state_proofs:      C.bool(o.StateProofs),
+		max_subcalls:      C.uint64_t(o.MaxSubcalls),
+		receiver_state:    C.bool(o.ReceiverState),
+		gc_candidates:     C.uint64_t(o.GCCandidates),
+		trace_depth_limit: C.uint64_t(o.TraceDepthLimit),
+		invoked_methods:   C.bool(o.InvokedMethods),
+		receiver_info:     C.bool(o.ReceiverInfo),
+		balance_margin:    C.bool(o.BalanceMargin),
+		actors_touched:    C.bool(o.ActorsTouched),
+		verify_signature:  C.bool(o.VerifySignature),
+	}
+}
+
+func (o FvmBatchOptionsGo) toC() FvmBatchOptions {
+	return FvmBatchOptions{
+		execute:        o.Execute.toC(),
+		created_actors: C.bool(o.CreatedActors),
+		all_or_nothing: C.bool(o.AllOrNothing),
+		receiver_gas:   C.bool(o.ReceiverGas),
+	}
+}
+
 func (r FvmMachineExecuteResponse) copy() FvmMachineExecuteResponseGo {
 	return FvmMachineExecuteResponseGo{
-		ExitCode:             uint64(r.exit_code),
-		ReturnVal:            r.return_val.copy(),
-		GasUsed:              uint64(r.gas_used),
-		PenaltyHi:            uint64(r.penalty_hi),
-		PenaltyLo:            uint64(r.penalty_lo),
-		MinerTipHi:           uint64(r.miner_tip_hi),
-		MinerTipLo:           uint64(r.miner_tip_lo),
-		BaseFeeBurnHi:        uint64(r.base_fee_burn_hi),
-		BaseFeeBurnLo:        uint64(r.base_fee_burn_lo),
-		OverEstimationBurnHi: uint64(r.over_estimation_burn_hi),
-		OverEstimationBurnLo: uint64(r.over_estimation_burn_lo),
-		RefundHi:             uint64(r.refund_hi),
-		RefundLo:             uint64(r.refund_lo),
-		GasRefund:            int64(r.gas_refund),
-		GasBurned:            int64(r.gas_burned),
-		ExecTrace:            r.exec_trace.copy(),
-		FailureInfo:          string(r.failure_info.slice()),
+		ExitCode:              uint64(r.exit_code),
+		ExitCodeClass:         r.exit_code_class,
+		ExecID:                uint64(r.exec_id),
+		ReturnVal:             r.return_val.copy(),
+		ReturnTruncated:       bool(r.return_truncated),
+		ReturnLen:             uint64(r.return_len),
+		ReceiverKind:          r.receiver_kind,
+		ActorCreated:          bool(r.actor_created),
+		CreatedActorID:        uint64(r.created_actor_id),
+		MethodName:            string(r.method_name.slice()),
+		ParamsLen:             uint64(r.params_len),
+		ParamsCodec:           uint64(r.params_codec),
+		ParamsDecodeFailed:    bool(r.params_decode_failed),
+		ActorsTouched:         uint64(r.actors_touched),
+		GasUsed:               uint64(r.gas_used),
+		DirectGas:             uint64(r.direct_gas),
+		SubcallGas:            uint64(r.subcall_gas),
+		LockWaitNs:            uint64(r.lock_wait_ns),
+		PenaltyHi:             uint64(r.penalty_hi),
+		PenaltyLo:             uint64(r.penalty_lo),
+		MinerTipHi:            uint64(r.miner_tip_hi),
+		MinerTipLo:            uint64(r.miner_tip_lo),
+		BaseFeeBurnHi:         uint64(r.base_fee_burn_hi),
+		BaseFeeBurnLo:         uint64(r.base_fee_burn_lo),
+		OverEstimationBurnHi:  uint64(r.over_estimation_burn_hi),
+		OverEstimationBurnLo:  uint64(r.over_estimation_burn_lo),
+		RefundHi:              uint64(r.refund_hi),
+		RefundLo:              uint64(r.refund_lo),
+		EffectiveBaseFeeHi:    uint64(r.effective_base_fee_hi),
+		EffectiveBaseFeeLo:    uint64(r.effective_base_fee_lo),
+		EffectivePremiumHi:    uint64(r.effective_premium_hi),
+		EffectivePremiumLo:    uint64(r.effective_premium_lo),
+		BalanceMarginNegative: bool(r.balance_margin_negative),
+		BalanceMarginHi:       uint64(r.balance_margin_hi),
+		BalanceMarginLo:       uint64(r.balance_margin_lo),
+		GasRefund:             int64(r.gas_refund),
+		GasBurned:             int64(r.gas_burned),
+		ExecTrace:             r.exec_trace.copy(),
+		TraceTruncated:        bool(r.trace_truncated),
+		FailureInfo:           string(r.failure_info.slice()),
+		RandomnessRequests:    r.randomness_requests.copy(),
+		PenaltyBreakdown:      r.penalty_breakdown.copy(),
+		PreStateRoot:          r.pre_state_root.copy(),
+		PostStateRoot:         r.post_state_root.copy(),
+		StateProofs:           r.state_proofs.copy(),
+		ReceiverState:         r.receiver_state.copy(),
+		GCCandidates:          r.gc_candidates.copyAsBytes(),
+		InvokedMethods:        r.invoked_methods.copy(),
+	}
+}
+
+func (ptr SliceBoxedFvmMachineExecuteResponse) slice() []FvmMachineExecuteResponse {
+	if ptr.ptr == nil {
+		return nil
+	}
+	return unsafe.Slice((*FvmMachineExecuteResponse)(unsafe.Pointer(ptr.ptr)), int(ptr.len))
+}
+
+func (ptr SliceBoxedFvmMachineExecuteResponse) copy() []FvmMachineExecuteResponseGo {
+	if ptr.ptr == nil {
+		return nil
+	} else if ptr.len == 0 {
+		return []FvmMachineExecuteResponseGo{}
+	}
+
+	ref := ptr.slice()
+	res := make([]FvmMachineExecuteResponseGo, len(ref))
+	for i := range ref {
+		res[i] = ref[i].copy()
+	}
+	return res
+}
+
+/// FvmReceiverGasGo is a go allocated version of `FvmReceiverGas`.
+type FvmReceiverGasGo struct {
+	ReceiverID uint64
+	GasUsed    uint64
+}
+
+func (ptr SliceBoxedFvmReceiverGas) slice() []FvmReceiverGas {
+	if ptr.ptr == nil {
+		return nil
+	}
+	return unsafe.Slice((*FvmReceiverGas)(unsafe.Pointer(ptr.ptr)), int(ptr.len))
+}
+
+func (ptr SliceBoxedFvmReceiverGas) copy() []FvmReceiverGasGo {
+	if ptr.ptr == nil {
+		return nil
+	} else if ptr.len == 0 {
+		return []FvmReceiverGasGo{}
+	}
+
+	ref := ptr.slice()
+	res := make([]FvmReceiverGasGo, len(ref))
+	for i := range ref {
+		res[i] = FvmReceiverGasGo{
+			ReceiverID: uint64(ref[i].receiver_id),
+			GasUsed:    uint64(ref[i].gas_used),
+		}
+	}
+	return res
+}
+
+/// FvmMachineExecuteBatchResponseGo is a go allocated version of `FvmMachineExecuteBatchResponse`.
+type FvmMachineExecuteBatchResponseGo struct {
+	Results       []FvmMachineExecuteResponseGo
+	CreatedActors []uint64
+	Failed        bool
+	FailedIndex   uint64
+	ReceiverGas   []FvmReceiverGasGo
+}
+
+func (r FvmMachineExecuteBatchResponse) copy() FvmMachineExecuteBatchResponseGo {
+	return FvmMachineExecuteBatchResponseGo{
+		Results:       r.results.copy(),
+		CreatedActors: r.created_actors.copy(),
+		Failed:        bool(r.failed),
+		FailedIndex:   uint64(r.failed_index),
+		ReceiverGas:   r.receiver_gas.copy(),
+	}
+}
+
+/// FvmExecutionResultGo is a go allocated version of `FvmExecutionResult`.
+type FvmExecutionResultGo struct {
+	ExitCode  uint64
+	ReturnVal []byte
+	GasUsed   uint64
+	ExecTrace []byte
+	StateRoot []byte
+}
+
+func (r FvmExecutionResult) copy() FvmExecutionResultGo {
+	return FvmExecutionResultGo{
+		ExitCode:  uint64(r.exit_code),
+		ReturnVal: r.return_val.copy(),
+		GasUsed:   uint64(r.gas_used),
+		ExecTrace: r.exec_trace.copy(),
+		StateRoot: r.state_root.copy(),
+	}
+}
+
+/// FvmBlockstoreBenchmarkResponseGo is a go allocated version of `FvmBlockstoreBenchmarkResponse`.
+type FvmBlockstoreBenchmarkResponseGo struct {
+	Operations   uint64
+	PutNs        uint64
+	GetNs        uint64
+	HasNs        uint64
+	OpsPerSecond uint64
+}
+
+func (r FvmBlockstoreBenchmarkResponse) copy() FvmBlockstoreBenchmarkResponseGo {
+	return FvmBlockstoreBenchmarkResponseGo{
+		Operations:   uint64(r.operations),
+		PutNs:        uint64(r.put_ns),
+		GetNs:        uint64(r.get_ns),
+		HasNs:        uint64(r.has_ns),
+		OpsPerSecond: uint64(r.ops_per_second),
+	}
+}
+
+/// FvmSupportedNetworkVersionGo is a go allocated version of `FvmSupportedNetworkVersion`.
+type FvmSupportedNetworkVersionGo struct {
+	NetworkVersion uint64
+	ActorSource    FvmActorSource
+}
+
+func (ptr SliceBoxedFvmSupportedNetworkVersion) slice() []FvmSupportedNetworkVersion {
+	if ptr.ptr == nil {
+		return nil
+	}
+	return unsafe.Slice((*FvmSupportedNetworkVersion)(unsafe.Pointer(ptr.ptr)), int(ptr.len))
+}
+
+func (ptr SliceBoxedFvmSupportedNetworkVersion) copy() []FvmSupportedNetworkVersionGo {
+	if ptr.ptr == nil {
+		return nil
+	} else if ptr.len == 0 {
+		return []FvmSupportedNetworkVersionGo{}
+	}
+
+	ref := ptr.slice()
+	res := make([]FvmSupportedNetworkVersionGo, len(ref))
+	for i := range ref {
+		res[i] = FvmSupportedNetworkVersionGo{
+			NetworkVersion: uint64(ref[i].network_version),
+			ActorSource:    ref[i].actor_source,
+		}
+	}
+
+	return res
+}
+
+func (ptr *resultSliceBoxedFvmSupportedNetworkVersion) statusCode() FCPResponseStatus {
+	return FCPResponseStatus(ptr.status_code)
+}
+
+func (ptr *resultSliceBoxedFvmSupportedNetworkVersion) errorMsg() *SliceBoxedUint8 {
+	return &ptr.error_msg
+}
+
+func (ptr *resultSliceBoxedFvmSupportedNetworkVersion) destroy() {
+	if ptr != nil {
+		C.destroy_fvm_supported_network_versions_response(ptr)
+		ptr = nil
 	}
 }
@@ -18,3 +18,10 @@ func InitLogFd(fd int32) error {
 
 	return nil
 }
+
+func SetLogLevel(level SliceRefUint8) error {
+	resp := C.set_log_level(level)
+	defer resp.destroy()
+
+	return CheckErr(resp)
+}
@@ -13,8 +13,10 @@ import (
 	"context"
 	gobig "math/big"
 	"runtime"
+	"time"
 
 	"github.com/filecoin-project/filecoin-ffi/cgo"
+	"github.com/filecoin-project/go-address"
 	"github.com/filecoin-project/go-state-types/abi"
 	"github.com/filecoin-project/go-state-types/big"
 	"github.com/filecoin-project/go-state-types/network"
@@ -23,7 +25,9 @@ import (
 )
 
 type FVM struct {
-	executor *cgo.FvmMachine
+	executor       *cgo.FvmMachine
+	forcedManifest bool
+	actorSource    cgo.FvmActorSource
 }
 
 const (
@@ -42,6 +46,32 @@ type FVMOpts struct {
 	StateBase      cid.Cid
 	Manifest       cid.Cid
 	Tracing        bool
+	// CompactTrace returns execution traces (ApplyRet.ExecTraceBytes) in the compact binary
+	// format documented in rust/src/fvm/trace.rs instead of as CBOR encoded Lotus traces.
+	CompactTrace bool
+	// LotusTraceFormat selects the schema of the CBOR encoded Lotus traces: the FVM trace of
+	// current Lotus releases (cgo.FvmLotusTraceFormatFvm, the default), or the trace of the legacy
+	// Lotus VM (cgo.FvmLotusTraceFormatLegacy) for consumers pinned to Lotus v1.15 or older.
+	LotusTraceFormat cgo.FvmLotusTraceFormat
+	// MaxReturnSize caps the size of the return data captured from a message (zero means no
+	// cap). Larger return values are truncated, see ApplyRet.ReturnTruncated.
+	MaxReturnSize uint64
+	// RecentExecutions is the number of applied message summaries to retain for post-mortem
+	// debugging (zero disables recording), see FVM.RecentExecutions.
+	RecentExecutions uint64
+	// ReadCacheSize is the number of blocks read from the blockstore to keep in an LRU cache,
+	// saving repeated reads of hot blocks across the cgo boundary (zero disables the cache). The
+	// cache also holds the blocks loaded by FVM.PrefetchState.
+	ReadCacheSize uint64
+	// StreamSummaries hands a summary of every applied message to Externs implementing
+	// cgo.ExecutionSummaryReceiver, right after it's applied.
+	StreamSummaries bool
+	// ReadOnly guards query-only FVMs against mutating their state: applying messages, flushing,
+	// and writing receipts to the blockstore fail, while state queries keep working.
+	ReadOnly bool
+	// FlushWriteLog records the blocks each flush writes to the blockstore, up to this many bytes,
+	// for auditing with FVM.LastFlushWrites (zero disables recording).
+	FlushWriteLog uint64
 }
 
 // CreateFVM creates a new FVM instance.
@@ -56,7 +86,7 @@ func CreateFVM(opts *FVMOpts) (*FVM, error) {
 	}
 
 	exHandle := cgo.Register(context.TODO(), opts.Externs)
-	executor, err := cgo.CreateFvmMachine(cgo.FvmRegisteredVersion(opts.FVMVersion),
+	executor, forcedManifest, actorSource, err := cgo.CreateFvmMachine(cgo.FvmRegisteredVersion(opts.FVMVersion),
 		uint64(opts.Epoch),
 		baseFeeHi,
 		baseFeeLo,
@@ -65,7 +95,17 @@ func CreateFVM(opts *FVMOpts) (*FVM, error) {
 		uint64(opts.NetworkVersion),
 		cgo.AsSliceRefUint8(opts.StateBase.Bytes()),
 		cgo.AsSliceRefUint8(opts.Manifest.Bytes()),
-		opts.Tracing,
+		cgo.FvmMachineOptionsGo{
+			Tracing:          opts.Tracing,
+			CompactTrace:     opts.CompactTrace,
+			LotusTraceFormat: opts.LotusTraceFormat,
+			StreamSummaries:  opts.StreamSummaries,
+			MaxReturnSize:    opts.MaxReturnSize,
+			RecentExecutions: opts.RecentExecutions,
+			ReadCacheSize:    opts.ReadCacheSize,
+			ReadOnly:         opts.ReadOnly,
+			FlushWriteLog:    opts.FlushWriteLog,
+		},
 		exHandle, exHandle,
 	)
 	if err != nil {
@@ -73,7 +113,9 @@ func CreateFVM(opts *FVMOpts) (*FVM, error) {
 	}
 
 	fvm := &FVM{
-		executor: executor,
+		executor:       executor,
+		forcedManifest: forcedManifest,
+		actorSource:    actorSource,
 	}
 	runtime.SetFinalizer(fvm, func(f *FVM) {
 		// Just to be extra safe
@@ -91,6 +133,88 @@ func CreateFVM(opts *FVMOpts) (*FVM, error) {
 }
 
 func (f *FVM) ApplyMessage(msgBytes []byte, chainLen uint) (*ApplyRet, error) {
+	return f.ApplyMessageWithOpts(msgBytes, chainLen, ApplyOpts{})
+}
+
+// ApplyMessageStrict applies an explicit message like ApplyMessage, but returns an error
+// (carrying the exit code and failure info) if the receipt isn't OK. The message is still applied.
+func (f *FVM) ApplyMessageStrict(msgBytes []byte, chainLen uint) (*ApplyRet, error) {
+	return f.ApplyMessageWithOpts(msgBytes, chainLen, ApplyOpts{Strict: true})
+}
+
+// ApplyOpts are optional behaviours when applying an explicit message.
+type ApplyOpts struct {
+	// Strict turns a non-OK receipt into an error.
+	Strict bool
+	// StateRoots computes the state root before and after the message (see
+	// ApplyRet.PreStateRoot). This requires flushing the state tree twice.
+	StateRoots bool
+	// AllocationBudget caps the total size (in bytes) of the return value, trace, randomness
+	// requests, penalty breakdown, state proofs, receiver state, GC candidates, and invoked methods
+	// returned for the message (zero means unlimited). Exceeding it returns an error, but the message
+	// is still applied.
+	AllocationBudget uint64
+	// GasFree is for testing actor logic: the message can't run out of gas and pays no fees. Its
+	// gas limit, fee cap, and premium are overridden, but ApplyRet.GasUsed still reports the gas
+	// it used.
+	GasFree bool
+	// StateProofs returns inclusion proofs of the actors changed by the message against the new
+	// state root (see ApplyRet.StateProofs). It implies StateRoots.
+	StateProofs bool
+	// MaxSubcalls fails the call if the message makes more subcalls than this (zero means
+	// unlimited). Subcalls are counted in the execution trace, so this requires FVMOpts.Tracing.
+	// Unlike with AllocationBudget, the message is reverted.
+	MaxSubcalls uint64
+	// ReceiverState returns the receiver's state block after the message was applied (see
+	// ApplyRet.ReceiverState).
+	ReceiverState bool
+	// GCCandidates returns the blocks the message made unreferenced (see ApplyRet.GCCandidates),
+	// reading at most this many blocks (zero disables it). It implies StateRoots. This walks the
+	// whole post-message state, so it's only practical on small (e.g., test network) states: the
+	// call fails once the limit is reached.
+	GCCandidates uint64
+	// TraceDepthLimit caps the levels of calls in ApplyRet.ExecTraceBytes (zero means unlimited),
+	// the message itself being the first level. The calls at the last level are returned without
+	// their subcalls, setting ApplyRet.TraceTruncated. Compact traces are never truncated.
+	TraceDepthLimit uint64
+	// InvokedMethods returns the distinct actor methods the message invoked (see
+	// ApplyRet.InvokedMethods). Invocations are found in the execution trace, so this requires
+	// FVMOpts.Tracing.
+	InvokedMethods bool
+	// ReceiverInfo looks the receiver up before and after the message, setting
+	// ApplyRet.ReceiverKind, MethodName, ParamsCodec, ActorCreated, and CreatedActor.
+	ReceiverInfo bool
+	// BalanceMargin looks the sender's balance up before the message, setting
+	// ApplyRet.BalanceMargin.
+	BalanceMargin bool
+	// ActorsTouched sets ApplyRet.ActorsTouched.
+	ActorsTouched bool
+	// VerifySignature checks the message signature against the sender's key before applying it,
+	// failing if it doesn't match. It's only used by ApplySignedMessage.
+	VerifySignature bool
+}
+
+func (opts ApplyOpts) toCgo() cgo.FvmExecuteOptionsGo {
+	return cgo.FvmExecuteOptionsGo{
+		Strict:           opts.Strict,
+		StateRoots:       opts.StateRoots,
+		AllocationBudget: opts.AllocationBudget,
+		GasFree:          opts.GasFree,
+		StateProofs:      opts.StateProofs,
+		MaxSubcalls:      opts.MaxSubcalls,
+		ReceiverState:    opts.ReceiverState,
+		GCCandidates:     opts.GCCandidates,
+		TraceDepthLimit:  opts.TraceDepthLimit,
+		InvokedMethods:   opts.InvokedMethods,
+		ReceiverInfo:     opts.ReceiverInfo,
+		BalanceMargin:    opts.BalanceMargin,
+		ActorsTouched:    opts.ActorsTouched,
+		VerifySignature:  opts.VerifySignature,
+	}
+}
+
+// ApplyMessageWithOpts applies an explicit message with the given options.
+func (f *FVM) ApplyMessageWithOpts(msgBytes []byte, chainLen uint, opts ApplyOpts) (*ApplyRet, error) {
 	// NOTE: we need to call KeepAlive here (and below) because go doesn't guarantee that the
 	// receiver will live to the end of the function. If we don't do this, go _will_ garbage
 	// collect the FVM, causing us to run the finalizer while we're in the middle of using the
@@ -101,68 +225,745 @@ func (f *FVM) ApplyMessage(msgBytes []byte, chainLen uint) (*ApplyRet, error) {
 		cgo.AsSliceRefUint8(msgBytes),
 		uint64(chainLen),
 		applyExplicit,
+		opts.toCgo(),
 	)
 	if err != nil {
 		return nil, err
 	}
 
-	return &ApplyRet{
-		Return:             resp.ReturnVal,
-		ExitCode:           resp.ExitCode,
-		GasUsed:            int64(resp.GasUsed),
-		MinerPenalty:       reformBigInt(resp.PenaltyHi, resp.PenaltyLo),
-		MinerTip:           reformBigInt(resp.MinerTipHi, resp.MinerTipLo),
-		BaseFeeBurn:        reformBigInt(resp.BaseFeeBurnHi, resp.BaseFeeBurnLo),
-		OverEstimationBurn: reformBigInt(resp.OverEstimationBurnHi, resp.OverEstimationBurnLo),
-		Refund:             reformBigInt(resp.RefundHi, resp.RefundLo),
-		GasRefund:          int64(resp.GasRefund),
-		GasBurned:          int64(resp.GasBurned),
-		ExecTraceBytes:     resp.ExecTrace,
-		FailureInfo:        resp.FailureInfo,
-	}, nil
+	return newApplyRet(resp)
+}
+
+// ApplySignedMessage applies a SignedMessage (as an explicit message) with the given options. If
+// opts.VerifySignature is set, the signature is verified against the sender's key first.
+func (f *FVM) ApplySignedMessage(signedMsgBytes []byte, chainLen uint, opts ApplyOpts) (*ApplyRet, error) {
+	defer runtime.KeepAlive(f)
+	resp, err := cgo.FvmMachineExecuteSignedMessage(
+		f.executor,
+		cgo.AsSliceRefUint8(signedMsgBytes),
+		uint64(chainLen),
+		opts.toCgo(),
+	)
+	if err != nil {
+		return nil, err
+	}
+
+	return newApplyRet(resp)
 }
 
 func (f *FVM) ApplyImplicitMessage(msgBytes []byte) (*ApplyRet, error) {
+	return f.ApplyImplicitMessageWithGasCeiling(msgBytes, 0)
+}
+
+// ApplyImplicitMessageWithGasCeiling applies an implicit message, capping its gas limit at
+// gasCeiling (if non-zero). Nonce and balance checks remain disabled.
+func (f *FVM) ApplyImplicitMessageWithGasCeiling(msgBytes []byte, gasCeiling uint64) (*ApplyRet, error) {
 	defer runtime.KeepAlive(f)
 	resp, err := cgo.FvmMachineExecuteMessage(
 		f.executor,
 		cgo.AsSliceRefUint8(msgBytes),
 		0, // this isn't an on-chain message, so it has no chain length.
 		applyImplicit,
+		cgo.FvmExecuteOptionsGo{GasCeiling: gasCeiling},
+	)
+	if err != nil {
+		return nil, err
+	}
+
+	ret, err := newApplyRet(resp)
+	if err != nil {
+		return nil, err
+	}
+	// Execution traces have never been returned for implicit messages.
+	ret.ExecTraceBytes = nil
+	return ret, nil
+}
+
+// ApplyMessageAndFlush applies an explicit message like ApplyMessage, then flushes the FVM's state
+// like Flush, in a single call. It returns the new state root along with the message's result.
+func (f *FVM) ApplyMessageAndFlush(msgBytes []byte, chainLen uint) (*ApplyRet, cid.Cid, error) {
+	defer runtime.KeepAlive(f)
+	resp, stateRoot, err := cgo.FvmMachineExecuteAndFlush(f.executor, cgo.AsSliceRefUint8(msgBytes), uint64(chainLen))
+	if err != nil {
+		return nil, cid.Undef, err
+	}
+
+	ret, err := newApplyRet(resp)
+	if err != nil {
+		return nil, cid.Undef, err
+	}
+	root, err := cid.Cast(stateRoot)
+	if err != nil {
+		return nil, cid.Undef, xerrors.Errorf("invalid state root: %w", err)
+	}
+	return ret, root, nil
+}
+
+// ApplyMessageForRoot applies an explicit message and flushes the FVM like ApplyMessageAndFlush,
+// but only returns the exit code and the new state root. It skips collecting everything else about
+// the execution (and recording it in the recent executions), for replays that only compare roots.
+func (f *FVM) ApplyMessageForRoot(msgBytes []byte, chainLen uint) (uint64, cid.Cid, error) {
+	defer runtime.KeepAlive(f)
+	exitCode, stateRoot, err := cgo.FvmMachineExecuteForRoot(f.executor, cgo.AsSliceRefUint8(msgBytes), uint64(chainLen))
+	if err != nil {
+		return 0, cid.Undef, err
+	}
+
+	root, err := cid.Cast(stateRoot)
+	if err != nil {
+		return 0, cid.Undef, xerrors.Errorf("invalid state root: %w", err)
+	}
+	return exitCode, root, nil
+}
+
+// ApplyMessageWithAccessList applies a message and flushes the FVM like ApplyMessageForRoot, also
+// returning the CBOR encoded [actors, cidsRead, cidsWritten] access list of the message: the actors
+// it touched (only the sender and receiver unless FVMOpts.Tracing is set), the blocks it read from
+// the blockstore, and the blocks the flush wrote to it. Blocks the FVM keeps in memory once loaded
+// are only listed by the first message reading them.
+func (f *FVM) ApplyMessageWithAccessList(msgBytes []byte, chainLen uint) (uint64, cid.Cid, []byte, error) {
+	defer runtime.KeepAlive(f)
+	exitCode, stateRoot, accessList, err := cgo.FvmMachineExecuteWithAccessList(f.executor, cgo.AsSliceRefUint8(msgBytes), uint64(chainLen))
+	if err != nil {
+		return 0, cid.Undef, nil, err
+	}
+
+	root, err := cid.Cast(stateRoot)
+	if err != nil {
+		return 0, cid.Undef, nil, xerrors.Errorf("invalid state root: %w", err)
+	}
+	return exitCode, root, accessList, nil
+}
+
+// BatchOpts are the options of ApplyBatch.
+type BatchOpts struct {
+	// Apply are the options every message of the batch is applied with.
+	Apply ApplyOpts
+	// CreatedActors sets BatchRet.CreatedActors.
+	CreatedActors bool
+	// AllOrNothing reverts the whole batch if a message doesn't succeed, setting BatchRet.Failed,
+	// instead of applying every message. It excludes ApplyOpts.StateRoots, StateProofs, and
+	// GCCandidates.
+	AllOrNothing bool
+	// ReceiverGas sets BatchRet.ReceiverGas.
+	ReceiverGas bool
+}
+
+// BatchRet is the result of ApplyBatch.
+type BatchRet struct {
+	// Results are the results of the messages, in order.
+	Results []*ApplyRet
+	// CreatedActors are the IDs of the actors created by the batch and still there after it, in
+	// increasing order. It's only set if requested with BatchOpts.CreatedActors.
+	CreatedActors []abi.ActorID
+	// Failed is whether a BatchOpts.AllOrNothing batch was reverted, because the message at
+	// FailedIndex didn't succeed. Results then ends with that message's result.
+	Failed      bool
+	FailedIndex uint64
+	// ReceiverGas is the gas used by the messages applied, added up by receiver. Receivers are
+	// resolved right after their message, those that don't resolve are reported as
+	// UnresolvedActorID. It's only set if requested with BatchOpts.ReceiverGas.
+	ReceiverGas map[abi.ActorID]int64
+}
+
+// ApplyBatch applies explicit messages in order in a single call, chainLens holding the length on
+// chain of every message. Messages are applied whether or not the ones before them succeeded, and
+// if one fails the call (e.g. because of ApplyOpts.Strict), the messages before it stay applied.
+// Unless BatchOpts.AllOrNothing is set: then the batch stops at the first message that doesn't
+// succeed, reverting all the messages applied, and the state is left unchanged if one fails the
+// call.
+func (f *FVM) ApplyBatch(msgs [][]byte, chainLens []uint, opts BatchOpts) (*BatchRet, error) {
+	defer runtime.KeepAlive(f)
+
+	messages := make([]cgo.SliceBoxedUint8, len(msgs))
+	defer func() {
+		for idx := range messages {
+			messages[idx].Destroy()
+		}
+	}()
+	for idx, msg := range msgs {
+		messages[idx] = cgo.AllocSliceBoxedUint8(msg)
+	}
+	lens := make([]uint64, len(chainLens))
+	for idx, chainLen := range chainLens {
+		lens[idx] = uint64(chainLen)
+	}
+
+	resp, err := cgo.FvmMachineExecuteBatch(
+		f.executor,
+		cgo.AsSliceRefSliceBoxedUint8(messages),
+		cgo.AsSliceRefUint64(lens),
+		cgo.FvmBatchOptionsGo{
+			Execute:       opts.Apply.toCgo(),
+			CreatedActors: opts.CreatedActors,
+			AllOrNothing:  opts.AllOrNothing,
+			ReceiverGas:   opts.ReceiverGas,
+		},
 	)
 	if err != nil {
 		return nil, err
 	}
 
+	ret := &BatchRet{
+		Results:     make([]*ApplyRet, len(resp.Results)),
+		Failed:      resp.Failed,
+		FailedIndex: resp.FailedIndex,
+	}
+	for idx, result := range resp.Results {
+		if ret.Results[idx], err = newApplyRet(result); err != nil {
+			return nil, xerrors.Errorf("invalid result %d: %w", idx, err)
+		}
+	}
+	if resp.CreatedActors != nil {
+		ret.CreatedActors = make([]abi.ActorID, len(resp.CreatedActors))
+		for idx, id := range resp.CreatedActors {
+			ret.CreatedActors[idx] = abi.ActorID(id)
+		}
+	}
+	if opts.ReceiverGas {
+		ret.ReceiverGas = make(map[abi.ActorID]int64, len(resp.ReceiverGas))
+		for _, entry := range resp.ReceiverGas {
+			ret.ReceiverGas[abi.ActorID(entry.ReceiverID)] = int64(entry.GasUsed)
+		}
+	}
+	return ret, nil
+}
+
+func (f *FVM) Flush() (cid.Cid, error) {
+	root, _, err := f.FlushIfDirty()
+	return root, err
+}
+
+// FlushIfDirty flushes the FVM's state and returns the new state root. If nothing changed since
+// the last flush, the previous root is returned and noop is true.
+func (f *FVM) FlushIfDirty() (root cid.Cid, noop bool, err error) {
+	defer runtime.KeepAlive(f)
+	stateRoot, noop, err := cgo.FvmMachineFlush(f.executor)
+	if err != nil {
+		return cid.Undef, false, err
+	}
+
+	root, err = cid.Cast(stateRoot)
+	return root, noop, err
+}
+
+// StateSize returns the number and total size (in bytes) of the blocks reachable from the given
+// root, including blocks the FVM has written but not yet flushed.
+func (f *FVM) StateSize(root cid.Cid) (blocks uint64, bytes uint64, err error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineStateSize(f.executor, cgo.AsSliceRefUint8(root.Bytes()))
+}
+
+// LastFlushWrites returns the CBOR encoded list of (cid, block) tuples the last flush wrote to the
+// blockstore, in order. A flush that's skipped because nothing changed keeps the writes of the
+// flush that produced its root. It fails unless FVMOpts.FlushWriteLog is set, or if the blocks
+// exceeded it.
+func (f *FVM) LastFlushWrites() ([]byte, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineLastFlushWrites(f.executor)
+}
+
+// VerifyCarRoundtrip exports the state rooted at root (including blocks the FVM has written but
+// not yet flushed) to a CAR, loads it into a fresh blockstore, and returns whether the same root
+// and blocks come back out.
+func (f *FVM) VerifyCarRoundtrip(root cid.Cid) (bool, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineVerifyCarRoundtrip(f.executor, cgo.AsSliceRefUint8(root.Bytes()))
+}
+
+// DumpStateJSON returns a JSON dump of every actor in the state tree rooted at root, for
+// debugging. Pass a root returned by Flush to dump the FVM's state without flushing it again.
+// The state heads of builtin actors are decoded from DAG-CBOR.
+func (f *FVM) DumpStateJSON(root cid.Cid) ([]byte, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineDumpStateJson(f.executor, cgo.AsSliceRefUint8(root.Bytes()))
+}
+
+// GetBlock returns the block with the given CID from the FVM's blockstore, including blocks
+// written by applied messages that haven't been flushed yet. The boolean is false if the block
+// doesn't exist.
+func (f *FVM) GetBlock(c cid.Cid) ([]byte, bool, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineGetBlock(f.executor, cgo.AsSliceRefUint8(c.Bytes()))
+}
+
+// ComputeReceiptsRoot stores the AMT of the receipts of the given messages (in message order) in
+// the FVM's blockstore and returns its root, as referenced by block headers. The return values
+// must not have been truncated.
+func (f *FVM) ComputeReceiptsRoot(rets []*ApplyRet) (cid.Cid, error) {
+	defer runtime.KeepAlive(f)
+
+	exitCodes := make([]uint64, len(rets))
+	gasUsed := make([]uint64, len(rets))
+	returnValues := make([]cgo.SliceBoxedUint8, len(rets))
+	defer func() {
+		for idx := range returnValues {
+			returnValues[idx].Destroy()
+		}
+	}()
+	for idx, ret := range rets {
+		if ret.ReturnTruncated {
+			return cid.Undef, xerrors.Errorf("return value of message %d was truncated", idx)
+		}
+		exitCodes[idx] = ret.ExitCode
+		gasUsed[idx] = uint64(ret.GasUsed)
+		returnValues[idx] = cgo.AllocSliceBoxedUint8(ret.Return)
+	}
+
+	root, err := cgo.FvmMachineComputeReceiptsRoot(
+		f.executor,
+		cgo.AsSliceRefUint64(exitCodes),
+		cgo.AsSliceRefSliceBoxedUint8(returnValues),
+		cgo.AsSliceRefUint64(gasUsed),
+	)
+	if err != nil {
+		return cid.Undef, err
+	}
+	return cid.Cast(root)
+}
+
+// ActorNonce returns the sequence (nonce) of the actor at the address in the FVM's current state.
+// The boolean is false if there's no such actor.
+func (f *FVM) ActorNonce(addr address.Address) (uint64, bool, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineActorNonce(f.executor, cgo.AsSliceRefUint8(addr.Bytes()))
+}
+
+// UnresolvedActorID is the ID ResolveAddresses returns for addresses that don't resolve.
+const UnresolvedActorID = ^abi.ActorID(0)
+
+// ResolveAddresses resolves the addresses to actor IDs in a single call, returning the IDs in the
+// same order. Addresses that don't resolve are reported as UnresolvedActorID.
+func (f *FVM) ResolveAddresses(addrs []address.Address) ([]abi.ActorID, error) {
+	defer runtime.KeepAlive(f)
+
+	addresses := make([]cgo.SliceBoxedUint8, len(addrs))
+	defer func() {
+		for idx := range addresses {
+			addresses[idx].Destroy()
+		}
+	}()
+	for idx, addr := range addrs {
+		addresses[idx] = cgo.AllocSliceBoxedUint8(addr.Bytes())
+	}
+
+	ids, err := cgo.FvmMachineResolveAddresses(f.executor, cgo.AsSliceRefSliceBoxedUint8(addresses))
+	if err != nil {
+		return nil, err
+	}
+	resolved := make([]abi.ActorID, len(ids))
+	for idx, id := range ids {
+		resolved[idx] = abi.ActorID(id)
+	}
+	return resolved, nil
+}
+
+// InclusionGasForSize returns the gas charged for including a message whose serialized size is
+// size bytes (see MessageSize) on chain, under the FVM's network version. Every explicit message
+// uses at least this much gas.
+func (f *FVM) InclusionGasForSize(size uint64) (int64, error) {
+	defer runtime.KeepAlive(f)
+	gas, err := cgo.FvmMachineInclusionGasForSize(f.executor, size)
+	if err != nil {
+		return 0, err
+	}
+	return int64(gas), nil
+}
+
+// ForcedManifest returns true if the FVM uses the manifest passed in FVMOpts.Manifest, which
+// always overrides the builtin actors (pre-nv16) and the manifest in the state (nv16+).
+func (f *FVM) ForcedManifest() bool {
+	return f.forcedManifest
+}
+
+// ActorSource returns where the FVM's actors come from: the builtin bundle (pre-nv16), the
+// manifest passed in FVMOpts.Manifest, or the manifest in the state (nv16+).
+func (f *FVM) ActorSource() cgo.FvmActorSource {
+	return f.actorSource
+}
+
+// ManifestCid returns the CID of the actor manifest the FVM is using (whether explicitly passed,
+// builtin, or loaded from state). Returns cid.Undef if there is none.
+func (f *FVM) ManifestCid() (cid.Cid, error) {
+	defer runtime.KeepAlive(f)
+	manifest, err := cgo.FvmMachineManifestCid(f.executor)
+	if err != nil {
+		return cid.Undef, err
+	}
+	if len(manifest) == 0 {
+		return cid.Undef, nil
+	}
+	return cid.Cast(manifest)
+}
+
+// VerifyManifest returns whether the FVM is using the expected actor manifest (whether explicitly
+// passed, builtin, or loaded from state).
+func (f *FVM) VerifyManifest(expected cid.Cid) (bool, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineVerifyManifest(f.executor, cgo.AsSliceRefUint8(expected.Bytes()))
+}
+
+// RecentExecutions returns the CBOR encoded list of (exec ID, to, method, exit code, gas used,
+// lock wait in nanoseconds) summaries of the last FVMOpts.RecentExecutions applied messages,
+// oldest first.
+func (f *FVM) RecentExecutions() ([]byte, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachineRecentExecutions(f.executor)
+}
+
+// PrefetchState loads the top levels of the initial state tree into the FVM's read cache ahead of
+// execution, returning the number of blocks loaded. Useful before applying many messages. It
+// requires FVMOpts.ReadCacheSize, and only the most recently loaded blocks remain cached if they
+// don't all fit.
+func (f *FVM) PrefetchState() (uint64, error) {
+	defer runtime.KeepAlive(f)
+	return cgo.FvmMachinePrefetchState(f.executor)
+}
+
+// FVMSelfcheck runs an end-to-end check of the FVM (machine creation, message execution, and
+// flushing) against a minimal in-memory state, returning a diagnostic error if anything fails.
+func FVMSelfcheck() error {
+	return cgo.FvmSelfcheck()
+}
+
+// BlockstoreBenchmark is the result of BenchmarkBlockstore.
+type BlockstoreBenchmark struct {
+	// Operations is the total number of puts, gets, and has checks performed.
+	Operations uint64
+	Put        time.Duration
+	Get        time.Duration
+	Has        time.Duration
+	// OpsPerSecond is the throughput over all the operations.
+	OpsPerSecond uint64
+}
+
+// BenchmarkBlockstore measures the throughput of the externs' blockstore through the FFI,
+// independent of message execution: it puts ops small distinct blocks, then gets and checks each
+// one. Blockstores can't delete blocks, so they stay in the blockstore for good. Pass externs
+// backed by a scratch blockstore of the same kind as the node's, never the node's own: the junk
+// blocks aren't part of any state, so nothing would ever garbage collect them.
+func BenchmarkBlockstore(scratch cgo.Externs, ops uint64) (*BlockstoreBenchmark, error) {
+	exHandle := cgo.Register(context.TODO(), scratch)
+	defer cgo.Unregister(exHandle)
+
+	resp, err := cgo.FvmBenchmarkBlockstore(ops, exHandle)
+	if err != nil {
+		return nil, err
+	}
+	return &BlockstoreBenchmark{
+		Operations:   resp.Operations,
+		Put:          time.Duration(resp.PutNs),
+		Get:          time.Duration(resp.GetNs),
+		Has:          time.Duration(resp.HasNs),
+		OpsPerSecond: resp.OpsPerSecond,
+	}, nil
+}
+
+// CompareTraces compares two CBOR encoded Lotus execution traces (see ApplyRet.ExecTraceBytes).
+// If they differ, it returns the subcall indices leading from the root to the first differing
+// frame, and the first field that differs in that frame. Otherwise, the field is
+// cgo.FvmTraceFieldNoDivergence.
+func CompareTraces(a, b []byte) ([]uint64, cgo.FvmTraceField, error) {
+	return cgo.FvmCompareTraces(cgo.AsSliceRefUint8(a), cgo.AsSliceRefUint8(b))
+}
+
+// DetectReentrancy finds the actors re-entered within a single call chain of a CBOR encoded Lotus
+// execution trace (see ApplyRet.ExecTraceBytes): the frames whose receiver already received one of
+// the frames leading to them. It returns the CBOR encoded list of (address, path) tuples, one per
+// re-entering frame, where path lists the receivers from the root down to that frame.
+func DetectReentrancy(trace []byte) ([]byte, error) {
+	return cgo.FvmTraceDetectReentrancy(cgo.AsSliceRefUint8(trace))
+}
+
+// SerializeExecutionResult encodes the receipt, execution trace, and post-state root of an applied
+// message into a self-contained blob, e.g. for caching replays. The message must have been applied
+// with ApplyOpts.StateRoots.
+func SerializeExecutionResult(ret *ApplyRet) ([]byte, error) {
+	if !ret.PostStateRoot.Defined() {
+		return nil, xerrors.Errorf("execution result has no post-state root")
+	}
+	if ret.ReturnTruncated {
+		return nil, xerrors.Errorf("execution result has truncated return data")
+	}
+	return cgo.FvmSerializeExecutionResult(
+		ret.ExitCode,
+		cgo.AsSliceRefUint8(ret.Return),
+		uint64(ret.GasUsed),
+		cgo.AsSliceRefUint8(ret.ExecTraceBytes),
+		cgo.AsSliceRefUint8(ret.PostStateRoot.Bytes()),
+	)
+}
+
+// DeserializeExecutionResult reads back a blob produced by SerializeExecutionResult. Only the
+// serialized fields (Return, ExitCode, GasUsed, ExecTraceBytes, and PostStateRoot) are set.
+func DeserializeExecutionResult(result []byte) (*ApplyRet, error) {
+	resp, err := cgo.FvmDeserializeExecutionResult(cgo.AsSliceRefUint8(result))
+	if err != nil {
+		return nil, err
+	}
+	root, err := cid.Cast(resp.StateRoot)
+	if err != nil {
+		return nil, xerrors.Errorf("invalid state root: %w", err)
+	}
 	return &ApplyRet{
+		Return:         resp.ReturnVal,
+		ReturnLen:      uint64(len(resp.ReturnVal)),
+		ExitCode:       resp.ExitCode,
+		GasUsed:        int64(resp.GasUsed),
+		ExecTraceBytes: resp.ExecTrace,
+		PostStateRoot:  root,
+	}, nil
+}
+
+// EstimateGasPremium suggests a gas premium (attoFIL per gas unit) for inclusion within
+// nblocksIncl blocks, using Lotus's heuristic. premiums and gasLimits describe the messages
+// included in the last blocks blocks (one entry per message), Lotus samples the last
+// 2*nblocksIncl tipsets. Unlike Lotus, no random noise is added.
+func EstimateGasPremium(nblocksIncl, blocks uint64, premiums, gasLimits []uint64) (abi.TokenAmount, error) {
+	premium, err := cgo.FvmEstimateGasPremium(nblocksIncl, blocks, cgo.AsSliceRefUint64(premiums), cgo.AsSliceRefUint64(gasLimits))
+	if err != nil {
+		return big.Zero(), err
+	}
+	return big.NewIntUnsigned(premium), nil
+}
+
+// ValidateCar checks that the CAR file is well-formed and has a single root, without loading its
+// blocks anywhere. The roots are returned whenever the CAR's header parses, even if the CAR is
+// otherwise invalid.
+func ValidateCar(car []byte) (bool, []cid.Cid, error) {
+	valid, rawRoots, err := cgo.FvmValidateCar(cgo.AsSliceRefUint8(car))
+	if err != nil {
+		return false, nil, err
+	}
+	roots := make([]cid.Cid, len(rawRoots))
+	for idx, raw := range rawRoots {
+		if roots[idx], err = cid.Cast(raw); err != nil {
+			return false, nil, xerrors.Errorf("invalid root %d: %w", idx, err)
+		}
+	}
+	return valid, roots, nil
+}
+
+// MessageSize returns the length of the message's canonical (on-chain) encoding, as seen by the FVM.
+func MessageSize(msgBytes []byte) (uint64, error) {
+	return cgo.FvmMessageSize(cgo.AsSliceRefUint8(msgBytes))
+}
+
+// EncodeMessage builds a message from its fields and returns its canonical encoding, exactly as
+// accepted by ApplyMessage.
+func EncodeMessage(from, to address.Address, sequence uint64, value abi.TokenAmount, method abi.MethodNum, params []byte, gasLimit int64, gasFeeCap, gasPremium abi.TokenAmount) ([]byte, error) {
+	if gasLimit < 0 {
+		return nil, xerrors.Errorf("negative gas limit: %d", gasLimit)
+	}
+	valueHi, valueLo, err := splitBigInt(value)
+	if err != nil {
+		return nil, xerrors.Errorf("invalid value: %w", err)
+	}
+	gasFeeCapHi, gasFeeCapLo, err := splitBigInt(gasFeeCap)
+	if err != nil {
+		return nil, xerrors.Errorf("invalid gas fee cap: %w", err)
+	}
+	gasPremiumHi, gasPremiumLo, err := splitBigInt(gasPremium)
+	if err != nil {
+		return nil, xerrors.Errorf("invalid gas premium: %w", err)
+	}
+	return cgo.FvmEncodeMessage(
+		cgo.AsSliceRefUint8(from.Bytes()),
+		cgo.AsSliceRefUint8(to.Bytes()),
+		sequence,
+		valueHi,
+		valueLo,
+		uint64(method),
+		cgo.AsSliceRefUint8(params),
+		uint64(gasLimit),
+		gasFeeCapHi,
+		gasFeeCapLo,
+		gasPremiumHi,
+		gasPremiumLo,
+	)
+}
+
+// SupportedNetworkVersion is a network version FVMs can be created for.
+type SupportedNetworkVersion struct {
+	Version network.Version
+	// ActorSource is where the actors come from if FVMOpts.Manifest isn't set: the bundle built
+	// into the library (cgo.FvmActorSourceBuiltin), or the state (cgo.FvmActorSourceFromState).
+	ActorSource cgo.FvmActorSource
+}
+
+// SupportedNetworkVersions lists the network versions FVMs can be created for.
+func SupportedNetworkVersions() ([]SupportedNetworkVersion, error) {
+	versions, err := cgo.FvmSupportedNetworkVersions()
+	if err != nil {
+		return nil, err
+	}
+	supported := make([]SupportedNetworkVersion, len(versions))
+	for idx, version := range versions {
+		supported[idx] = SupportedNetworkVersion{
+			Version:     network.Version(version.NetworkVersion),
+			ActorSource: version.ActorSource,
+		}
+	}
+	return supported, nil
+}
+
+// EmptyStateRoot writes a state tree holding only the system and init actors, for a network named
+// networkName, to the externs' blockstore and returns its root. FVMs can be created on it for the
+// network version, with the same manifest (cid.Undef for the builtin actors).
+func EmptyStateRoot(externs cgo.Externs, nv network.Version, manifest cid.Cid, networkName string) (cid.Cid, error) {
+	exHandle := cgo.Register(context.TODO(), externs)
+	defer cgo.Unregister(exHandle)
+
+	root, err := cgo.FvmEmptyStateRoot(
+		uint64(nv),
+		cgo.AsSliceRefUint8(manifest.Bytes()),
+		cgo.AsSliceRefUint8([]byte(networkName)),
+		exHandle,
+	)
+	if err != nil {
+		return cid.Undef, err
+	}
+	return cid.Cast(root)
+}
+
+// GasParams returns the key parameters of the gas price list FVMs use for the network version, as
+// the CBOR encoded tuple (message base, message per byte, return value per byte, send base, send
+// invoke method, send transfer funds, create actor, delete actor, syscall, verify BLS signature,
+// verify secp256k1 signature, block open base, block create base) of total gas amounts.
+func GasParams(nv network.Version) ([]byte, error) {
+	return cgo.FvmGasParams(uint64(nv))
+}
+
+// ExitCodeName returns the symbolic name of an exit code (e.g. "SYS_OUT_OF_GAS"), or "" if it's
+// neither a system exit code nor one of the common user exit codes, or is a reserved system exit
+// code.
+func ExitCodeName(exitCode uint64) (string, error) {
+	return cgo.FvmExitCodeName(exitCode)
+}
+
+// ValidateMessageSyntax checks that the message is structurally valid for inclusion in a block,
+// without consulting any state. If it isn't, the first violated rule is returned.
+func ValidateMessageSyntax(msgBytes []byte) (bool, cgo.FvmMessageSyntaxError, error) {
+	return cgo.FvmValidateMessageSyntax(cgo.AsSliceRefUint8(msgBytes))
+}
+
+// ValidateBatchGas checks, without applying them, that the gas limits of the (serialized) messages
+// add up to at most blockGasLimit. If they don't, it also returns by how much they exceed it.
+func ValidateBatchGas(msgs [][]byte, blockGasLimit uint64) (bool, uint64, error) {
+	messages := make([]cgo.SliceBoxedUint8, len(msgs))
+	defer func() {
+		for idx := range messages {
+			messages[idx].Destroy()
+		}
+	}()
+	for idx, msg := range msgs {
+		messages[idx] = cgo.AllocSliceBoxedUint8(msg)
+	}
+
+	return cgo.FvmValidateBatchGas(cgo.AsSliceRefSliceBoxedUint8(messages), blockGasLimit)
+}
+
+func newApplyRet(resp cgo.FvmMachineExecuteResponseGo) (*ApplyRet, error) {
+	ret := &ApplyRet{
+		ExecID:             resp.ExecID,
 		Return:             resp.ReturnVal,
+		ReturnTruncated:    resp.ReturnTruncated,
+		ReturnLen:          resp.ReturnLen,
+		ReceiverKind:       resp.ReceiverKind,
+		ParamsLen:          resp.ParamsLen,
+		ParamsCodec:        resp.ParamsCodec,
+		ParamsDecodeFailed: resp.ParamsDecodeFailed,
+		ActorsTouched:      resp.ActorsTouched,
 		ExitCode:           resp.ExitCode,
+		ExitCodeClass:      resp.ExitCodeClass,
 		GasUsed:            int64(resp.GasUsed),
+		DirectGas:          int64(resp.DirectGas),
+		SubcallGas:         int64(resp.SubcallGas),
 		MinerPenalty:       reformBigInt(resp.PenaltyHi, resp.PenaltyLo),
 		MinerTip:           reformBigInt(resp.MinerTipHi, resp.MinerTipLo),
 		BaseFeeBurn:        reformBigInt(resp.BaseFeeBurnHi, resp.BaseFeeBurnLo),
 		OverEstimationBurn: reformBigInt(resp.OverEstimationBurnHi, resp.OverEstimationBurnLo),
 		Refund:             reformBigInt(resp.RefundHi, resp.RefundLo),
+		EffectiveBaseFee:   reformBigInt(resp.EffectiveBaseFeeHi, resp.EffectiveBaseFeeLo),
+		EffectivePremium:   reformBigInt(resp.EffectivePremiumHi, resp.EffectivePremiumLo),
 		GasRefund:          int64(resp.GasRefund),
 		GasBurned:          int64(resp.GasBurned),
+		ExecTraceBytes:     resp.ExecTrace,
+		TraceTruncated:     resp.TraceTruncated,
 		FailureInfo:        resp.FailureInfo,
-	}, nil
-}
-
-func (f *FVM) Flush() (cid.Cid, error) {
-	defer runtime.KeepAlive(f)
-	stateRoot, err := cgo.FvmMachineFlush(f.executor)
-	if err != nil {
-		return cid.Undef, err
+		RandomnessRequests: resp.RandomnessRequests,
+		PenaltyBreakdown:   resp.PenaltyBreakdown,
+		ActorCreated:       resp.ActorCreated,
+		CreatedActor:       abi.ActorID(resp.CreatedActorID),
+		MethodName:         resp.MethodName,
+		BalanceMargin:      reformBigInt(resp.BalanceMarginHi, resp.BalanceMarginLo),
+		StateProofs:        resp.StateProofs,
+		ReceiverState:      resp.ReceiverState,
+		InvokedMethods:     resp.InvokedMethods,
+		LockWait:           time.Duration(resp.LockWaitNs),
+	}
+	if resp.BalanceMarginNegative {
+		ret.BalanceMargin = big.Neg(ret.BalanceMargin)
 	}
 
-	return cid.Cast(stateRoot)
+	var err error
+	if resp.PreStateRoot != nil {
+		if ret.PreStateRoot, err = cid.Cast(resp.PreStateRoot); err != nil {
+			return nil, xerrors.Errorf("invalid pre-state root: %w", err)
+		}
+	}
+	if resp.PostStateRoot != nil {
+		if ret.PostStateRoot, err = cid.Cast(resp.PostStateRoot); err != nil {
+			return nil, xerrors.Errorf("invalid post-state root: %w", err)
+		}
+	}
+	if resp.GCCandidates != nil {
+		ret.GCCandidates = make([]cid.Cid, len(resp.GCCandidates))
+		for idx, raw := range resp.GCCandidates {
+			if ret.GCCandidates[idx], err = cid.Cast(raw); err != nil {
+				return nil, xerrors.Errorf("invalid gc candidate %d: %w", idx, err)
+			}
+		}
+	}
+	return ret, nil
 }
 
 type ApplyRet struct {
-	Return             []byte
-	ExitCode           uint64
-	GasUsed            int64
+	// ExecID identifies the execution within the process, IDs increase with every execution. It's
+	// also recorded in the FVM's recent executions.
+	ExecID uint64
+	Return []byte
+	// ReturnTruncated is set if Return was truncated to FVMOpts.MaxReturnSize, ReturnLen is the
+	// length of the return value before truncation.
+	ReturnTruncated bool
+	ReturnLen       uint64
+	// ReceiverKind classifies the receiver by its code in the actor manifest, it's
+	// cgo.FvmReceiverKindUnknown if the receiver doesn't exist or isn't a builtin actor. It's only
+	// set if requested with ApplyOpts.ReceiverInfo, like ParamsCodec, ActorCreated, CreatedActor,
+	// and MethodName.
+	ReceiverKind cgo.FvmReceiverKind
+	// ParamsLen is the length of the message params, ParamsCodec the IPLD codec the receiving
+	// method expects them in (0 if unknown: the receiver isn't a builtin actor, or the method isn't
+	// one of its known methods).
+	ParamsLen   uint64
+	ParamsCodec uint64
+	// ParamsDecodeFailed is whether the receiving builtin actor aborted because it couldn't decode
+	// the params.
+	ParamsDecodeFailed bool
+	// ActorsTouched is the number of distinct actors involved in the message: its sender and
+	// receiver, and the actors in its subcalls if the FVM is tracing. It's only set if requested
+	// with ApplyOpts.ActorsTouched.
+	ActorsTouched uint64
+	ExitCode      uint64
+	// ExitCodeClass is whether ExitCode is a system error (raised by the VM) or a user error
+	// (returned by an actor), cgo.FvmExitCodeClassNoError if the message succeeded.
+	ExitCodeClass cgo.FvmExitCodeClass
+	GasUsed       int64
+	// DirectGas is the part of GasUsed not used by the subcalls of the receiver: its own execution,
+	// and the inclusion of an explicit message. SubcallGas is the gas used by the subcalls it made,
+	// including their own subcalls.
+	DirectGas          int64
+	SubcallGas         int64
 	MinerPenalty       abi.TokenAmount
 	MinerTip           abi.TokenAmount
 	BaseFeeBurn        abi.TokenAmount
@@ -171,7 +972,56 @@ type ApplyRet struct {
 	GasRefund          int64
 	GasBurned          int64
 	ExecTraceBytes     []byte
-	FailureInfo        string
+	// TraceTruncated is whether subcalls were dropped from ExecTraceBytes to fit
+	// ApplyOpts.TraceDepthLimit.
+	TraceTruncated bool
+	FailureInfo    string
+	// RandomnessRequests is the CBOR encoded list of (beacon, personalization, round, entropy)
+	// tuples describing the randomness looked up by the message.
+	RandomnessRequests []byte
+	// PenaltyBreakdown is the CBOR encoded list of (cause, amount) tuples MinerPenalty is made of.
+	// The FVM doesn't separate causes yet, so a non-zero penalty has a single "total" entry.
+	PenaltyBreakdown []byte
+	// PreStateRoot and PostStateRoot are the state roots before and after the message. They're
+	// only set if requested with ApplyOpts.StateRoots.
+	PreStateRoot  cid.Cid
+	PostStateRoot cid.Cid
+	// EffectiveBaseFee and EffectivePremium are the base fee and miner premium per gas unit
+	// actually charged, after clamping to the message's fee cap. Both are zero for implicit
+	// messages.
+	EffectiveBaseFee abi.TokenAmount
+	EffectivePremium abi.TokenAmount
+	// ActorCreated is set if applying the message created an actor at its receiver address, e.g.
+	// an account for a previously unknown key address. CreatedActor is the ID it was assigned,
+	// which is the same whenever the message is applied to the same state.
+	ActorCreated bool
+	CreatedActor abi.ActorID
+	// MethodName is the name of the invoked method if the receiver is a builtin actor, or "" if
+	// it's unknown.
+	MethodName string
+	// BalanceMargin is the sender's balance before the message, minus its value and maximum gas
+	// cost (gas limit times fee cap). It's negative if the sender couldn't afford the message. It's
+	// only set if requested with ApplyOpts.BalanceMargin.
+	BalanceMargin abi.TokenAmount
+	// StateProofs is the CBOR encoded list of (address, [(cid, block)]) inclusion proofs of the
+	// actors changed by the message, only set if requested with ApplyOpts.StateProofs. Looking an
+	// actor up from PostStateRoot in a blockstore holding only its proof's blocks proves its
+	// state. Actors called in subcalls are only covered if the FVM is tracing.
+	StateProofs []byte
+	// ReceiverState is the receiver's raw (CBOR) state block after the message was applied, only
+	// set if requested with ApplyOpts.ReceiverState and the receiver exists.
+	ReceiverState []byte
+	// GCCandidates are the blocks referenced by PreStateRoot but not by PostStateRoot, the blocks a
+	// blockstore GC could drop once the pre-message state is no longer needed. Only set if
+	// requested with ApplyOpts.GCCandidates.
+	GCCandidates []cid.Cid
+	// InvokedMethods is the CBOR encoded list of distinct (code, method) tuples invoked by the
+	// message and its subcalls, in the order they were first invoked. Only set if requested with
+	// ApplyOpts.InvokedMethods.
+	InvokedMethods []byte
+	// LockWait is how long the call waited for the FVM's lock, held by concurrent calls on the same
+	// FVM, before applying the message. It tells contention apart from execution cost.
+	LockWait time.Duration
 }
 
 // NOTE: We only support 64bit platforms
@@ -1,11 +1,22 @@
 package ffi
 
 import (
+	"context"
 	"math"
 	"testing"
 
+	"github.com/filecoin-project/filecoin-ffi/cgo"
+	"github.com/filecoin-project/go-state-types/abi"
 	"github.com/filecoin-project/go-state-types/big"
+	"github.com/filecoin-project/go-state-types/crypto"
+	"github.com/filecoin-project/go-state-types/network"
+	blocks "github.com/ipfs/go-block-format"
+	"github.com/ipfs/go-cid"
+	"github.com/ipfs/go-datastore"
+	dssync "github.com/ipfs/go-datastore/sync"
+	blockstore "github.com/ipfs/go-ipfs-blockstore"
 	"github.com/stretchr/testify/require"
+	"golang.org/x/xerrors"
 )
 
 func checkSplitBigInt(t *testing.T, i big.Int, hi, lo uint64) {
@@ -34,3 +45,60 @@ func TestSplitBigIntNeg(t *testing.T) {
 	_, _, err := splitBigInt(big.NewInt(-1))
 	require.Error(t, err)
 }
+
+// testExterns serves blocks from an in-memory blockstore, and has no randomness.
+type testExterns struct {
+	blockstore.Blockstore
+}
+
+func newTestExterns() testExterns {
+	return testExterns{blockstore.NewBlockstore(dssync.MutexWrap(datastore.NewMapDatastore()))}
+}
+
+func (testExterns) GetChainRandomness(ctx context.Context, personalization crypto.DomainSeparationTag, epoch abi.ChainEpoch, entropy []byte) ([]byte, error) {
+	return nil, xerrors.New("no chain randomness")
+}
+
+func (testExterns) GetBeaconRandomness(ctx context.Context, personalization crypto.DomainSeparationTag, epoch abi.ChainEpoch, entropy []byte) ([]byte, error) {
+	return nil, xerrors.New("no beacon randomness")
+}
+
+func (testExterns) VerifyConsensusFault(ctx context.Context, h1, h2, extra []byte) (*cgo.ConsensusFault, int64) {
+	return nil, 0
+}
+
+func (e testExterns) View(ctx context.Context, c cid.Cid, callback func([]byte) error) error {
+	blk, err := e.Get(ctx, c)
+	if err != nil {
+		return err
+	}
+	return callback(blk.RawData())
+}
+
+func createTestFVM(externs testExterns, stateBase cid.Cid) (*FVM, error) {
+	return CreateFVM(&FVMOpts{
+		FVMVersion:     0,
+		Externs:        externs,
+		BaseFee:        big.Zero(),
+		BaseCircSupply: big.Zero(),
+		NetworkVersion: network.Version15,
+		StateBase:      stateBase,
+	})
+}
+
+func TestCreateFVMMissingStateRoot(t *testing.T) {
+	root := blocks.NewBlock([]byte{0x80}).Cid()
+	_, err := createTestFVM(newTestExterns(), root)
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "state root "+root.String()+" not found in the blockstore")
+}
+
+func TestCreateFVMUnreadableStateRoot(t *testing.T) {
+	externs := newTestExterns()
+	// An empty CBOR list, which is neither a state root nor a v0 state tree.
+	blk := blocks.NewBlock([]byte{0x80})
+	require.NoError(t, externs.Put(context.Background(), blk))
+	_, err := createTestFVM(externs, blk.Cid())
+	require.Error(t, err)
+	require.Contains(t, err.Error(), "state root "+blk.Cid().String()+" is unreadable")
+}
@@ -0,0 +1,99 @@
+//! A benchmark of a blockstore behind the externs, independent of any execution.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use cid::multihash::Code;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::DAG_CBOR;
+use safer_ffi::prelude::*;
+
+use super::blockstore::CgoBlockstore;
+use super::types::FvmBlockstoreBenchmarkResponse;
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+/// A DAG-CBOR byte string holding "fvm blockstore benchmark" and an 8 byte counter, so every
+/// benchmarked block is distinct.
+const BLOCK_PREFIX: &[u8] = b"\x58\x20fvm blockstore benchmark";
+
+/// Times `operations` puts of small distinct blocks to the blockstore, followed by a get and a has
+/// of each one. Blockstores can't delete, so the blocks stay: the blockstore should be a scratch
+/// one, not the one holding the chain state.
+#[ffi_export]
+fn fvm_benchmark_blockstore(
+    operations: u64,
+    blockstore_id: u64,
+) -> repr_c::Box<Result<FvmBlockstoreBenchmarkResponse>> {
+    catch_panic_response("fvm_benchmark_blockstore", || {
+        benchmark_blockstore(&CgoBlockstore::new(blockstore_id), operations)
+    })
+}
+
+destructor!(
+    destroy_fvm_benchmark_blockstore_response,
+    Result<FvmBlockstoreBenchmarkResponse>
+);
+
+fn benchmark_blockstore(
+    bs: &impl Blockstore,
+    operations: u64,
+) -> anyhow::Result<FvmBlockstoreBenchmarkResponse> {
+    if operations == 0 {
+        bail!("the benchmark needs at least one operation");
+    }
+
+    let start = Instant::now();
+    let keys = (0..operations)
+        .map(|i| {
+            let mut block = BLOCK_PREFIX.to_vec();
+            block.extend(i.to_be_bytes());
+            bs.put(Code::Blake2b256, &Block::new(DAG_CBOR, block))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let put = start.elapsed();
+
+    let start = Instant::now();
+    for k in &keys {
+        bs.get(k)?
+            .ok_or_else(|| anyhow!("block {} is missing right after being put", k))?;
+    }
+    let get = start.elapsed();
+
+    let start = Instant::now();
+    for k in &keys {
+        if !bs.has(k)? {
+            bail!("block {} is missing right after being put", k);
+        }
+    }
+    let has = start.elapsed();
+
+    let nanos = |elapsed: Duration| u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+    let total = nanos(put + get + has).max(1);
+    Ok(FvmBlockstoreBenchmarkResponse {
+        operations: operations * 3,
+        put_ns: nanos(put),
+        get_ns: nanos(get),
+        has_ns: nanos(has),
+        ops_per_second: (operations as u128 * 3 * 1_000_000_000 / total as u128)
+            .try_into()
+            .unwrap_or(u64::MAX),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_blockstore::MemoryBlockstore;
+
+    use super::benchmark_blockstore;
+
+    #[test]
+    fn test_benchmark_blockstore() {
+        let result = benchmark_blockstore(&MemoryBlockstore::new(), 100).unwrap();
+        assert_eq!(result.operations, 300);
+        assert!(result.put_ns > 0);
+        assert!(result.ops_per_second > 0);
+
+        assert!(benchmark_blockstore(&MemoryBlockstore::new(), 0).is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+use super::GetMany;
+
+#[derive(Default)]
+struct AccessLogState {
+    reads: Vec<Cid>,
+    writes: Vec<Cid>,
+    seen: HashSet<(bool, Cid)>,
+}
+
+impl AccessLogState {
+    fn record(&mut self, write: bool, k: &Cid) {
+        if self.seen.insert((write, *k)) {
+            if write {
+                self.writes.push(*k);
+            } else {
+                self.reads.push(*k);
+            }
+        }
+    }
+}
+
+/// A shared log of the CIDs of the blocks read from and written to an [`AccessLogBlockstore`]
+/// while recording, for the access list of a message.
+///
+/// Below the machine's buffered blockstore, reads only show up if they miss its write buffer, and
+/// writes only show up when the machine is flushed. So a message's reads are the blocks it loaded
+/// from the state it was applied to, and its writes are those of the flush following it.
+#[derive(Clone, Default)]
+pub struct AccessLog(Arc<Mutex<Option<AccessLogState>>>);
+
+impl AccessLog {
+    /// Clears the log and starts recording accesses.
+    pub fn start(&self) {
+        *self.0.lock().unwrap() = Some(AccessLogState::default());
+    }
+
+    /// Stops recording accesses, returning the distinct CIDs read and written since the log was
+    /// started, in the order they were first accessed.
+    pub fn stop(&self) -> (Vec<Cid>, Vec<Cid>) {
+        match self.0.lock().unwrap().take() {
+            Some(state) => (state.reads, state.writes),
+            None => Default::default(),
+        }
+    }
+
+    fn record(&self, write: bool, k: &Cid) {
+        if let Some(state) = &mut *self.0.lock().unwrap() {
+            state.record(write, k);
+        }
+    }
+}
+
+/// A blockstore recording the CIDs of the blocks read from (and found in) the base blockstore and
+/// written to it in an [`AccessLog`].
+pub struct AccessLogBlockstore<BS> {
+    log: AccessLog,
+    base: BS,
+}
+
+impl<BS> AccessLogBlockstore<BS> {
+    pub fn new(base: BS, log: AccessLog) -> Self {
+        AccessLogBlockstore { log, base }
+    }
+}
+
+impl<BS> Blockstore for AccessLogBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.base.get(k)?;
+        if block.is_some() {
+            self.log.record(false, k);
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.log.record(true, k);
+        self.base.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.base.has(k)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.base.put_many_keyed(
+            blocks
+                .into_iter()
+                .inspect(|(k, _)| self.log.record(true, k)),
+        )
+    }
+}
+
+impl<BS> GetMany for AccessLogBlockstore<BS>
+where
+    BS: GetMany,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        let blocks = self.base.get_many(ks)?;
+        for (k, block) in ks.iter().zip(&blocks) {
+            if block.is_some() {
+                self.log.record(false, k);
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::Code;
+    use fvm::blockstore::BufferedBlockstore;
+    use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    use super::{AccessLog, AccessLogBlockstore};
+
+    #[test]
+    fn test_access_log() {
+        let log = AccessLog::default();
+        let base = MemoryBlockstore::new();
+        let old = base
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, [0x01]))
+            .unwrap();
+        let buffered = BufferedBlockstore::new(AccessLogBlockstore::new(base, log.clone()));
+
+        // Nothing is recorded until the log is started.
+        buffered.get(&old).unwrap().unwrap();
+        log.start();
+        buffered.get(&old).unwrap().unwrap();
+        buffered.get(&old).unwrap().unwrap();
+        let new = buffered
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, [0x02]))
+            .unwrap();
+        // The new block is read from the write buffer, and only written on flush.
+        buffered.get(&new).unwrap().unwrap();
+        assert_eq!(log.stop(), (vec![old], vec![]));
+
+        log.start();
+        buffered.flush(&new).unwrap();
+        assert_eq!(log.stop(), (vec![], vec![new]));
+        assert_eq!(log.stop(), (vec![], vec![]));
+    }
+}
@@ -5,6 +5,7 @@ use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 
 use super::super::cgo::*;
+use super::GetMany;
 
 /// The maximum amount of data to buffer in a batch before writing it to the underlying blockstore.
 const MAX_BUF_SIZE: usize = 4 << 20; // 4MiB
@@ -152,3 +153,54 @@ impl Blockstore for CgoBlockstore {
         }
     }
 }
+
+impl GetMany for CgoBlockstore {
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        if ks.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut k_lengths = Vec::with_capacity(ks.len());
+        let mut k_buf = Vec::with_capacity(ks.len() * EST_MAX_CID_LEN);
+        for k in ks {
+            let start = k_buf.len();
+            k.write_bytes(&mut k_buf)?;
+            k_lengths.push((k_buf.len() - start) as i32);
+        }
+
+        let mut bufs: Vec<*mut u8> = vec![ptr::null_mut(); ks.len()];
+        let mut sizes: Vec<i32> = vec![0; ks.len()];
+        unsafe {
+            match cgo_blockstore_get_many(
+                self.handle,
+                k_lengths.as_ptr(),
+                k_lengths.len() as i32,
+                k_buf.as_ptr(),
+                bufs.as_mut_ptr(),
+                sizes.as_mut_ptr(),
+            ) {
+                // Missing blocks are returned as null buffers.
+                0 => Ok(bufs
+                    .into_iter()
+                    .zip(sizes)
+                    .map(|(buf, size)| {
+                        if buf.is_null() {
+                            None
+                        } else {
+                            Some(Vec::from_raw_parts(buf, size as usize, size as usize))
+                        }
+                    })
+                    .collect()),
+                r @ 1.. => panic!("invalid return value from get_many: {}", r),
+                x if x == FvmError::InvalidHandle as i32 => {
+                    panic!("blockstore {} not registered", self.handle)
+                }
+                // The go side frees any blocks it fetched before failing.
+                e => Err(anyhow!(
+                    "cgo blockstore 'get_many' failed with error code {}",
+                    e
+                )),
+            }
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use std::cell::Cell;
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+
+use super::GetMany;
+
+/// A blockstore that counts reads, for testing the blockstores layered over it.
+#[derive(Default)]
+pub struct CountingBlockstore {
+    bs: MemoryBlockstore,
+    /// The number of blocks read.
+    pub reads: Cell<usize>,
+    /// The number of `get` and `get_many` calls.
+    pub round_trips: Cell<usize>,
+}
+
+impl Blockstore for CountingBlockstore {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.reads.set(self.reads.get() + 1);
+        self.round_trips.set(self.round_trips.get() + 1);
+        self.bs.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.bs.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.bs.has(k)
+    }
+}
+
+impl GetMany for CountingBlockstore {
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.reads.set(self.reads.get() + ks.len());
+        self.round_trips.set(self.round_trips.get() + 1);
+        ks.iter().map(|k| self.bs.get(k)).collect()
+    }
+}
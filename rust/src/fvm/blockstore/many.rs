@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use cid::Cid;
+use fvm::blockstore::BufferedBlockstore;
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+
+/// A blockstore that can fetch many blocks at once. Blockstores backed by the go side of the cgo
+/// bridge implement this with a single call across CGO; all others fall back on calling
+/// [`Blockstore::get`] for each key.
+pub trait GetMany: Blockstore {
+    /// Get the blocks with the given CIDs, in order. Missing blocks are returned as `None`.
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        ks.iter().map(|k| self.get(k)).collect()
+    }
+}
+
+impl GetMany for MemoryBlockstore {}
+
+impl<BS> GetMany for &BS
+where
+    BS: GetMany,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        (*self).get_many(ks)
+    }
+}
+
+/// A batch of the reads a [`BufferedBlockstore`] passes on to its base blockstore. The buffered
+/// blockstore exposes neither its write buffer nor its base, so the base takes part in the batch
+/// by answering its reads through [`ReadBatch::get`].
+///
+/// Clones share the same batch.
+#[derive(Clone, Default)]
+pub struct ReadBatch(Arc<Mutex<BatchState>>);
+
+enum BatchState {
+    /// Reads are answered by the base blockstore as usual.
+    Idle,
+    /// Reads are recorded and answered with `None`.
+    Collecting(Vec<Cid>),
+    /// The recorded reads are fetched with a single `get_many` on the next read.
+    Fetching(Vec<Cid>),
+    /// Reads of the recorded keys are answered with the fetched blocks.
+    Serving(HashMap<Cid, Option<Vec<u8>>>),
+}
+
+impl Default for BatchState {
+    fn default() -> Self {
+        BatchState::Idle
+    }
+}
+
+impl ReadBatch {
+    fn state(&self) -> MutexGuard<'_, BatchState> {
+        self.0.lock().unwrap()
+    }
+
+    /// Answers a read of `k` by the base blockstore if it's part of a batch, fetching the recorded
+    /// keys with `get_many` if they haven't been yet. Returns `None` if the base blockstore should
+    /// answer the read as usual.
+    pub fn get<F>(&self, k: &Cid, get_many: F) -> Option<Result<Option<Vec<u8>>>>
+    where
+        F: FnOnce(&[Cid]) -> Result<Vec<Option<Vec<u8>>>>,
+    {
+        let mut state = self.state();
+        if let BatchState::Fetching(ks) = &mut *state {
+            let ks = mem::take(ks);
+            let blocks = match get_many(&ks) {
+                Ok(blocks) => blocks,
+                Err(err) => return Some(Err(err)),
+            };
+            *state = BatchState::Serving(ks.into_iter().zip(blocks).collect());
+        }
+        match &mut *state {
+            BatchState::Idle | BatchState::Fetching(_) => None,
+            BatchState::Collecting(ks) => {
+                ks.push(*k);
+                Some(Ok(None))
+            }
+            BatchState::Serving(blocks) => blocks.get(k).cloned().map(Ok),
+        }
+    }
+
+    /// Gets the blocks with the given CIDs from `bs`, whose base blockstore takes part in this
+    /// batch. Blocks are read from the write buffer first, and all the others are fetched from the
+    /// base blockstore with a single `get_many`.
+    pub fn get_many<BS>(
+        &self,
+        bs: &BufferedBlockstore<BS>,
+        ks: &[Cid],
+    ) -> Result<Vec<Option<Vec<u8>>>>
+    where
+        BS: Blockstore,
+    {
+        struct Reset<'a>(&'a ReadBatch);
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                *self.0.state() = BatchState::Idle;
+            }
+        }
+        let _reset = Reset(self);
+
+        *self.state() = BatchState::Collecting(Vec::new());
+        let mut blocks = ks.iter().map(|k| bs.get(k)).collect::<Result<Vec<_>>>()?;
+        let missing = match mem::take(&mut *self.state()) {
+            BatchState::Collecting(missing) => missing,
+            _ => unreachable!("the batch changed state while collecting reads"),
+        };
+        if missing.is_empty() {
+            return Ok(blocks);
+        }
+
+        // Read the missing blocks again, this time with the base fetching them all on the first
+        // read.
+        *self.state() = BatchState::Fetching(missing);
+        for (k, block) in ks.iter().zip(&mut blocks) {
+            if block.is_none() {
+                *block = bs.get(k)?;
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+/// A [`BufferedBlockstore`] whose base blockstore takes part in `batch`, so that
+/// [`GetMany::get_many`] reads through the write buffer into a single `get_many` on the base.
+pub struct BatchedBlockstore<'a, BS> {
+    bs: &'a BufferedBlockstore<BS>,
+    batch: &'a ReadBatch,
+}
+
+impl<'a, BS> BatchedBlockstore<'a, BS> {
+    pub fn new(bs: &'a BufferedBlockstore<BS>, batch: &'a ReadBatch) -> Self {
+        BatchedBlockstore { bs, batch }
+    }
+}
+
+impl<BS> Blockstore for BatchedBlockstore<'_, BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.bs.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.bs.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.bs.has(k)
+    }
+}
+
+impl<BS> GetMany for BatchedBlockstore<'_, BS>
+where
+    BS: Blockstore,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.batch.get_many(self.bs, ks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::blockstore::BufferedBlockstore;
+    use fvm_ipld_blockstore::Blockstore;
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    use super::super::{CountingBlockstore, OverlayBlockstore};
+    use super::{BatchedBlockstore, GetMany};
+
+    #[test]
+    fn test_batched_get_many() {
+        let cid = |data: &[u8]| Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data));
+        let (base, over, written, missing) =
+            (cid(b"base"), cid(b"over"), cid(b"written"), cid(b"missing"));
+
+        let base_bs = CountingBlockstore::default();
+        base_bs.put_keyed(&base, b"base").unwrap();
+        let overlay = OverlayBlockstore::new(HashMap::from([(over, b"over".to_vec())]), &base_bs);
+        let batch = overlay.batch();
+        let buffered = BufferedBlockstore::new(overlay);
+        buffered.put_keyed(&written, b"written").unwrap();
+        let bs = BatchedBlockstore::new(&buffered, &batch);
+
+        let blocks = bs.get_many(&[base, written, over, missing, base]).unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                Some(b"base".to_vec()),
+                Some(b"written".to_vec()),
+                Some(b"over".to_vec()),
+                None,
+                Some(b"base".to_vec())
+            ]
+        );
+        // Only the blocks missing from the buffer and the overlay are read, in one round-trip.
+        assert_eq!(base_bs.round_trips.get(), 1);
+        assert_eq!(base_bs.reads.get(), 3);
+
+        // Nothing is fetched if the buffer has every block.
+        bs.get_many(&[written, over]).unwrap();
+        assert_eq!(base_bs.round_trips.get(), 1);
+
+        // And reads outside of a batch go to the base as usual.
+        assert_eq!(bs.get(&base).unwrap(), Some(b"base".to_vec()));
+        assert_eq!(base_bs.round_trips.get(), 2);
+    }
+}
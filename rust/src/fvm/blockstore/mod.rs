@@ -1,7 +1,19 @@
+mod access_log;
 mod cgo;
+#[cfg(test)]
+mod counting;
 mod fake;
+mod many;
 mod overlay;
+mod read_cache;
+mod write_log;
 
+pub use access_log::*;
 pub use cgo::*;
+#[cfg(test)]
+pub use counting::*;
 pub use fake::*;
+pub use many::*;
 pub use overlay::*;
+pub use read_cache::*;
+pub use write_log::*;
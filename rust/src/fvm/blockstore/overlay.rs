@@ -4,14 +4,20 @@ use anyhow::Result;
 use cid::Cid;
 use fvm_ipld_blockstore::Blockstore;
 
+use super::{GetMany, ReadBatch};
+
 /// A blockstore with a read-only, in-memory "overlay".
 ///
 /// 1. On get, the overlay will be checked first.
 /// 2. All puts will go directly to the base blockstore.
 ///
 /// Use this blockstore to "overlay" some pre-determined set of blocks over a real blockstore.
+///
+/// Reads that miss the overlay take part in the blockstore's [`ReadBatch`], so that bulk reads
+/// through a `BufferedBlockstore` on top of it reach the base blockstore in a single `get_many`.
 pub struct OverlayBlockstore<BS> {
     over: HashMap<Cid, Vec<u8>>,
+    batch: ReadBatch,
     base: BS,
 }
 
@@ -20,18 +26,27 @@ impl<BS> OverlayBlockstore<BS> {
     pub fn new(overlay: HashMap<Cid, Vec<u8>>, base: BS) -> Self {
         OverlayBlockstore {
             over: overlay,
+            batch: ReadBatch::default(),
             base,
         }
     }
+
+    /// Returns a handle to the batch the blockstore's reads take part in.
+    pub fn batch(&self) -> ReadBatch {
+        self.batch.clone()
+    }
 }
 
 impl<BS> Blockstore for OverlayBlockstore<BS>
 where
-    BS: Blockstore,
+    BS: GetMany,
 {
     fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
-        match self.over.get(k) {
-            Some(blk) => Ok(Some(blk.clone())),
+        if let Some(blk) = self.over.get(k) {
+            return Ok(Some(blk.clone()));
+        }
+        match self.batch.get(k, |ks| self.base.get_many(ks)) {
+            Some(blk) => blk,
             None => self.base.get(k),
         }
     }
@@ -74,3 +89,58 @@ where
         self.base.put_many_keyed(blocks)
     }
 }
+
+impl<BS> GetMany for OverlayBlockstore<BS>
+where
+    BS: GetMany,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut blocks: Vec<_> = ks.iter().map(|k| self.over.get(k).cloned()).collect();
+        let (missing_idx, missing): (Vec<_>, Vec<_>) = ks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| blocks[*i].is_none())
+            .map(|(i, k)| (i, *k))
+            .unzip();
+        if !missing.is_empty() {
+            for (i, block) in missing_idx.into_iter().zip(self.base.get_many(&missing)?) {
+                blocks[i] = block;
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    use super::{GetMany, OverlayBlockstore};
+
+    #[test]
+    fn test_get_many() {
+        let cid = |data: &[u8]| Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data));
+        let (over, base, missing) = (cid(b"over"), cid(b"base"), cid(b"missing"));
+
+        let base_bs = MemoryBlockstore::new();
+        base_bs.put_keyed(&base, b"base").unwrap();
+        let bs = OverlayBlockstore::new(HashMap::from([(over, b"over".to_vec())]), base_bs);
+
+        let blocks = bs.get_many(&[base, missing, over, base]).unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                Some(b"base".to_vec()),
+                None,
+                Some(b"over".to_vec()),
+                Some(b"base".to_vec())
+            ]
+        );
+        assert!(bs.get_many(&[]).unwrap().is_empty());
+    }
+}
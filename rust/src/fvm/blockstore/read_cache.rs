@@ -0,0 +1,285 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+use super::GetMany;
+
+/// A fixed capacity map of blocks, evicting the least recently used block when full.
+struct Lru {
+    capacity: usize,
+    /// Incremented on every access, orders the blocks by recency.
+    tick: u64,
+    blocks: HashMap<Cid, (Vec<u8>, u64)>,
+    by_tick: BTreeMap<u64, Cid>,
+}
+
+impl Lru {
+    fn new(capacity: usize) -> Self {
+        Lru {
+            capacity,
+            tick: 0,
+            blocks: HashMap::new(),
+            by_tick: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, k: &Cid) -> Option<Vec<u8>> {
+        self.tick += 1;
+        let (block, tick) = self.blocks.get_mut(k)?;
+        self.by_tick.remove(&*tick);
+        self.by_tick.insert(self.tick, *k);
+        *tick = self.tick;
+        Some(block.clone())
+    }
+
+    fn insert(&mut self, k: Cid, block: Vec<u8>) {
+        self.tick += 1;
+        if let Some((_, tick)) = self.blocks.insert(k, (block, self.tick)) {
+            self.by_tick.remove(&tick);
+        } else if self.blocks.len() > self.capacity {
+            let (&oldest_tick, &oldest) = self.by_tick.iter().next().expect("cache isn't empty");
+            self.by_tick.remove(&oldest_tick);
+            self.blocks.remove(&oldest);
+        }
+        self.by_tick.insert(self.tick, k);
+    }
+}
+
+/// A shared cache of up to a fixed number of blocks, evicting the least recently used block when
+/// full. Blocks are immutable, so cached blocks never need to be invalidated. A cache created with
+/// a capacity of zero is disabled, and never holds anything.
+#[derive(Clone, Default)]
+pub struct BlockCache(Option<Arc<Mutex<Lru>>>);
+
+impl BlockCache {
+    pub fn new(capacity: usize) -> Self {
+        BlockCache((capacity > 0).then(|| Arc::new(Mutex::new(Lru::new(capacity)))))
+    }
+
+    /// The maximum number of blocks the cache holds, zero if it's disabled.
+    pub fn capacity(&self) -> usize {
+        match &self.0 {
+            Some(lru) => lru.lock().unwrap().capacity,
+            None => 0,
+        }
+    }
+
+    pub fn insert(&self, k: Cid, block: Vec<u8>) {
+        if let Some(lru) = &self.0 {
+            lru.lock().unwrap().insert(k, block);
+        }
+    }
+
+    pub fn get(&self, k: &Cid) -> Option<Vec<u8>> {
+        self.0.as_ref()?.lock().unwrap().get(k)
+    }
+
+    pub fn contains(&self, k: &Cid) -> bool {
+        match &self.0 {
+            Some(lru) => lru.lock().unwrap().blocks.contains_key(k),
+            None => false,
+        }
+    }
+}
+
+/// A blockstore caching the blocks most recently read from the base blockstore in a
+/// [`BlockCache`], to save round-trips to slow (e.g., cgo) base blockstores. The cache can also be
+/// filled ahead of time through [`ReadCacheBlockstore::cache`].
+pub struct ReadCacheBlockstore<BS> {
+    cache: BlockCache,
+    base: BS,
+}
+
+impl<BS> ReadCacheBlockstore<BS> {
+    /// Creates a blockstore caching up to `capacity` blocks, zero disables caching.
+    pub fn new(base: BS, capacity: usize) -> Self {
+        ReadCacheBlockstore {
+            cache: BlockCache::new(capacity),
+            base,
+        }
+    }
+
+    /// Creates a blockstore sharing an existing cache.
+    pub fn with_cache(base: BS, cache: BlockCache) -> Self {
+        ReadCacheBlockstore { cache, base }
+    }
+
+    /// Returns a handle to this blockstore's block cache.
+    pub fn cache(&self) -> BlockCache {
+        self.cache.clone()
+    }
+}
+
+impl<BS> Blockstore for ReadCacheBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let cache = match &self.cache.0 {
+            Some(cache) => cache,
+            None => return self.base.get(k),
+        };
+        if let Some(block) = cache.lock().unwrap().get(k) {
+            return Ok(Some(block));
+        }
+        let block = self.base.get(k)?;
+        if let Some(block) = &block {
+            cache.lock().unwrap().insert(*k, block.clone());
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.base.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        Ok(self.cache.contains(k) || self.base.has(k)?)
+    }
+
+    fn put<D>(
+        &self,
+        mh_code: cid::multihash::Code,
+        block: &fvm_ipld_blockstore::Block<D>,
+    ) -> Result<Cid>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+    {
+        self.base.put(mh_code, block)
+    }
+
+    fn put_many<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (cid::multihash::Code, fvm_ipld_blockstore::Block<D>)>,
+    {
+        self.base.put_many(blocks)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.base.put_many_keyed(blocks)
+    }
+}
+
+impl<BS> GetMany for ReadCacheBlockstore<BS>
+where
+    BS: GetMany,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        let cache = match &self.cache.0 {
+            Some(cache) => cache,
+            None => return self.base.get_many(ks),
+        };
+        let mut blocks: Vec<_> = {
+            let mut cache = cache.lock().unwrap();
+            ks.iter().map(|k| cache.get(k)).collect()
+        };
+        let (missing_idx, missing): (Vec<_>, Vec<_>) = ks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| blocks[*i].is_none())
+            .map(|(i, k)| (i, *k))
+            .unzip();
+        if !missing.is_empty() {
+            let fetched = self.base.get_many(&missing)?;
+            let mut cache = cache.lock().unwrap();
+            for ((i, k), block) in missing_idx.into_iter().zip(missing).zip(fetched) {
+                if let Some(block) = &block {
+                    cache.insert(k, block.clone());
+                }
+                blocks[i] = block;
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::IPLD_RAW;
+
+    use super::super::CountingBlockstore;
+    use super::{BlockCache, GetMany, ReadCacheBlockstore};
+
+    fn put(bs: &impl Blockstore, data: &[u8]) -> Cid {
+        let cid = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(data));
+        bs.put_keyed(&cid, data).unwrap();
+        cid
+    }
+
+    #[test]
+    fn test_read_cache() {
+        let bs = ReadCacheBlockstore::new(CountingBlockstore::default(), 0);
+        let a = put(&bs, b"a");
+        bs.get(&a).unwrap();
+        bs.get(&a).unwrap();
+        assert_eq!(bs.base.reads.get(), 2);
+
+        let bs = ReadCacheBlockstore::new(CountingBlockstore::default(), 2);
+        let (a, b, c) = (put(&bs, b"a"), put(&bs, b"b"), put(&bs, b"c"));
+        for _ in 0..3 {
+            assert_eq!(bs.get(&a).unwrap(), Some(b"a".to_vec()));
+        }
+        assert_eq!(bs.base.reads.get(), 1);
+        assert_eq!(
+            bs.get_many(&[a, b]).unwrap(),
+            vec![Some(b"a".to_vec()), Some(b"b".to_vec())]
+        );
+        assert_eq!(bs.base.reads.get(), 2);
+        // The missing block is fetched in one batch.
+        assert_eq!(bs.base.round_trips.get(), 2);
+
+        // Reading c evicts the least recently used block, a.
+        bs.get(&c).unwrap();
+        bs.get(&b).unwrap();
+        assert_eq!(bs.base.reads.get(), 3);
+        bs.get(&a).unwrap();
+        assert_eq!(bs.base.reads.get(), 4);
+
+        // Missing blocks aren't cached.
+        let missing = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(b"missing"));
+        assert_eq!(bs.get(&missing).unwrap(), None);
+        assert_eq!(bs.get(&missing).unwrap(), None);
+        assert_eq!(bs.base.reads.get(), 6);
+    }
+
+    #[test]
+    fn test_shared_cache() {
+        let bs = ReadCacheBlockstore::new(CountingBlockstore::default(), 2);
+        let (a, b) = (put(&bs, b"a"), put(&bs, b"b"));
+
+        // Blocks inserted through a handle are read from the cache.
+        let cache = bs.cache();
+        cache.insert(a, b"a".to_vec());
+        assert_eq!(bs.get(&a).unwrap(), Some(b"a".to_vec()));
+        assert_eq!(bs.get_many(&[a]).unwrap(), vec![Some(b"a".to_vec())]);
+        assert_eq!(bs.base.reads.get(), 0);
+
+        // And count towards the capacity: inserting a third block evicts the least recently used.
+        bs.get(&b).unwrap();
+        assert_eq!(bs.base.reads.get(), 1);
+        let c = put(&bs, b"c");
+        cache.insert(c, b"c".to_vec());
+        assert!(!cache.contains(&a));
+        assert!(cache.contains(&b) && cache.contains(&c));
+        assert_eq!(cache.capacity(), 2);
+
+        let disabled = BlockCache::new(0);
+        disabled.insert(a, b"a".to_vec());
+        assert!(!disabled.contains(&a));
+        assert_eq!(disabled.capacity(), 0);
+    }
+}
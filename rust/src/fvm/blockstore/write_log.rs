@@ -0,0 +1,179 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use cid::Cid;
+use fvm_ipld_blockstore::Blockstore;
+
+use super::GetMany;
+
+#[derive(Default)]
+struct WriteLogState {
+    max_bytes: usize,
+    recording: bool,
+    /// Set once the blocks recorded since the last start exceed `max_bytes`, which drops them.
+    overflowed: bool,
+    bytes: usize,
+    writes: Vec<(Cid, Vec<u8>)>,
+}
+
+/// A shared log of the blocks written to a [`WriteLogBlockstore`] while recording, for auditing
+/// what a flush wrote to the base blockstore. A log created with a capacity of zero is disabled,
+/// and never records anything.
+#[derive(Clone, Default)]
+pub struct WriteLog(Option<Arc<Mutex<WriteLogState>>>);
+
+impl WriteLog {
+    /// Creates a log holding up to `max_bytes` of blocks.
+    pub fn new(max_bytes: usize) -> Self {
+        WriteLog((max_bytes > 0).then(|| {
+            Arc::new(Mutex::new(WriteLogState {
+                max_bytes,
+                ..Default::default()
+            }))
+        }))
+    }
+
+    /// Clears the log and starts recording writes.
+    pub fn start(&self) {
+        if let Some(state) = &self.0 {
+            let mut state = state.lock().unwrap();
+            *state = WriteLogState {
+                max_bytes: state.max_bytes,
+                recording: true,
+                ..Default::default()
+            };
+        }
+    }
+
+    /// Stops recording writes, keeping the ones recorded so far.
+    pub fn stop(&self) {
+        if let Some(state) = &self.0 {
+            state.lock().unwrap().recording = false;
+        }
+    }
+
+    fn record(&self, k: &Cid, block: &[u8]) {
+        let mut state = match &self.0 {
+            Some(state) => state.lock().unwrap(),
+            None => return,
+        };
+        if !state.recording || state.overflowed {
+            return;
+        }
+        state.bytes += block.len();
+        if state.bytes > state.max_bytes {
+            state.overflowed = true;
+            state.writes = Vec::new();
+            return;
+        }
+        state.writes.push((*k, block.to_vec()));
+    }
+
+    /// Returns the blocks written since the log was last started, in order. Fails if the log is
+    /// disabled, or if the blocks didn't fit.
+    pub fn writes(&self) -> Result<Vec<(Cid, Vec<u8>)>> {
+        let state = match &self.0 {
+            Some(state) => state.lock().unwrap(),
+            None => bail!("writes aren't being recorded"),
+        };
+        if state.overflowed {
+            bail!("the writes exceeded the {} byte write log", state.max_bytes);
+        }
+        Ok(state.writes.clone())
+    }
+}
+
+/// A blockstore recording the blocks written to the base blockstore in a [`WriteLog`].
+pub struct WriteLogBlockstore<BS> {
+    log: WriteLog,
+    base: BS,
+}
+
+impl<BS> WriteLogBlockstore<BS> {
+    pub fn new(base: BS, log: WriteLog) -> Self {
+        WriteLogBlockstore { log, base }
+    }
+}
+
+impl<BS> Blockstore for WriteLogBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.base.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.log.record(k, block);
+        self.base.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.base.has(k)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.base.put_many_keyed(
+            blocks
+                .into_iter()
+                .inspect(|(k, block)| self.log.record(k, block.as_ref())),
+        )
+    }
+}
+
+impl<BS> GetMany for WriteLogBlockstore<BS>
+where
+    BS: GetMany,
+{
+    fn get_many(&self, ks: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.base.get_many(ks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::Code;
+    use fvm::blockstore::BufferedBlockstore;
+    use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    use super::{WriteLog, WriteLogBlockstore};
+
+    #[test]
+    fn test_write_log() {
+        let log = WriteLog::new(16);
+        let buffered = BufferedBlockstore::new(WriteLogBlockstore::new(
+            MemoryBlockstore::new(),
+            log.clone(),
+        ));
+        let block = vec![0x83, 0x01, 0x02, 0x03];
+        let root = buffered
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, &block))
+            .unwrap();
+
+        // Nothing has been written to the base blockstore yet.
+        assert!(log.writes().unwrap().is_empty());
+        log.start();
+        buffered.flush(&root).unwrap();
+        log.stop();
+        assert_eq!(log.writes().unwrap(), vec![(root, block)]);
+
+        // A 20 byte byte string.
+        let mut big = vec![0x54];
+        big.extend([0; 20]);
+        let big = buffered
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, big))
+            .unwrap();
+        log.start();
+        buffered.flush(&big).unwrap();
+        log.stop();
+        assert!(log.writes().is_err());
+
+        assert!(WriteLog::new(0).writes().is_err());
+    }
+}
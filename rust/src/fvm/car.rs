@@ -0,0 +1,150 @@
+//! CAR file helpers that don't need a machine.
+
+use cid::Cid;
+use futures::executor::block_on;
+use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+use fvm_ipld_car::{load_car, CarHeader, CarReader};
+use safer_ffi::prelude::*;
+
+use super::blockstore::GetMany;
+use super::dag::{walk_dag, MAX_DAG_DEPTH};
+use super::types::FvmValidateCarResponse;
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+/// Checks that the CAR file is well-formed (its header and every block frame parse) and has a
+/// single root, without loading it into a blockstore. The roots are returned as long as the header
+/// parses, even if the rest of the CAR doesn't.
+#[ffi_export]
+fn fvm_validate_car(car: c_slice::Ref<u8>) -> repr_c::Box<Result<FvmValidateCarResponse>> {
+    catch_panic_response("fvm_validate_car", || {
+        let (roots, well_formed) = validate_car(&car);
+        Ok(FvmValidateCarResponse {
+            valid: well_formed && roots.len() == 1,
+            root_count: roots.len() as u64,
+            roots: roots
+                .into_iter()
+                .map(|root| root.into_boxed_slice().into())
+                .collect::<Vec<_>>()
+                .into_boxed_slice()
+                .into(),
+        })
+    })
+}
+
+destructor!(
+    destroy_fvm_validate_car_response,
+    Result<FvmValidateCarResponse>
+);
+
+/// Returns the CAR's roots (in their byte encoding), and whether all of it parses.
+fn validate_car(car: &[u8]) -> (Vec<Vec<u8>>, bool) {
+    block_on(async {
+        let mut reader = match CarReader::new(car).await {
+            Ok(reader) => reader,
+            Err(_) => return (Vec::new(), false),
+        };
+        let roots = reader
+            .header
+            .roots
+            .iter()
+            .map(|root| root.to_bytes())
+            .collect();
+        loop {
+            match reader.next_block().await {
+                Ok(Some(_)) => continue,
+                Ok(None) => return (roots, true),
+                Err(_) => return (roots, false),
+            }
+        }
+    })
+}
+
+/// Exports the DAG rooted at `root` to a CAR, loads it into a fresh in-memory blockstore, and
+/// checks that the CAR's only root is `root` and that every exported block reads back unchanged.
+pub fn car_roundtrip<BS: GetMany>(bs: &BS, root: Cid) -> anyhow::Result<bool> {
+    let mut blocks = Vec::new();
+    walk_dag(bs, root, MAX_DAG_DEPTH, |k, block| {
+        blocks.push((*k, block.to_vec()));
+        Ok(())
+    })?;
+
+    let mut car = Vec::new();
+    block_on(
+        CarHeader::new(vec![root], 1)
+            .write_stream_async(&mut car, &mut futures::stream::iter(blocks.iter().cloned())),
+    )?;
+
+    let fresh = MemoryBlockstore::new();
+    let roots = block_on(load_car(&fresh, &car[..]))?;
+    if roots != [root] {
+        return Ok(false);
+    }
+    for (k, block) in &blocks {
+        if fresh.get(k)?.as_ref() != Some(block) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::state_tree::{ActorState, StateTree};
+    use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::{car_roundtrip, fvm_validate_car};
+
+    #[test]
+    fn test_validate_car() {
+        let car = actors_v7::BUNDLE_CAR;
+
+        let resp = fvm_validate_car(car.into());
+        assert!(resp.error_msg.is_empty());
+        assert!(resp.value.valid);
+        assert_eq!(resp.value.root_count, 1);
+        assert_eq!(resp.value.roots.len(), 1);
+
+        // The header survives the truncation, the last block doesn't.
+        let resp = fvm_validate_car(car[..car.len() - 1].into());
+        assert!(resp.error_msg.is_empty());
+        assert!(!resp.value.valid);
+        assert_eq!(
+            &resp.value.roots[0][..],
+            &fvm_validate_car(car.into()).value.roots[0][..]
+        );
+
+        let resp = fvm_validate_car([0xff][..].into());
+        assert!(resp.error_msg.is_empty());
+        assert!(!resp.value.valid);
+        assert_eq!(resp.value.root_count, 0);
+    }
+
+    #[test]
+    fn test_car_roundtrip() {
+        let bs = MemoryBlockstore::new();
+        let put = |data| {
+            bs.put(Code::Blake2b256, &Block::new(DAG_CBOR, data))
+                .unwrap()
+        };
+        let code = put(b"\x67account".to_vec());
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        for id in 100..110 {
+            let state = put(vec![0x81, (id - 100) as u8]);
+            let actor = ActorState::new(code, state, TokenAmount::from(id), 0);
+            state_tree.set_actor(&Address::new_id(id), actor).unwrap();
+        }
+        let root = state_tree.flush().unwrap();
+
+        assert!(car_roundtrip(&bs, root).unwrap());
+        // The DAG has to be complete to be exported.
+        let missing = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"missing"));
+        assert!(car_roundtrip(&bs, missing).is_err());
+    }
+}
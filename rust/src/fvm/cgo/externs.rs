@@ -9,6 +9,15 @@ extern "C" {
         size: *mut i32,
     ) -> i32;
 
+    pub fn cgo_blockstore_get_many(
+        store: u64,
+        k_lengths: *const i32,
+        k_lengths_len: i32,
+        ks: *const u8,
+        blocks: *mut *mut u8,
+        sizes: *mut i32,
+    ) -> i32;
+
     pub fn cgo_blockstore_put(
         store: u64,
         k: *const u8,
@@ -44,6 +53,14 @@ extern "C" {
         randomness: *mut [u8; 32],
     ) -> i32;
 
+    pub fn cgo_extern_report_execution(
+        handle: u64,
+        exec_id: u64,
+        exit_code: u64,
+        gas_used: u64,
+        num_subcalls: u64,
+    ) -> i32;
+
     pub fn cgo_extern_verify_consensus_fault(
         handle: u64,
         h1: *const u8,
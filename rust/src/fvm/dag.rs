@@ -0,0 +1,451 @@
+//! Helpers for walking IPLD DAGs stored in a blockstore.
+
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use anyhow::{anyhow, bail, Result};
+use cid::Cid;
+use fvm_ipld_encoding::DAG_CBOR;
+use serde_json::{json, Map, Value};
+
+use super::blockstore::GetMany;
+
+/// The maximum number of links we'll follow from the root before giving up. Hash-linked DAGs
+/// can't contain cycles, but "fake" blocks can, so we don't want to rely on that.
+pub const MAX_DAG_DEPTH: usize = 1024;
+
+/// The multihash code of the identity hash. Blocks with identity CIDs are inlined into the CID and
+/// never stored in the blockstore.
+const IDENTITY_HASH: u64 = 0x00;
+
+/// The CBOR tag used by DAG-CBOR to encode CIDs.
+const CID_TAG: u64 = 42;
+
+/// Walks the DAG rooted at `root` breadth-first, calling `visit` exactly once for every reachable
+/// block. Only DAG-CBOR blocks are scanned for links, all other blocks are treated as leaves.
+///
+/// Each layer of the DAG is fetched with a single [`GetMany::get_many`] call.
+///
+/// Fails if a block is missing from the blockstore, or if the DAG is deeper than `max_depth`.
+pub fn walk_dag<BS, F>(bs: &BS, root: Cid, max_depth: usize, visit: F) -> Result<()>
+where
+    BS: GetMany,
+    F: FnMut(&Cid, &[u8]) -> Result<()>,
+{
+    walk(bs, root, max_depth, true, visit)
+}
+
+/// Like [`walk_dag`], but only visits the blocks at most `depth` links away from the root, without
+/// failing if the DAG is any deeper.
+pub fn walk_dag_prefix<BS, F>(bs: &BS, root: Cid, depth: usize, visit: F) -> Result<()>
+where
+    BS: GetMany,
+    F: FnMut(&Cid, &[u8]) -> Result<()>,
+{
+    walk(bs, root, depth, false, visit)
+}
+
+fn walk<BS, F>(bs: &BS, root: Cid, max_depth: usize, strict: bool, mut visit: F) -> Result<()>
+where
+    BS: GetMany,
+    F: FnMut(&Cid, &[u8]) -> Result<()>,
+{
+    let mut seen = HashSet::new();
+    seen.insert(root);
+    let mut layer = vec![root];
+
+    for depth in 0.. {
+        layer.retain(|k| k.hash().code() != IDENTITY_HASH);
+        if layer.is_empty() {
+            break;
+        }
+
+        let mut next = Vec::new();
+        for (k, block) in layer.iter().zip(bs.get_many(&layer)?) {
+            let block = block.ok_or_else(|| anyhow!("missing block {}", k))?;
+            visit(k, &block)?;
+
+            if k.codec() != DAG_CBOR {
+                continue;
+            }
+            scan_for_links(&block, |link| {
+                if depth < max_depth {
+                    if seen.insert(link) {
+                        next.push(link);
+                    }
+                } else if strict && !seen.contains(&link) {
+                    bail!("dag exceeds the maximum depth of {}", max_depth);
+                }
+                Ok(())
+            })?;
+        }
+        layer = next;
+    }
+    Ok(())
+}
+
+/// Returns the blocks reachable from `old` but not from `new` (the blocks a GC of `old` in favor
+/// of `new` could drop), in the order they're reached from `old`.
+///
+/// This reads every block reachable from `new`, and then the blocks only reachable from `old`:
+/// subtrees shared with `new` aren't walked twice. Hash-linked DAGs share everything a change
+/// didn't touch, so the second walk is cheap, but the first one costs as much as walking the whole
+/// new DAG. Fails once more than `max_blocks` blocks have been read, or if a block is missing.
+pub fn unreferenced_blocks<BS>(bs: &BS, old: Cid, new: Cid, max_blocks: usize) -> Result<Vec<Cid>>
+where
+    BS: GetMany,
+{
+    let mut read = 0;
+    let mut count_read = || {
+        read += 1;
+        if read > max_blocks {
+            bail!("dag diff exceeds the maximum of {} blocks", max_blocks);
+        }
+        Ok(())
+    };
+
+    let mut referenced = HashSet::new();
+    walk_dag(bs, new, MAX_DAG_DEPTH, |k, _| {
+        referenced.insert(*k);
+        count_read()
+    })?;
+
+    let mut unreferenced = Vec::new();
+    let mut seen = HashSet::new();
+    seen.insert(old);
+    let mut layer = vec![old];
+    loop {
+        layer.retain(|k| k.hash().code() != IDENTITY_HASH && !referenced.contains(k));
+        if layer.is_empty() {
+            break;
+        }
+
+        let mut next = Vec::new();
+        for (k, block) in layer.iter().zip(bs.get_many(&layer)?) {
+            let block = block.ok_or_else(|| anyhow!("missing block {}", k))?;
+            count_read()?;
+            unreferenced.push(*k);
+
+            if k.codec() != DAG_CBOR {
+                continue;
+            }
+            scan_for_links(&block, |link| {
+                if seen.insert(link) {
+                    next.push(link);
+                }
+                Ok(())
+            })?;
+        }
+        layer = next;
+    }
+    Ok(unreferenced)
+}
+
+fn read_u8(buf: &mut &[u8]) -> Result<u8> {
+    let (&b, rest) = buf
+        .split_first()
+        .ok_or_else(|| anyhow!("unexpected end of cbor"))?;
+    *buf = rest;
+    Ok(b)
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8], n: u64) -> Result<&'a [u8]> {
+    let n = usize::try_from(n)?;
+    if buf.len() < n {
+        bail!("unexpected end of cbor");
+    }
+    let (bytes, rest) = buf.split_at(n);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn read_arg(buf: &mut &[u8], extra: u8) -> Result<u64> {
+    let len = match extra {
+        0..=23 => return Ok(extra as u64),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => bail!("invalid cbor argument: {}", extra),
+    };
+    Ok(read_bytes(buf, len)?
+        .iter()
+        .fold(0, |acc, &b| (acc << 8) | b as u64))
+}
+
+/// Reads the CID following a CID tag.
+fn read_cid(buf: &mut &[u8]) -> Result<Cid> {
+    let header = read_u8(buf)?;
+    if header >> 5 != 2 {
+        bail!("cid tag must be followed by bytes");
+    }
+    let len = read_arg(buf, header & 0x1f)?;
+    match read_bytes(buf, len)? {
+        // CIDs are prefixed with the multibase "identity" prefix.
+        [0, cid @ ..] => Ok(Cid::try_from(cid)?),
+        _ => bail!("invalid cid encoding"),
+    }
+}
+
+/// Scans a DAG-CBOR encoded block for CIDs, calling `f` on each link in the order they appear.
+pub fn scan_for_links<F>(mut buf: &[u8], mut f: F) -> Result<()>
+where
+    F: FnMut(Cid) -> Result<()>,
+{
+    // The number of items we still need to read. This starts at one (the top-level item) and
+    // grows as we enter lists, maps, and tags.
+    let mut remaining: u64 = 1;
+    while remaining > 0 {
+        remaining -= 1;
+        let header = read_u8(&mut buf)?;
+        let (major, extra) = (header >> 5, header & 0x1f);
+        match major {
+            // Integers.
+            0 | 1 => {
+                read_arg(&mut buf, extra)?;
+            }
+            // Bytes and strings.
+            2 | 3 => {
+                let len = read_arg(&mut buf, extra)?;
+                read_bytes(&mut buf, len)?;
+            }
+            // Lists.
+            4 => remaining += read_arg(&mut buf, extra)?,
+            // Maps.
+            5 => remaining += read_arg(&mut buf, extra)? * 2,
+            // Tags.
+            6 => {
+                if read_arg(&mut buf, extra)? != CID_TAG {
+                    remaining += 1;
+                    continue;
+                }
+                f(read_cid(&mut buf)?)?;
+            }
+            // Simple values and floats.
+            _ => {
+                let len = match extra {
+                    0..=23 => 0,
+                    25 => 2,
+                    26 => 4,
+                    27 => 8,
+                    _ => bail!("invalid cbor simple value: {}", extra),
+                };
+                read_bytes(&mut buf, len)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The maximum nesting of lists and maps [`decode_json`] decodes.
+const MAX_JSON_NESTING: usize = 64;
+
+/// Decodes a DAG-CBOR encoded block into JSON, for debugging. Links are decoded as `{"/": cid}`
+/// and byte strings as hex strings. Fails on floats, tags other than CIDs, and negative integers
+/// that don't fit an `i64`.
+pub fn decode_json(mut buf: &[u8]) -> Result<Value> {
+    fn decode(buf: &mut &[u8], depth: usize) -> Result<Value> {
+        if depth > MAX_JSON_NESTING {
+            bail!("cbor is nested deeper than {} levels", MAX_JSON_NESTING);
+        }
+        let header = read_u8(buf)?;
+        let (major, extra) = (header >> 5, header & 0x1f);
+        Ok(match major {
+            0 => read_arg(buf, extra)?.into(),
+            1 => (-1 - i64::try_from(read_arg(buf, extra)?)?).into(),
+            2 => {
+                let len = read_arg(buf, extra)?;
+                let bytes = read_bytes(buf, len)?;
+                bytes
+                    .iter()
+                    .map(|b| format!("{:02x}", b))
+                    .collect::<String>()
+                    .into()
+            }
+            3 => {
+                let len = read_arg(buf, extra)?;
+                std::str::from_utf8(read_bytes(buf, len)?)?.into()
+            }
+            4 => (0..read_arg(buf, extra)?)
+                .map(|_| decode(buf, depth + 1))
+                .collect::<Result<Vec<_>>>()?
+                .into(),
+            5 => {
+                let mut map = Map::new();
+                for _ in 0..read_arg(buf, extra)? {
+                    let key = match decode(buf, depth + 1)? {
+                        Value::String(key) => key,
+                        _ => bail!("map keys must be strings"),
+                    };
+                    map.insert(key, decode(buf, depth + 1)?);
+                }
+                map.into()
+            }
+            6 => {
+                let tag = read_arg(buf, extra)?;
+                if tag != CID_TAG {
+                    bail!("unsupported cbor tag: {}", tag);
+                }
+                json!({ "/": read_cid(buf)?.to_string() })
+            }
+            _ => match extra {
+                20 => false.into(),
+                21 => true.into(),
+                22 => Value::Null,
+                _ => bail!("unsupported cbor simple value: {}", extra),
+            },
+        })
+    }
+
+    let value = decode(&mut buf, 0)?;
+    if !buf.is_empty() {
+        bail!("trailing bytes after cbor");
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cid::multihash::Code;
+    use fvm::state_tree::{ActorState, StateTree};
+    use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::{to_vec, DAG_CBOR, IPLD_RAW};
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::{
+        decode_json, scan_for_links, unreferenced_blocks, walk_dag, walk_dag_prefix, MAX_DAG_DEPTH,
+    };
+
+    #[test]
+    fn test_walk_dag() {
+        let bs = MemoryBlockstore::new();
+        let leaf = bs
+            .put(Code::Blake2b256, &Block::new(IPLD_RAW, &b"leaf"[..]))
+            .unwrap();
+        let inner_data = to_vec(&(1u64, "inner", leaf)).unwrap();
+        let inner = bs
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, &inner_data))
+            .unwrap();
+        let root_data = to_vec(&(vec![inner, leaf], -5i64, true)).unwrap();
+        let root = bs
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, &root_data))
+            .unwrap();
+
+        let mut links = Vec::new();
+        scan_for_links(&root_data, |c| {
+            links.push(c);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(links, vec![inner, leaf]);
+
+        let (mut blocks, mut bytes) = (0, 0);
+        walk_dag(&bs, root, MAX_DAG_DEPTH, |_, data| {
+            blocks += 1;
+            bytes += data.len();
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(blocks, 3);
+        assert_eq!(bytes, root_data.len() + inner_data.len() + 4);
+
+        // Everything but the root is beyond a depth of zero.
+        assert!(walk_dag(&bs, root, 0, |_, _| Ok(())).is_err());
+
+        let mut visited = Vec::new();
+        walk_dag_prefix(&bs, root, 0, |k, _| {
+            visited.push(*k);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(visited, vec![root]);
+
+        visited.clear();
+        walk_dag_prefix(&bs, root, 1, |k, _| {
+            visited.push(*k);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(visited, vec![root, inner, leaf]);
+    }
+
+    #[test]
+    fn test_decode_json() {
+        let bs = MemoryBlockstore::new();
+        let leaf = bs
+            .put(Code::Blake2b256, &Block::new(IPLD_RAW, &b"leaf"[..]))
+            .unwrap();
+        let block = to_vec(&(
+            leaf,
+            -5i64,
+            u64::MAX,
+            "text",
+            serde_bytes::Bytes::new(&[0xca, 0xfe]),
+            HashMap::from([("key", true)]),
+            Option::<u64>::None,
+        ))
+        .unwrap();
+        assert_eq!(
+            decode_json(&block).unwrap(),
+            serde_json::json!([
+                { "/": leaf.to_string() },
+                -5,
+                u64::MAX,
+                "text",
+                "cafe",
+                { "key": true },
+                null
+            ])
+        );
+
+        assert!(decode_json(&block[..block.len() - 1]).is_err());
+        assert!(decode_json(&[&block[..], &[0x00]].concat()).is_err());
+        // A float.
+        assert!(decode_json(&[0xf9, 0x3c, 0x00]).is_err());
+        // Too deeply nested lists.
+        assert!(decode_json(&[&[0x81; 100][..], &[0x00]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_unreferenced_blocks() {
+        let bs = MemoryBlockstore::new();
+        let code = bs
+            .put(Code::Blake2b256, &Block::new(IPLD_RAW, &b"account"[..]))
+            .unwrap();
+        let put_state = |n: u64| {
+            bs.put(
+                Code::Blake2b256,
+                &Block::new(DAG_CBOR, to_vec(&(n, "state")).unwrap()),
+            )
+            .unwrap()
+        };
+        let (old_state, new_state, other_state) = (put_state(1), put_state(2), put_state(3));
+
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        let (address, other) = (Address::new_id(100), Address::new_id(101));
+        let actor = |state| ActorState::new(code, state, TokenAmount::from(0u8), 0);
+        state_tree.set_actor(&address, actor(old_state)).unwrap();
+        state_tree.set_actor(&other, actor(other_state)).unwrap();
+        let old_root = state_tree.flush().unwrap();
+
+        // Overwrite the first actor's state.
+        state_tree.set_actor(&address, actor(new_state)).unwrap();
+        let new_root = state_tree.flush().unwrap();
+
+        let unreferenced = unreferenced_blocks(&bs, old_root, new_root, 1_000).unwrap();
+        assert_eq!(unreferenced[0], old_root);
+        assert!(unreferenced.contains(&old_state));
+        for live in [new_root, new_state, other_state, code] {
+            assert!(!unreferenced.contains(&live));
+        }
+
+        assert!(unreferenced_blocks(&bs, old_root, old_root, 1_000)
+            .unwrap()
+            .is_empty());
+        assert!(unreferenced_blocks(&bs, old_root, new_root, 2).is_err());
+    }
+}
@@ -1,11 +1,16 @@
+use std::sync::Mutex;
+
 use anyhow::{anyhow, Context};
 use fvm::externs::{Consensus, Externs, Rand};
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::RawBytes;
 use fvm_shared::address::Address;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
 use num_traits::FromPrimitive;
 
 use super::cgo::*;
+use super::kernel::SubcallGas;
 
 /// An implementation of [`fvm::externs::Externs`] that can call out to go. See the `cgo` directory
 /// in this repo for the go side.
@@ -14,16 +19,168 @@ use super::cgo::*;
 /// fault verification to the FVM.
 pub struct CgoExterns {
     handle: u64,
+    rand: RecordingRand<CgoRand>,
+    subcall_gas: SubcallGas,
+}
+
+/// The randomness lookups of [`CgoExterns`], calling out to go.
+struct CgoRand {
+    handle: u64,
+}
+
+/// Wraps a [`Rand`] to record the lookups made through it, in order. Lookups are recorded whether
+/// or not they succeed.
+#[derive(Default)]
+pub struct RecordingRand<R> {
+    inner: R,
+    requests: Mutex<Vec<RandomnessRequest>>,
+}
+
+/// A randomness lookup performed through the externs. Recording these lets callers build a witness
+/// that replays a message without access to the chain.
+#[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+pub struct RandomnessRequest {
+    /// True for beacon randomness, false for chain (ticket) randomness.
+    pub beacon: bool,
+    pub personalization: i64,
+    pub round: ChainEpoch,
+    pub entropy: RawBytes,
+}
+
+/// What applying messages takes from a machine's externs, besides the FVM's [`Externs`].
+pub trait ExecutionExterns {
+    /// Returns (and forgets) the randomness requests recorded since the last call.
+    fn take_randomness_requests(&self) -> Vec<RandomnessRequest>;
+
+    /// The gas used by the subcalls of the message being applied.
+    fn subcall_gas(&self) -> &SubcallGas;
+
+    /// Streams the summary of an applied message to the node.
+    fn report_execution(
+        &self,
+        exec_id: u64,
+        exit_code: u64,
+        gas_used: u64,
+        num_subcalls: u64,
+    ) -> anyhow::Result<()>;
 }
 
 impl CgoExterns {
     /// Construct a new externs from a handle.
     pub fn new(handle: u64) -> CgoExterns {
-        CgoExterns { handle }
+        CgoExterns {
+            handle,
+            rand: RecordingRand::new(CgoRand { handle }),
+            subcall_gas: SubcallGas::default(),
+        }
+    }
+}
+
+impl ExecutionExterns for CgoExterns {
+    fn take_randomness_requests(&self) -> Vec<RandomnessRequest> {
+        self.rand.take_requests()
+    }
+
+    fn subcall_gas(&self) -> &SubcallGas {
+        &self.subcall_gas
+    }
+
+    /// Streams the summary to the go side.
+    fn report_execution(
+        &self,
+        exec_id: u64,
+        exit_code: u64,
+        gas_used: u64,
+        num_subcalls: u64,
+    ) -> anyhow::Result<()> {
+        unsafe {
+            match cgo_extern_report_execution(
+                self.handle,
+                exec_id,
+                exit_code,
+                gas_used,
+                num_subcalls,
+            ) {
+                0 => Ok(()),
+                r @ 1.. => panic!("invalid return value from report_execution: {}", r),
+                x if x == FvmError::InvalidHandle as i32 => {
+                    panic!("extern {} not registered", self.handle)
+                }
+                e => Err(anyhow!(
+                    "cgo extern 'report_execution' failed with error code {}",
+                    e
+                )),
+            }
+        }
+    }
+}
+
+impl<R> RecordingRand<R> {
+    pub fn new(inner: R) -> Self {
+        RecordingRand {
+            inner,
+            requests: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns (and forgets) the requests recorded since the last call.
+    pub fn take_requests(&self) -> Vec<RandomnessRequest> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+
+    fn record(&self, beacon: bool, personalization: i64, round: ChainEpoch, entropy: &[u8]) {
+        self.requests.lock().unwrap().push(RandomnessRequest {
+            beacon,
+            personalization,
+            round,
+            entropy: RawBytes::new(entropy.to_vec()),
+        });
+    }
+}
+
+impl<R: Rand> Rand for RecordingRand<R> {
+    fn get_chain_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.record(false, pers, round, entropy);
+        self.inner.get_chain_randomness(pers, round, entropy)
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.record(true, pers, round, entropy);
+        self.inner.get_beacon_randomness(pers, round, entropy)
     }
 }
 
 impl Rand for CgoExterns {
+    fn get_chain_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.rand.get_chain_randomness(pers, round, entropy)
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.rand.get_beacon_randomness(pers, round, entropy)
+    }
+}
+
+impl Rand for CgoRand {
     fn get_chain_randomness(
         &self,
         pers: i64,
@@ -134,3 +291,63 @@ impl Consensus for CgoExterns {
 }
 
 impl Externs for CgoExterns {}
+
+#[cfg(test)]
+mod test {
+    use anyhow::bail;
+    use fvm::externs::Rand;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::clock::ChainEpoch;
+
+    use super::{RandomnessRequest, RecordingRand};
+
+    /// Answers chain randomness with the round in every byte, and fails beacon randomness.
+    struct StubRand;
+
+    impl Rand for StubRand {
+        fn get_chain_randomness(
+            &self,
+            _pers: i64,
+            round: ChainEpoch,
+            _entropy: &[u8],
+        ) -> anyhow::Result<[u8; 32]> {
+            Ok([round as u8; 32])
+        }
+
+        fn get_beacon_randomness(
+            &self,
+            _pers: i64,
+            _round: ChainEpoch,
+            _entropy: &[u8],
+        ) -> anyhow::Result<[u8; 32]> {
+            bail!("no beacon randomness")
+        }
+    }
+
+    #[test]
+    fn test_recording_rand() {
+        let rand = RecordingRand::new(StubRand);
+        assert_eq!(
+            rand.get_chain_randomness(2, 10, b"first").unwrap(),
+            [10; 32]
+        );
+        assert!(rand.get_beacon_randomness(5, 11, b"second").is_err());
+        assert_eq!(rand.get_chain_randomness(7, 12, &[]).unwrap(), [12; 32]);
+
+        let request = |beacon, personalization, round, entropy: &[u8]| RandomnessRequest {
+            beacon,
+            personalization,
+            round,
+            entropy: RawBytes::new(entropy.to_vec()),
+        };
+        assert_eq!(
+            rand.take_requests(),
+            vec![
+                request(false, 2, 10, b"first"),
+                request(true, 5, 11, b"second"),
+                request(false, 7, 12, &[]),
+            ]
+        );
+        assert!(rand.take_requests().is_empty());
+    }
+}
@@ -0,0 +1,102 @@
+//! Fee estimation helpers that don't need a machine.
+
+use anyhow::bail;
+use safer_ffi::prelude::*;
+
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+/// The gas target of a block (half the block gas limit).
+const BLOCK_GAS_TARGET: u64 = 5_000_000_000;
+
+/// The smallest gas premium (attoFIL per gas unit) Lotus suggests.
+const MIN_GAS_PREMIUM: u64 = 100_000;
+
+/// Suggests a gas premium (attoFIL per gas unit) for a message that should be included within
+/// `nblocks_incl` blocks, using Lotus's estimation heuristic.
+///
+/// `premiums` and `gas_limits` describe the messages included in the last `blocks` blocks (Lotus
+/// samples the last `2 * nblocks_incl` tipsets), one entry per message. The fuller those blocks
+/// were with high premium messages, the higher the suggestion. Unlike Lotus, no random noise is
+/// added to the result.
+#[ffi_export]
+fn fvm_estimate_gas_premium(
+    nblocks_incl: u64,
+    blocks: u64,
+    premiums: c_slice::Ref<u64>,
+    gas_limits: c_slice::Ref<u64>,
+) -> repr_c::Box<Result<u64>> {
+    catch_panic_response("fvm_estimate_gas_premium", || {
+        if premiums.len() != gas_limits.len() {
+            bail!(
+                "got {} premiums but {} gas limits",
+                premiums.len(),
+                gas_limits.len()
+            );
+        }
+        let mut samples: Vec<_> = premiums
+            .iter()
+            .copied()
+            .zip(gas_limits.iter().copied())
+            .collect();
+        Ok(estimate_gas_premium(nblocks_incl, blocks, &mut samples))
+    })
+}
+
+destructor!(destroy_fvm_estimate_gas_premium_response, Result<u64>);
+
+/// Lotus's `medianGasPremium`: the premium at which the highest paying messages fill half of the
+/// sampled blocks' gas target (plus 5%), clamped from below by a minimum that depends on how
+/// quickly the message should be included.
+fn estimate_gas_premium(nblocks_incl: u64, blocks: u64, samples: &mut [(u64, u64)]) -> u64 {
+    samples.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let target = BLOCK_GAS_TARGET as i128 * blocks as i128;
+    let mut at = target / 2 + target / 40;
+    let (mut prev1, mut prev2) = (0, 0);
+    for &(premium, gas_limit) in samples.iter() {
+        prev2 = prev1;
+        prev1 = premium;
+        at -= gas_limit as i128;
+        if at < 0 {
+            break;
+        }
+    }
+
+    let premium = if prev2 != 0 {
+        ((prev1 as u128 + prev2 as u128) / 2) as u64
+    } else {
+        prev1
+    };
+    if premium >= MIN_GAS_PREMIUM {
+        return premium;
+    }
+    match nblocks_incl {
+        0 | 1 => 2 * MIN_GAS_PREMIUM,
+        2 => 3 * MIN_GAS_PREMIUM / 2,
+        _ => MIN_GAS_PREMIUM,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{estimate_gas_premium, fvm_estimate_gas_premium, MIN_GAS_PREMIUM};
+
+    #[test]
+    fn test_estimate_gas_premium() {
+        let mut quiet = vec![(200_000, 1_000_000_000), (300_000, 1_000_000_000)];
+        let mut busy = vec![
+            (200_000, 1_000_000_000),
+            (500_000, 3_000_000_000),
+            (400_000, 3_000_000_000),
+        ];
+        assert_eq!(estimate_gas_premium(1, 2, &mut quiet), 250_000);
+        assert_eq!(estimate_gas_premium(1, 2, &mut busy), 450_000);
+
+        assert_eq!(estimate_gas_premium(1, 2, &mut []), 2 * MIN_GAS_PREMIUM);
+        assert_eq!(estimate_gas_premium(5, 10, &mut []), MIN_GAS_PREMIUM);
+
+        let resp = fvm_estimate_gas_premium(1, 2, [1u64][..].into(), [0u64; 0][..].into());
+        assert!(!resp.error_msg.is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+//! Minimal states to create machines from, for test networks bootstrapped without a genesis tool.
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Result};
+use cid::multihash::Code;
+use cid::Cid;
+use fvm::state_tree::{ActorState, StateTree};
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::ActorID;
+
+use super::machine::SYSTEM_ACTOR_ID;
+
+const INIT_ACTOR_ID: ActorID = 1;
+
+/// The first ID assigned by the init actor, lower IDs are reserved for singleton actors.
+const FIRST_NON_SINGLETON_ID: ActorID = 100;
+
+/// An empty HAMT node: an empty bitfield, and no pointers.
+const EMPTY_HAMT: &[u8] = &[0x82, 0x40, 0x80];
+
+/// The state of a v7 system actor, an empty tuple.
+const EMPTY_TUPLE: &[u8] = &[0x80];
+
+/// Builds a state tree holding only the system and init actors in the blockstore, returning its
+/// root. The actors' code CIDs are looked up by name in `builtin_actor_names`, as loaded from the
+/// manifest.
+///
+/// From v8 on, the system actor's state holds the manifest (`manifest_in_state`), v7 system actors
+/// have an empty state. The init actor has assigned no addresses yet, and runs a network named
+/// `network_name`.
+pub fn empty_state_root<BS: Blockstore>(
+    bs: &BS,
+    builtin_actor_names: &HashMap<Cid, String>,
+    manifest_in_state: Option<Cid>,
+    network_name: &str,
+) -> Result<Cid> {
+    let code = |name: &str| {
+        builtin_actor_names
+            .iter()
+            .find(|(_, actor_name)| actor_name.as_str() == name)
+            .map(|(code, _)| *code)
+            .ok_or_else(|| anyhow!("the manifest has no {} actor", name))
+    };
+    let put = |data: Vec<u8>| bs.put(Code::Blake2b256, &Block::new(DAG_CBOR, data));
+
+    let system_state = match manifest_in_state {
+        Some(manifest) => put(to_vec(&(manifest,))?)?,
+        None => put(EMPTY_TUPLE.to_vec())?,
+    };
+    let address_map = put(EMPTY_HAMT.to_vec())?;
+    let init_state = put(to_vec(&(
+        address_map,
+        FIRST_NON_SINGLETON_ID,
+        network_name,
+    ))?)?;
+
+    let mut state_tree = StateTree::new(bs, StateTreeVersion::V4)?;
+    for (id, code, state) in [
+        (SYSTEM_ACTOR_ID, code("system")?, system_state),
+        (INIT_ACTOR_ID, code("init")?, init_state),
+    ] {
+        let actor = ActorState::new(code, state, TokenAmount::from(0u8), 0);
+        state_tree.set_actor(&Address::new_id(id), actor)?;
+    }
+    Ok(state_tree.flush()?)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::state_tree::StateTree;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::{from_slice, DAG_CBOR};
+    use fvm_shared::address::Address;
+
+    use super::empty_state_root;
+
+    #[test]
+    fn test_empty_state_root() {
+        let bs = MemoryBlockstore::new();
+        let code = |name: &str| Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(name.as_bytes()));
+        let mut names: HashMap<_, _> = ["system", "init", "account"]
+            .into_iter()
+            .map(|name| (code(name), name.to_owned()))
+            .collect();
+        let manifest = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"manifest"));
+
+        let root = empty_state_root(&bs, &names, Some(manifest), "localnet").unwrap();
+        let state_tree = StateTree::new_from_root(&bs, &root).unwrap();
+
+        let system = state_tree.get_actor(&Address::new_id(0)).unwrap().unwrap();
+        assert_eq!(system.code, code("system"));
+        let (system_manifest,): (Cid,) =
+            from_slice(&bs.get(&system.state).unwrap().unwrap()).unwrap();
+        assert_eq!(system_manifest, manifest);
+
+        let init = state_tree.get_actor(&Address::new_id(1)).unwrap().unwrap();
+        assert_eq!(init.code, code("init"));
+        let (_, next_id, network_name): (Cid, u64, String) =
+            from_slice(&bs.get(&init.state).unwrap().unwrap()).unwrap();
+        assert_eq!((next_id, network_name.as_str()), (100, "localnet"));
+        // No addresses have been assigned yet.
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        assert_eq!(state_tree.lookup_id(&key).unwrap(), None);
+        assert_eq!(state_tree.get_actor(&Address::new_id(100)).unwrap(), None);
+
+        // v7 system actors have an empty state.
+        let root = empty_state_root(&bs, &names, None, "localnet").unwrap();
+        let state_tree = StateTree::new_from_root(&bs, &root).unwrap();
+        let system = state_tree.get_actor(&Address::new_id(0)).unwrap().unwrap();
+        assert_eq!(bs.get(&system.state).unwrap().unwrap(), vec![0x80]);
+
+        // The manifest needs both actors.
+        names.remove(&code("init"));
+        assert!(empty_state_root(&bs, &names, None, "localnet").is_err());
+    }
+}
@@ -0,0 +1,84 @@
+//! A bounded, in-memory history of recently applied messages, kept for post-mortem debugging.
+
+use std::collections::VecDeque;
+
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_shared::address::Address;
+use fvm_shared::MethodNum;
+
+/// A summary of an applied message.
+#[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+pub struct ExecutionSummary {
+    /// The ID of the execution, as returned in its response.
+    pub exec_id: u64,
+    pub to: Address,
+    pub method: MethodNum,
+    pub exit_code: u64,
+    pub gas_used: u64,
+    /// How long the execution waited for the machine's lock, in nanoseconds.
+    pub lock_wait_ns: u64,
+}
+
+/// A ring buffer holding the summaries of the last `capacity` applied messages. A capacity of
+/// zero disables recording.
+#[derive(Debug, Default)]
+pub struct RecentExecutions {
+    capacity: usize,
+    entries: VecDeque<ExecutionSummary>,
+}
+
+impl RecentExecutions {
+    pub fn new(capacity: usize) -> Self {
+        RecentExecutions {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Records a summary, evicting the oldest one if the buffer is full.
+    pub fn push(&mut self, summary: ExecutionSummary) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(summary);
+    }
+
+    /// Returns the recorded summaries, oldest first.
+    pub fn entries(&self) -> Vec<ExecutionSummary> {
+        self.entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_shared::address::Address;
+
+    use super::{ExecutionSummary, RecentExecutions};
+
+    fn summary(method: u64) -> ExecutionSummary {
+        ExecutionSummary {
+            exec_id: method + 1,
+            to: Address::new_id(100),
+            method,
+            exit_code: 0,
+            gas_used: method * 10,
+            lock_wait_ns: method * 100,
+        }
+    }
+
+    #[test]
+    fn test_recent_executions() {
+        let mut recent = RecentExecutions::new(3);
+        for method in 0..5 {
+            recent.push(summary(method));
+        }
+        assert_eq!(recent.entries(), vec![summary(2), summary(3), summary(4)]);
+
+        let mut disabled = RecentExecutions::default();
+        disabled.push(summary(0));
+        assert!(disabled.entries().is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+//! A kernel wrapper attributing the gas of a message to its receiver and to the subcalls it makes.
+
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+
+use cid::Cid;
+use fvm::call_manager::{CallManager, InvocationResult};
+use fvm::gas::PriceList;
+use fvm::kernel::{
+    ActorOps, BlockId, BlockOps, BlockRegistry, BlockStat, CircSupplyOps, CryptoOps, DebugOps,
+    GasOps, MessageOps, NetworkOps, RandomnessOps, Result, SelfOps, SendOps,
+};
+use fvm::machine::Machine;
+use fvm::Kernel;
+use fvm_shared::actor::builtin::Type;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::crypto::signature::Signature;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::piece::PieceInfo;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::sector::{
+    AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
+    WindowPoStVerifyInfo,
+};
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, MethodNum};
+
+use super::externs::ExecutionExterns;
+
+/// A shared tally of the gas used by the subcalls of the messages applied since it was last
+/// taken, kept by the machine's externs and filled in by [`SubcallGasKernel`].
+#[derive(Clone, Debug, Default)]
+pub struct SubcallGas(Arc<AtomicI64>);
+
+impl SubcallGas {
+    /// Returns (and resets) the gas used by subcalls since the last call.
+    pub fn take(&self) -> i64 {
+        self.0.swap(0, Ordering::Relaxed)
+    }
+
+    fn add(&self, gas: i64) {
+        self.0.fetch_add(gas, Ordering::Relaxed);
+    }
+}
+
+/// Wraps the kernel of the frames the executor invokes, adding the gas used by every send they
+/// make to the [`SubcallGas`] of the machine's externs.
+///
+/// Subcalls are invoked with the wrapped kernel, so only the sends of the receiver of a message
+/// (and of the constructor of an account it creates, which makes none) are measured, and a
+/// subcall's own subcalls are part of its gas. The gas of a send includes everything charged while
+/// it runs, failed or not, so the gas of a message is split between its receiver (with the
+/// message's inclusion) and its subcalls.
+pub struct SubcallGasKernel<K> {
+    inner: K,
+    subcall_gas: SubcallGas,
+}
+
+impl<K> Kernel for SubcallGasKernel<K>
+where
+    K: Kernel,
+    <<K::CallManager as CallManager>::Machine as Machine>::Externs: ExecutionExterns,
+{
+    type CallManager = K::CallManager;
+
+    fn take(self) -> Self::CallManager {
+        self.inner.take()
+    }
+
+    fn new(
+        mgr: Self::CallManager,
+        blocks: BlockRegistry,
+        caller: ActorID,
+        actor_id: ActorID,
+        method: MethodNum,
+        value_received: TokenAmount,
+    ) -> Self {
+        let subcall_gas = mgr.machine().externs().subcall_gas().clone();
+        SubcallGasKernel {
+            inner: K::new(mgr, blocks, caller, actor_id, method, value_received),
+            subcall_gas,
+        }
+    }
+}
+
+impl<K: SendOps + GasOps> SendOps for SubcallGasKernel<K> {
+    fn send(
+        &mut self,
+        recipient: &Address,
+        method: MethodNum,
+        params: BlockId,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult> {
+        let gas_before = self.inner.gas_used();
+        let result = self.inner.send(recipient, method, params, value);
+        self.subcall_gas.add(self.inner.gas_used() - gas_before);
+        result
+    }
+}
+
+impl<K: ActorOps> ActorOps for SubcallGasKernel<K> {
+    fn resolve_address(&self, address: &Address) -> Result<Option<ActorID>> {
+        self.inner.resolve_address(address)
+    }
+
+    fn get_actor_code_cid(&self, addr: &Address) -> Result<Option<Cid>> {
+        self.inner.get_actor_code_cid(addr)
+    }
+
+    fn new_actor_address(&mut self) -> Result<Address> {
+        self.inner.new_actor_address()
+    }
+
+    fn create_actor(&mut self, code_id: Cid, actor_id: ActorID) -> Result<()> {
+        self.inner.create_actor(code_id, actor_id)
+    }
+
+    fn resolve_builtin_actor_type(&self, code_cid: &Cid) -> Option<Type> {
+        self.inner.resolve_builtin_actor_type(code_cid)
+    }
+
+    fn get_code_cid_for_type(&self, typ: Type) -> Result<Cid> {
+        self.inner.get_code_cid_for_type(typ)
+    }
+}
+
+impl<K: BlockOps> BlockOps for SubcallGasKernel<K> {
+    fn block_open(&mut self, cid: &Cid) -> Result<(BlockId, BlockStat)> {
+        self.inner.block_open(cid)
+    }
+
+    fn block_create(&mut self, codec: u64, data: &[u8]) -> Result<BlockId> {
+        self.inner.block_create(codec, data)
+    }
+
+    fn block_link(&mut self, id: BlockId, hash_fun: u64, hash_len: u32) -> Result<Cid> {
+        self.inner.block_link(id, hash_fun, hash_len)
+    }
+
+    fn block_read(&mut self, id: BlockId, offset: u32, buf: &mut [u8]) -> Result<u32> {
+        self.inner.block_read(id, offset, buf)
+    }
+
+    fn block_stat(&mut self, id: BlockId) -> Result<BlockStat> {
+        self.inner.block_stat(id)
+    }
+}
+
+impl<K: CircSupplyOps> CircSupplyOps for SubcallGasKernel<K> {
+    fn total_fil_circ_supply(&self) -> Result<TokenAmount> {
+        self.inner.total_fil_circ_supply()
+    }
+}
+
+impl<K: CryptoOps> CryptoOps for SubcallGasKernel<K> {
+    fn verify_signature(
+        &mut self,
+        signature: &Signature,
+        signer: &Address,
+        plaintext: &[u8],
+    ) -> Result<bool> {
+        self.inner.verify_signature(signature, signer, plaintext)
+    }
+
+    fn hash_blake2b(&mut self, data: &[u8]) -> Result<[u8; 32]> {
+        self.inner.hash_blake2b(data)
+    }
+
+    fn compute_unsealed_sector_cid(
+        &mut self,
+        proof_type: RegisteredSealProof,
+        pieces: &[PieceInfo],
+    ) -> Result<Cid> {
+        self.inner.compute_unsealed_sector_cid(proof_type, pieces)
+    }
+
+    fn verify_seal(&mut self, vi: &SealVerifyInfo) -> Result<bool> {
+        self.inner.verify_seal(vi)
+    }
+
+    fn verify_post(&mut self, verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        self.inner.verify_post(verify_info)
+    }
+
+    fn verify_consensus_fault(
+        &mut self,
+        h1: &[u8],
+        h2: &[u8],
+        extra: &[u8],
+    ) -> Result<Option<ConsensusFault>> {
+        self.inner.verify_consensus_fault(h1, h2, extra)
+    }
+
+    fn batch_verify_seals(&mut self, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        self.inner.batch_verify_seals(vis)
+    }
+
+    fn verify_aggregate_seals(
+        &mut self,
+        aggregate: &AggregateSealVerifyProofAndInfos,
+    ) -> Result<bool> {
+        self.inner.verify_aggregate_seals(aggregate)
+    }
+
+    fn verify_replica_update(&mut self, replica: &ReplicaUpdateInfo) -> Result<bool> {
+        self.inner.verify_replica_update(replica)
+    }
+}
+
+impl<K: DebugOps> DebugOps for SubcallGasKernel<K> {
+    fn log(&self, msg: String) {
+        self.inner.log(msg)
+    }
+
+    fn debug_enabled(&self) -> bool {
+        self.inner.debug_enabled()
+    }
+}
+
+impl<K: GasOps> GasOps for SubcallGasKernel<K> {
+    fn gas_used(&self) -> i64 {
+        self.inner.gas_used()
+    }
+
+    fn charge_gas(&mut self, name: &str, compute: i64) -> Result<()> {
+        self.inner.charge_gas(name, compute)
+    }
+
+    fn price_list(&self) -> &PriceList {
+        self.inner.price_list()
+    }
+}
+
+impl<K: MessageOps> MessageOps for SubcallGasKernel<K> {
+    fn msg_caller(&self) -> ActorID {
+        self.inner.msg_caller()
+    }
+
+    fn msg_receiver(&self) -> ActorID {
+        self.inner.msg_receiver()
+    }
+
+    fn msg_method_number(&self) -> MethodNum {
+        self.inner.msg_method_number()
+    }
+
+    fn msg_value_received(&self) -> TokenAmount {
+        self.inner.msg_value_received()
+    }
+}
+
+impl<K: NetworkOps> NetworkOps for SubcallGasKernel<K> {
+    fn network_epoch(&self) -> ChainEpoch {
+        self.inner.network_epoch()
+    }
+
+    fn network_version(&self) -> NetworkVersion {
+        self.inner.network_version()
+    }
+
+    fn network_base_fee(&self) -> &TokenAmount {
+        self.inner.network_base_fee()
+    }
+}
+
+impl<K: RandomnessOps> RandomnessOps for SubcallGasKernel<K> {
+    fn get_randomness_from_tickets(
+        &mut self,
+        personalization: i64,
+        rand_epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.inner
+            .get_randomness_from_tickets(personalization, rand_epoch, entropy)
+    }
+
+    fn get_randomness_from_beacon(
+        &mut self,
+        personalization: i64,
+        rand_epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.inner
+            .get_randomness_from_beacon(personalization, rand_epoch, entropy)
+    }
+}
+
+impl<K: SelfOps> SelfOps for SubcallGasKernel<K> {
+    fn root(&self) -> Result<Cid> {
+        self.inner.root()
+    }
+
+    fn set_root(&mut self, root: Cid) -> Result<()> {
+        self.inner.set_root(root)
+    }
+
+    fn current_balance(&self) -> Result<TokenAmount> {
+        self.inner.current_balance()
+    }
+
+    fn self_destruct(&mut self, beneficiary: &Address) -> Result<()> {
+        self.inner.self_destruct(beneficiary)
+    }
+}
@@ -1,43 +1,186 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, bail};
+use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
 use futures::executor::block_on;
+use fvm::call_manager::backtrace::{Backtrace, Cause};
 use fvm::call_manager::DefaultCallManager;
-use fvm::executor::{ApplyKind, DefaultExecutor, Executor, ThreadedExecutor};
-use fvm::machine::{DefaultMachine, MultiEngine};
+use fvm::executor::{
+    ApplyFailure, ApplyKind, ApplyRet, DefaultExecutor, Executor, ThreadedExecutor,
+};
+use fvm::externs::Externs;
+use fvm::gas::price_list_by_network_version;
+use fvm::machine::{DefaultMachine, Engine, Machine, MachineContext, MultiEngine};
+use fvm::state_tree::{ActorState, StateTree};
 use fvm::trace::ExecutionEvent;
-use fvm::DefaultKernel;
+use fvm::{DefaultKernel, Kernel};
 use fvm_ipld_blockstore::Blockstore;
 use fvm_ipld_car::load_car;
 use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
-use fvm_ipld_encoding::{to_vec, RawBytes};
-use fvm_shared::address::Address;
+use fvm_ipld_encoding::{to_vec, RawBytes, DAG_CBOR};
+use fvm_shared::address::{Address, Protocol};
+use fvm_shared::bigint::bigint_ser;
+use fvm_shared::crypto::signature::{Signature, SignatureType};
 use fvm_shared::error::{ErrorNumber, ExitCode};
 use fvm_shared::receipt::Receipt;
-use fvm_shared::{clock::ChainEpoch, econ::TokenAmount, message::Message, version::NetworkVersion};
+use fvm_shared::{
+    clock::ChainEpoch, econ::TokenAmount, message::Message, version::NetworkVersion, ActorID,
+    MethodNum, METHOD_SEND,
+};
 use lazy_static::lazy_static;
-use log::info;
+use log::{error, info};
+use num_traits::Zero;
 use safer_ffi::prelude::*;
 
-use super::blockstore::{CgoBlockstore, FakeBlockstore, OverlayBlockstore};
-use super::externs::CgoExterns;
+use super::blockstore::{
+    AccessLog, AccessLogBlockstore, BatchedBlockstore, CgoBlockstore, FakeBlockstore,
+    OverlayBlockstore, ReadCacheBlockstore, WriteLog, WriteLogBlockstore,
+};
+use super::car::car_roundtrip;
+use super::dag::{decode_json, unreferenced_blocks, walk_dag, walk_dag_prefix, MAX_DAG_DEPTH};
+use super::externs::{CgoExterns, ExecutionExterns};
+use super::genesis::empty_state_root;
+use super::history::{ExecutionSummary, RecentExecutions};
+use super::kernel::SubcallGasKernel;
+use super::methods::method_name;
+use super::proofs::{actor_proof, changed_actors};
+use super::receipts::receipts_root;
+use super::trace::encode_compact_trace;
 use super::types::*;
 use crate::destructor;
 use crate::util::types::{catch_panic_response, catch_panic_response_no_default, Result};
 
-type CgoMachine = DefaultMachine<OverlayBlockstore<CgoBlockstore>, CgoExterns>;
-type BaseExecutor = DefaultExecutor<DefaultKernel<DefaultCallManager<CgoMachine>>>;
+type CgoMachine = DefaultMachine<
+    OverlayBlockstore<AccessLogBlockstore<ReadCacheBlockstore<WriteLogBlockstore<CgoBlockstore>>>>,
+    CgoExterns,
+>;
+type BaseExecutor =
+    DefaultExecutor<SubcallGasKernel<DefaultKernel<DefaultCallManager<CgoMachine>>>>;
 
 pub type CgoExecutor = ThreadedExecutor<BaseExecutor>;
 
+/// An executor of a default machine over any blockstore and externs: the [`CgoExecutor`], or the
+/// scripted executors of tests.
+type MachineExecutor<B, E> = ThreadedExecutor<
+    DefaultExecutor<SubcallGasKernel<DefaultKernel<DefaultCallManager<DefaultMachine<B, E>>>>>,
+>;
+
 fn new_executor(machine: CgoMachine) -> CgoExecutor {
     ThreadedExecutor(BaseExecutor::new(machine))
 }
 
+impl InnerFvmMachine {
+    /// Locks the machine.
+    fn lock(&self) -> MutexGuard<'_, CgoExecutor> {
+        self.machine
+            .as_ref()
+            .expect("missing executor")
+            .lock()
+            .unwrap()
+    }
+
+    /// Locks the machine for modification, invalidating the root cached by the last flush. Also
+    /// returns how long it waited for the lock, which is reported with the executions. Fails if
+    /// the machine is read-only.
+    fn lock_mut(&self) -> anyhow::Result<(MutexGuard<'_, CgoExecutor>, Duration)> {
+        self.check_writable()?;
+        let (machine, wait) = timed_lock(self.machine.as_ref().expect("missing executor"));
+        self.flushed_root.lock().unwrap().take();
+        Ok((machine, wait))
+    }
+
+    /// Fails if the machine was created read-only.
+    fn check_writable(&self) -> anyhow::Result<()> {
+        if self.read_only {
+            bail!("the machine is read-only");
+        }
+        Ok(())
+    }
+
+    /// The machine's blockstore below its buffered blockstore, for writes that must persist even
+    /// though they aren't reachable from the state root. They go to the caller's blockstore through
+    /// the same read cache and write log as the machine's flushes.
+    fn base_blockstore(&self) -> ReadCacheBlockstore<WriteLogBlockstore<CgoBlockstore>> {
+        ReadCacheBlockstore::with_cache(
+            WriteLogBlockstore::new(
+                CgoBlockstore::new(self.blockstore_id),
+                self.write_log.clone(),
+            ),
+            self.block_cache.clone(),
+        )
+    }
+
+    /// Records a summary of an applied message in the recent executions buffer.
+    fn record_execution(&self, to: Address, method: MethodNum, resp: &FvmMachineExecuteResponse) {
+        self.recent_executions
+            .lock()
+            .unwrap()
+            .push(ExecutionSummary {
+                exec_id: resp.exec_id,
+                to,
+                method,
+                exit_code: resp.exit_code,
+                gas_used: resp.gas_used,
+                lock_wait_ns: resp.lock_wait_ns,
+            });
+    }
+
+    /// Returns the CID of the manifest the machine is using, resolving it from the system actor's
+    /// state if it wasn't passed explicitly (or loaded from the builtin bundles).
+    fn effective_manifest_cid(&self) -> anyhow::Result<Option<Cid>> {
+        match self.manifest_cid {
+            Some(cid) => Ok(Some(cid)),
+            None => manifest_cid_from_state(self.lock().0.state_tree()),
+        }
+    }
+}
+
+/// Locks the mutex, returning the guard and how long it waited to acquire it.
+fn timed_lock<T>(mutex: &Mutex<T>) -> (MutexGuard<'_, T>, Duration) {
+    let started = Instant::now();
+    let guard = mutex.lock().unwrap();
+    (guard, started.elapsed())
+}
+
+/// The ID of the next execution. IDs start at 1, so that 0 never identifies an execution.
+static NEXT_EXEC_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Returns a new execution ID, greater than all previously returned ones.
+fn next_exec_id() -> u64 {
+    NEXT_EXEC_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The maximum size of a JSON state dump. Dumps are meant for inspecting small (test) states.
+const MAX_STATE_DUMP_SIZE: usize = 64 << 20; // 64MiB
+
+/// How far below the state root `fvm_machine_prefetch_state` prefetches: the actors HAMT root
+/// and two levels of HAMT nodes.
+const PREFETCH_DEPTH: usize = 3;
+
+/// The ID of the system actor, whose state holds the actor manifest from nv16 onwards.
+pub(super) const SYSTEM_ACTOR_ID: ActorID = 0;
+
+/// The ID of the init actor, which assigns the IDs of new actors.
+const INIT_ACTOR_ID: ActorID = 1;
+
 lazy_static! {
-    static ref ENGINES: MultiEngine = MultiEngine::new();
+    pub(super) static ref ENGINES: MultiEngine = MultiEngine::new();
+    /// Debugging mode: when `FVM_FAIL_FAST_ON_SYSCALL_ERROR` is set, a message during which a
+    /// syscall failed with an error number (e.g. a send to a missing actor, or an illegal CID)
+    /// fails the call with extended diagnostics instead of being mapped to an exit code, and is
+    /// reverted. See [`syscall_error_diagnostics`] for the errors that can be seen. This forces
+    /// tracing on for every machine.
+    static ref FAIL_FAST_ON_SYSCALL_ERROR: bool =
+        std::env::var_os("FVM_FAIL_FAST_ON_SYSCALL_ERROR").is_some();
+    /// Debugging mode: when `FVM_ASSERT_GAS` is set, applying an explicit message fails if it used
+    /// more gas than its gas limit, which would indicate a gas accounting bug. The message is then
+    /// reverted.
+    static ref ASSERT_GAS: bool = std::env::var_os("FVM_ASSERT_GAS").is_some();
 }
 
 /// Note: the incoming args as u64 and odd conversions to i32/i64
@@ -55,10 +198,10 @@ fn create_fvm_machine(
     network_version: u64,
     state_root: c_slice::Ref<u8>,
     manifest_cid: c_slice::Ref<u8>,
-    tracing: bool,
+    options: FvmMachineOptions,
     blockstore_id: u64,
     externs_id: u64,
-) -> repr_c::Box<Result<FvmMachine>> {
+) -> repr_c::Box<Result<FvmMachineCreateResponse>> {
     use fvm::machine::NetworkConfig;
     unsafe {
         catch_panic_response_no_default("create_fvm_machine", || {
@@ -95,15 +238,27 @@ fn create_fvm_machine(
                 None
             };
 
-            let blockstore = FakeBlockstore::new(CgoBlockstore::new(blockstore_id));
+            // A manifest passed by the caller always takes precedence.
+            let forced_manifest = manifest_cid.is_some();
+
+            let write_log = WriteLog::new(options.flush_write_log as usize);
+            let read_cache = ReadCacheBlockstore::new(
+                WriteLogBlockstore::new(CgoBlockstore::new(blockstore_id), write_log.clone()),
+                options.read_cache_size as usize,
+            );
+            let block_cache = read_cache.cache();
+            let access_log = AccessLog::default();
+            let blockstore =
+                FakeBlockstore::new(AccessLogBlockstore::new(read_cache, access_log.clone()));
 
             let mut network_config = NetworkConfig::new(network_version);
-            match import_actors(&blockstore, manifest_cid, network_version) {
-                Ok(Some(manifest)) => {
-                    network_config.override_actors(manifest);
-                }
-                Ok(None) => {}
+            let manifest_cid = match import_actors(&blockstore, manifest_cid, network_version) {
+                Ok(manifest_cid) => manifest_cid,
                 Err(err) => bail!("couldn't load builtin actors: {}", err),
+            };
+            let actor_source = actor_source(forced_manifest, manifest_cid.is_some());
+            if let Some(manifest) = manifest_cid {
+                network_config.override_actors(manifest);
             }
             let mut machine_context = network_config.for_epoch(chain_epoch, state_root);
 
@@ -111,10 +266,11 @@ fn create_fvm_machine(
                 .set_base_fee(base_fee)
                 .set_circulating_supply(base_circ_supply);
 
-            if tracing {
+            if options.tracing || *FAIL_FAST_ON_SYSCALL_ERROR {
                 machine_context.enable_tracing();
             }
             let blockstore = blockstore.finish();
+            let read_batch = blockstore.batch();
 
             let externs = CgoExterns::new(externs_id);
 
@@ -123,252 +279,2439 @@ fn create_fvm_machine(
                 Err(err) => bail!("failed to create engine: {}", err),
             };
 
-            let machine = CgoMachine::new(&engine, &machine_context, blockstore, externs)?;
+            let machine = new_machine(&engine, &machine_context, blockstore, externs)?;
+
+            let builtin_actor_names = match manifest_cid {
+                Some(manifest) => Some(manifest),
+                None => manifest_cid_from_state(machine.state_tree())?,
+            }
+            .map(|manifest| builtin_actor_names(machine.blockstore(), &manifest))
+            .transpose()?
+            .unwrap_or_default();
 
-            Ok(Some(repr_c::Box::new(InnerFvmMachine {
+            let machine = InnerFvmMachine {
                 machine: Some(Mutex::new(new_executor(machine))),
-            })))
+                flushed_root: Mutex::new(None),
+                max_return_size: options.max_return_size,
+                compact_trace: options.compact_trace,
+                lotus_trace_format: options.lotus_trace_format,
+                manifest_cid,
+                builtin_actor_names,
+                recent_executions: Mutex::new(RecentExecutions::new(
+                    options.recent_executions as usize,
+                )),
+                stream_summaries: options.stream_summaries,
+                block_cache,
+                read_batch,
+                blockstore_id,
+                read_only: options.read_only,
+                write_log,
+                access_log,
+            };
+            Ok(FvmMachineCreateResponse {
+                machine: Some(repr_c::Box::new(machine)),
+                forced_manifest,
+                actor_source,
+            })
         })
     }
 }
 
+/// Creates a machine over the blockstore, first loading the state tree at the context's initial
+/// state root. A root missing from the blockstore, or not holding a state tree, fails the creation
+/// with an error naming the root, instead of the first message applied.
+pub(super) fn new_machine<B, E>(
+    engine: &Engine,
+    context: &MachineContext,
+    blockstore: B,
+    externs: E,
+) -> anyhow::Result<DefaultMachine<B, E>>
+where
+    B: Blockstore + 'static,
+    E: Externs + 'static,
+{
+    let state_root = context.initial_state_root;
+    if !blockstore
+        .has(&state_root)
+        .map_err(|err| anyhow!("couldn't read state root {}: {}", state_root, err))?
+    {
+        bail!("state root {} not found in the blockstore", state_root);
+    }
+    StateTree::new_from_root(&blockstore, &state_root)
+        .map_err(|err| anyhow!("state root {} is unreadable: {}", state_root, err))?;
+    DefaultMachine::new(engine, context, blockstore, externs)
+}
+
 #[ffi_export]
 fn fvm_machine_execute_message(
     executor: &'_ InnerFvmMachine,
     message: c_slice::Ref<u8>,
     chain_len: u64,
-    apply_kind: u64, /* 0: Explicit, _: Implicit */
+    apply_kind: u64, /* 0: Explicit, 1: Implicit */
+    options: FvmExecuteOptions,
 ) -> repr_c::Box<Result<FvmMachineExecuteResponse>> {
     catch_panic_response("fvm_machine_execute_message", || {
-        let apply_kind = if apply_kind == 0 {
-            ApplyKind::Explicit
-        } else {
-            ApplyKind::Implicit
-        };
-
+        let apply_kind = decode_apply_kind(apply_kind)?;
         let message: Message = fvm_ipld_encoding::from_slice(&message)?;
 
-        let mut executor = executor
-            .machine
-            .as_ref()
-            .expect("missing executor")
-            .lock()
-            .unwrap();
-        let apply_ret = executor.execute_message(message, apply_kind, chain_len as usize)?;
-
-        let exec_trace = if !apply_ret.exec_trace.is_empty() {
-            let mut trace_iter = apply_ret.exec_trace.into_iter();
-            build_lotus_trace(
-                &trace_iter
-                    .next()
-                    .expect("already checked trace for emptiness"),
-                &mut trace_iter,
-            )
-            .ok()
-            .and_then(|t| to_vec(&t).ok())
-            .map(|trace| trace.into_boxed_slice().into())
-        } else {
-            None
-        };
+        let (mut machine, lock_wait) = executor.lock_mut()?;
+        apply_message(
+            executor,
+            &mut machine,
+            message,
+            apply_kind,
+            chain_len,
+            lock_wait,
+            options,
+        )
+    })
+}
 
-        let failure_info = apply_ret
-            .failure_info
-            .map(|info| info.to_string().into_boxed_str().into());
+/// Applies a `SignedMessage` as an explicit message, with the options of
+/// `fvm_machine_execute_message`. If `verify_signature` is set in the options, the signature is
+/// first checked against the sender's key, failing with "message signature verification failed" if
+/// it doesn't match.
+#[ffi_export]
+fn fvm_machine_execute_signed_message(
+    executor: &'_ InnerFvmMachine,
+    signed_message: c_slice::Ref<u8>,
+    chain_len: u64,
+    options: FvmExecuteOptions,
+) -> repr_c::Box<Result<FvmMachineExecuteResponse>> {
+    catch_panic_response("fvm_machine_execute_signed_message", || {
+        let SignedMessage { message, signature } = fvm_ipld_encoding::from_slice(&signed_message)?;
 
-        // TODO: use the non-bigint token amount everywhere in the FVM
-        let penalty: u128 = apply_ret.penalty.try_into().unwrap();
-        let miner_tip: u128 = apply_ret.miner_tip.try_into().unwrap();
-        let base_fee_burn: u128 = apply_ret.base_fee_burn.try_into().unwrap();
-        let over_estimation_burn: u128 = apply_ret.over_estimation_burn.try_into().unwrap();
-        let refund: u128 = apply_ret.refund.try_into().unwrap();
-        let gas_refund = apply_ret.gas_refund;
-        let gas_burned = apply_ret.gas_burned;
+        let (mut machine, lock_wait) = executor.lock_mut()?;
+        if options.verify_signature {
+            let key = resolve_key_address(&machine, &message.from)?;
+            verify_message_signature(&message, &signature, &key)?;
+        }
+        apply_message(
+            executor,
+            &mut machine,
+            message,
+            ApplyKind::Explicit,
+            chain_len,
+            lock_wait,
+            options,
+        )
+    })
+}
 
-        let Receipt {
-            exit_code,
-            return_data,
-            gas_used,
-        } = apply_ret.msg_receipt;
+/// Applies an explicit message like `fvm_machine_execute_message` (with the default options), then
+/// flushes the machine like `fvm_machine_flush`, all under a single lock. This saves a call and a
+/// lock acquisition for callers that flush after every message.
+#[ffi_export]
+fn fvm_machine_execute_and_flush(
+    executor: &'_ InnerFvmMachine,
+    message: c_slice::Ref<u8>,
+    chain_len: u64,
+) -> repr_c::Box<Result<FvmMachineExecuteAndFlushResponse>> {
+    catch_panic_response("fvm_machine_execute_and_flush", || {
+        let message: Message = fvm_ipld_encoding::from_slice(&message)?;
 
-        let return_val = if return_data.is_empty() {
-            None
-        } else {
-            let bytes: Vec<u8> = return_data.into();
-            Some(bytes.into_boxed_slice().into())
-        };
-
-        // TODO: Do something with the backtrace.
-        Ok(FvmMachineExecuteResponse {
-            exit_code: exit_code.value() as u64,
-            return_val,
-            gas_used: gas_used as u64,
-            penalty_hi: (penalty >> u64::BITS) as u64,
-            penalty_lo: penalty as u64,
-            miner_tip_hi: (miner_tip >> u64::BITS) as u64,
-            miner_tip_lo: miner_tip as u64,
-            base_fee_burn_hi: (base_fee_burn >> u64::BITS) as u64,
-            base_fee_burn_lo: base_fee_burn as u64,
-            over_estimation_burn_hi: (over_estimation_burn >> u64::BITS) as u64,
-            over_estimation_burn_lo: over_estimation_burn as u64,
-            refund_hi: (refund >> u64::BITS) as u64,
-            refund_lo: refund as u64,
-            gas_refund,
-            gas_burned,
-            exec_trace,
-            failure_info,
+        let (mut machine, lock_wait) = executor.lock_mut()?;
+        let execution = apply_message(
+            executor,
+            &mut machine,
+            message,
+            ApplyKind::Explicit,
+            chain_len,
+            lock_wait,
+            FvmExecuteOptions::default(),
+        )?;
+        let (state_root, _) = flush_machine(executor, &mut machine)?;
+        Ok(FvmMachineExecuteAndFlushResponse {
+            execution,
+            state_root: state_root.to_bytes().into_boxed_slice().into(),
         })
     })
 }
 
+/// Applies an explicit message and flushes the machine like `fvm_machine_execute_and_flush`, but
+/// only returns the exit code and the new state root. Nothing else is collected from the
+/// execution, and it isn't recorded in the recent executions or streamed to the externs, making
+/// this the cheapest way to replay messages when only the resulting root matters.
 #[ffi_export]
-fn fvm_machine_flush(executor: &'_ InnerFvmMachine) -> repr_c::Box<Result<c_slice::Box<u8>>> {
-    catch_panic_response("fvm_machine_flush", || {
-        let mut executor = executor
-            .machine
-            .as_ref()
-            .expect("missing executor")
-            .lock()
-            .unwrap();
-        let cid = executor.0.flush()?;
+fn fvm_machine_execute_for_root(
+    executor: &'_ InnerFvmMachine,
+    message: c_slice::Ref<u8>,
+    chain_len: u64,
+) -> repr_c::Box<Result<FvmMachineExecuteForRootResponse>> {
+    catch_panic_response("fvm_machine_execute_for_root", || {
+        let message: Message = fvm_ipld_encoding::from_slice(&message)?;
 
-        Ok(cid.to_bytes().into_boxed_slice().into())
+        let (mut machine, _) = executor.lock_mut()?;
+        let apply_ret =
+            machine.execute_message(message, ApplyKind::Explicit, chain_len as usize)?;
+        machine.0.externs().take_randomness_requests();
+        let (state_root, _) = flush_machine(executor, &mut machine)?;
+        Ok(FvmMachineExecuteForRootResponse {
+            exit_code: apply_ret.msg_receipt.exit_code.value() as u64,
+            state_root: state_root.to_bytes().into_boxed_slice().into(),
+        })
     })
 }
 
-destructor!(drop_fvm_machine, InnerFvmMachine);
-destructor!(destroy_create_fvm_machine_response, Result<FvmMachine>);
-
-destructor!(
-    destroy_fvm_machine_execute_response,
-    Result<FvmMachineExecuteResponse>
-);
+/// Applies an explicit message and flushes the machine like `fvm_machine_execute_for_root`, also
+/// returning the message's access list: the actors it touched, the blocks it read from the caller's
+/// blockstore, and the blocks the flush wrote to it.
+///
+/// The actors are those of [`touched_actors`], so the ones called by the message are only known if
+/// the machine traces executions. Blocks the machine keeps in memory once loaded (like the nodes of
+/// the state tree) are only listed by the first message reading them, and the writes include those
+/// of messages applied without being flushed since the last flush.
+#[ffi_export]
+fn fvm_machine_execute_with_access_list(
+    executor: &'_ InnerFvmMachine,
+    message: c_slice::Ref<u8>,
+    chain_len: u64,
+) -> repr_c::Box<Result<FvmMachineExecuteWithAccessListResponse>> {
+    catch_panic_response("fvm_machine_execute_with_access_list", || {
+        let message: Message = fvm_ipld_encoding::from_slice(&message)?;
 
-destructor!(destroy_fvm_machine_flush_response, Result<c_slice::Box<u8>>);
+        let (mut machine, _) = executor.lock_mut()?;
+        let (apply_ret, state_root, access_list) = execute_with_access_list(
+            &mut machine,
+            &executor.access_log,
+            message,
+            chain_len as usize,
+            |machine| flush_machine(executor, machine).map(|(root, _)| root),
+        )?;
+        machine.0.externs().take_randomness_requests();
+        Ok(FvmMachineExecuteWithAccessListResponse {
+            exit_code: apply_ret.msg_receipt.exit_code.value() as u64,
+            state_root: state_root.to_bytes().into_boxed_slice().into(),
+            access_list: to_vec(&access_list)?.into_boxed_slice().into(),
+        })
+    })
+}
 
-fn import_actors(
-    blockstore: &impl Blockstore,
-    manifest_cid: Option<Cid>,
-    network_version: NetworkVersion,
-) -> std::result::Result<Option<Cid>, &'static str> {
-    if manifest_cid.is_some() {
-        return Ok(manifest_cid);
-    }
-    let car = match network_version {
-        NetworkVersion::V15 => Ok(actors_v7::BUNDLE_CAR),
-        NetworkVersion::V16 => {
-            return Ok(None);
+/// Applies the messages in order as explicit messages, under a single lock, with the options of
+/// `fvm_machine_execute_message`. `chain_lens` holds the length on chain of every message. Messages
+/// are applied whether or not the ones before them succeeded, and if one fails the call (e.g.
+/// because of `strict`), the messages before it stay applied. Unless `all_or_nothing` is set: then
+/// the batch stops at the first message that doesn't succeed, reverting all the messages applied,
+/// and the state is left unchanged if one fails the call.
+///
+/// Messages are applied one after the other, never concurrently, so the results and the state are
+/// those of applying the messages one at a time in that order, as Lotus does. The machine has a
+/// single state tree, which each message needs exclusive access to.
+#[ffi_export]
+fn fvm_machine_execute_batch(
+    executor: &'_ InnerFvmMachine,
+    messages: c_slice::Ref<c_slice::Box<u8>>,
+    chain_lens: c_slice::Ref<u64>,
+    options: FvmBatchOptions,
+) -> repr_c::Box<Result<FvmMachineExecuteBatchResponse>> {
+    catch_panic_response("fvm_machine_execute_batch", || {
+        if messages.len() != chain_lens.len() {
+            bail!(
+                "got {} messages but {} chain lengths",
+                messages.len(),
+                chain_lens.len()
+            );
         }
-        _ => Err("unsupported network version"),
-    }?;
-    let roots = block_on(async { load_car(blockstore, car).await.unwrap() });
-    assert_eq!(roots.len(), 1);
-    Ok(Some(roots[0]))
-}
+        let messages = messages
+            .iter()
+            .zip(chain_lens.iter().copied())
+            .map(|(message, chain_len)| Ok((fvm_ipld_encoding::from_slice(message)?, chain_len)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
 
-#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple)]
-struct LotusTrace {
-    pub msg: Message,
-    pub msg_receipt: Receipt,
-    pub error: String,
-    pub subcalls: Vec<LotusTrace>,
+        let (mut machine, lock_wait) = executor.lock_mut()?;
+        apply_batch(executor, &mut machine, messages, lock_wait, options)
+    })
 }
 
-fn build_lotus_trace(
-    new_call: &ExecutionEvent,
-    trace_iter: &mut impl Iterator<Item = ExecutionEvent>,
-) -> anyhow::Result<LotusTrace> {
-    let mut new_trace = LotusTrace {
-        msg: match new_call {
-            ExecutionEvent::Call {
+/// Applies the message to the executor's already locked machine, converting the result into an FFI
+/// response according to the executor's and the call's options, and records it in the recent
+/// executions. Under `strict`, a message that didn't succeed fails the call once it's recorded.
+///
+/// The message is reverted if its execution fails, or fails the fail-fast, gas assertion, or
+/// subcall limit checks. Exceeding the allocation budget fails the call once the message has been
+/// applied, and leaves it applied: the budget covers output, like state proofs, built from the post
+/// state root, which can't be computed while the message can still be reverted.
+fn apply_message<B, E>(
+    executor: &InnerFvmMachine,
+    machine: &mut MachineExecutor<B, E>,
+    mut message: Message,
+    apply_kind: ApplyKind,
+    chain_len: u64,
+    lock_wait: Duration,
+    options: FvmExecuteOptions,
+) -> anyhow::Result<FvmMachineExecuteResponse>
+where
+    B: Blockstore + 'static,
+    E: Externs + ExecutionExterns + 'static,
+    MachineExecutor<B, E>: Executor,
+{
+    apply_gas_ceiling(&mut message, apply_kind, options.gas_ceiling)?;
+    if options.gas_free {
+        if options.gas_ceiling != 0 {
+            bail!("a gas ceiling can't be applied to gas-free messages");
+        }
+        make_gas_free(&mut message);
+    }
+
+    let mut budget = AllocationBudget::new(options.allocation_budget);
+    let (from, to, method, gas_limit) = (
+        message.from,
+        message.to,
+        message.method_num,
+        message.gas_limit,
+    );
+    let params_len = message.params.bytes().len() as u64;
+    let assert_gas = *ASSERT_GAS && matches!(apply_kind, ApplyKind::Explicit);
+    // Implicit messages don't pay any fees.
+    let (effective_base_fee, effective_premium) = match apply_kind {
+        ApplyKind::Explicit => effective_fees(
+            &machine.0.context().base_fee,
+            &message.gas_fee_cap,
+            &message.gas_premium,
+        ),
+        ApplyKind::Implicit => (TokenAmount::default(), TokenAmount::default()),
+    };
+    let balance_margin = if options.balance_margin {
+        let balance = match machine.0.state_tree().get_actor(&message.from)? {
+            Some(sender) => sender.balance,
+            None => TokenAmount::default(),
+        };
+        balance_margin(
+            &balance,
+            &message,
+            matches!(apply_kind, ApplyKind::Explicit),
+        )
+    } else {
+        TokenAmount::zero()
+    };
+    let pre_receiver_id = if options.receiver_info {
+        machine.0.state_tree().lookup_id(&to)?
+    } else {
+        None
+    };
+    // The proofs are against the post state root, so it's returned along with them.
+    let state_roots = options.state_roots || options.state_proofs || options.gc_candidates != 0;
+    let pre_state_root = if state_roots {
+        Some(state_root(machine.0.state_tree_mut())?)
+    } else {
+        None
+    };
+
+    // Drop anything left over from a previous (failed) execution.
+    machine.0.externs().take_randomness_requests();
+    machine.0.externs().subcall_gas().take();
+    let apply_ret = execute_checked(machine, message, apply_kind, chain_len as usize, |ret| {
+        if *FAIL_FAST_ON_SYSCALL_ERROR {
+            if let Some(diagnostics) =
+                syscall_error_diagnostics(&ret.exec_trace, ret.failure_info.as_ref())
+            {
+                bail!(diagnostics);
+            }
+        }
+        if assert_gas {
+            check_gas_used(ret.msg_receipt.gas_used, gas_limit)?;
+        }
+        if options.max_subcalls != 0 {
+            check_subcalls(count_subcalls(&ret.exec_trace), options.max_subcalls)?;
+        }
+        Ok(())
+    })?;
+    let randomness_requests = machine.0.externs().take_randomness_requests();
+    let subcall_gas = machine.0.externs().subcall_gas().take() as u64;
+
+    let post_state_root = if state_roots {
+        Some(state_root(machine.0.state_tree_mut())?)
+    } else {
+        None
+    };
+    let state_proofs = match (&pre_state_root, &post_state_root) {
+        (Some(pre_root), Some(post_root)) if options.state_proofs => {
+            let bs = machine.0.blockstore();
+            let candidates = state_proof_candidates(
                 from,
                 to,
-                method,
-                params,
-                value,
-            } => Message {
-                version: 0,
-                from: Address::new_id(*from),
-                to: *to,
-                sequence: 0,
-                value: value.clone(),
-                method_num: *method,
-                params: params.clone(),
-                gas_limit: 0,
-                gas_fee_cap: TokenAmount::default(),
-                gas_premium: TokenAmount::default(),
-            },
-            _ => {
-                return Err(anyhow!("expected ExecutionEvent of type Call"));
+                matches!(apply_kind, ApplyKind::Explicit),
+                &apply_ret.exec_trace,
+            );
+            let changed = changed_actors(
+                &StateTree::new_from_root(bs, pre_root)?,
+                &StateTree::new_from_root(bs, post_root)?,
+                candidates,
+            )?;
+            // Deleted actors have nothing to prove.
+            let used = budget.used;
+            let mut proofs = Vec::new();
+            for address in &changed {
+                if let Some(proof) = actor_proof(bs, post_root, address)? {
+                    let bytes = proof
+                        .blocks
+                        .iter()
+                        .map(|(_, block)| block.bytes().len())
+                        .sum();
+                    budget.charge("state proofs", bytes)?;
+                    proofs.push(proof);
+                }
             }
+            let proofs = to_vec(&proofs)?;
+            budget.charge_rest("state proofs", used, proofs.len())?;
+            Some(proofs)
+        }
+        _ => None,
+    };
+    let gc_candidates = match (&pre_state_root, &post_state_root) {
+        (Some(pre_root), Some(post_root)) if options.gc_candidates != 0 => unreferenced_blocks(
+            &BatchedBlockstore::new(machine.0.blockstore(), &executor.read_batch),
+            *pre_root,
+            *post_root,
+            options.gc_candidates as usize,
+        )?,
+        _ => Vec::new(),
+    };
+    // Telling params that failed to decode apart from other serialization errors takes the
+    // receiver's kind, so the receiver is also looked up for messages failing with one.
+    let serialization_error = apply_ret.msg_receipt.exit_code == ExitCode::USR_SERIALIZATION;
+    let receiver_code = if options.receiver_info || serialization_error {
+        machine
+            .0
+            .state_tree()
+            .get_actor(&to)?
+            .map(|actor| actor.code)
+    } else {
+        None
+    };
+    let params_decode_failed = params_decode_failed(
+        apply_ret.msg_receipt.exit_code,
+        method,
+        match &receiver_code {
+            Some(code) => receiver_kind(&executor.builtin_actor_names, code),
+            None => FvmReceiverKind::Unknown,
         },
-        msg_receipt: Receipt {
-            exit_code: ExitCode::OK,
-            return_data: RawBytes::default(),
-            gas_used: 0,
-        },
-        error: String::new(),
-        subcalls: vec![],
+    );
+    let (receiver_kind, method_name) = match &receiver_code {
+        Some(code) if options.receiver_info => (
+            receiver_kind(&executor.builtin_actor_names, code),
+            builtin_method_name(&executor.builtin_actor_names, code, method),
+        ),
+        _ => (FvmReceiverKind::Unknown, None),
+    };
+    let params_codec = params_codec(method, method_name);
+    let created_actor_id = if options.receiver_info {
+        created_actor_id(machine.0.state_tree(), &to, pre_receiver_id)?
+    } else {
+        None
+    };
+    let receiver_state = if options.receiver_state {
+        actor_state_block(machine.0.blockstore(), machine.0.state_tree(), &to)?
+    } else {
+        None
+    };
+    let invoked_methods = if options.invoked_methods && !apply_ret.exec_trace.is_empty() {
+        let methods = to_vec(&invoked_methods(
+            machine.0.state_tree(),
+            &apply_ret.exec_trace,
+        )?)?;
+        budget.charge("invoked methods", methods.len())?;
+        Some(methods.into_boxed_slice().into())
+    } else {
+        None
     };
 
-    while let Some(trace) = trace_iter.next() {
-        match trace {
-            ExecutionEvent::Call { .. } => {
-                new_trace
-                    .subcalls
-                    .push(build_lotus_trace(&trace, trace_iter)?);
-            }
-            ExecutionEvent::CallReturn(return_data) => {
-                new_trace.msg_receipt = Receipt {
-                    exit_code: ExitCode::OK,
-                    return_data,
-                    gas_used: 0,
-                };
-                return Ok(new_trace);
-            }
-            ExecutionEvent::CallAbort(exit_code) => {
-                if exit_code.is_success() {
-                    return Err(anyhow!("actor failed with status OK"));
-                }
-                new_trace.msg_receipt = Receipt {
-                    exit_code,
-                    return_data: Default::default(),
-                    gas_used: 0,
-                };
-                return Ok(new_trace);
-            }
-            ExecutionEvent::CallError(syscall_err) => {
-                // Errors indicate the message couldn't be dispatched at all
-                // (as opposed to failing during execution of the receiving actor).
-                // These errors are mapped to exit codes that persist on chain.
-                let exit_code = match syscall_err.1 {
-                    ErrorNumber::InsufficientFunds => ExitCode::SYS_INSUFFICIENT_FUNDS,
-                    ErrorNumber::NotFound => ExitCode::SYS_INVALID_RECEIVER,
-                    _ => ExitCode::SYS_ASSERTION_FAILED,
-                };
+    let num_subcalls = if executor.stream_summaries {
+        count_subcalls(&apply_ret.exec_trace)
+    } else {
+        0
+    };
+    let actors_touched = if options.actors_touched {
+        count_actors_touched(machine.0.state_tree(), from, to, &apply_ret.exec_trace)?
+    } else {
+        0
+    };
 
-                new_trace.msg_receipt = Receipt {
-                    exit_code,
-                    return_data: Default::default(),
-                    gas_used: 0,
-                };
-                return Ok(new_trace);
+    let trace_depth_limit = match options.trace_depth_limit {
+        0 => u64::MAX,
+        limit => limit,
+    };
+    let trace_truncated =
+        !executor.compact_trace && trace_depth(&apply_ret.exec_trace) > trace_depth_limit;
+    let exec_trace: Option<c_slice::Box<u8>> =
+        if executor.compact_trace && !apply_ret.exec_trace.is_empty() {
+            let trace =
+                encode_compact_trace(&apply_ret.exec_trace, |bytes| budget.charge("trace", bytes))?;
+            Some(trace.into_boxed_slice().into())
+        } else if !apply_ret.exec_trace.is_empty() {
+            let used = budget.used;
+            let mut trace_iter = apply_ret.exec_trace.into_iter();
+            let trace = build_lotus_trace(
+                &trace_iter
+                    .next()
+                    .expect("already checked trace for emptiness"),
+                &mut trace_iter,
+                trace_depth_limit,
+                &mut budget,
+            );
+            // Exceeding the budget fails the execution, while malformed traces are just dropped.
+            budget.check()?;
+            match trace
+                .ok()
+                .and_then(|t| encode_lotus_trace(t, executor.lotus_trace_format).ok())
+            {
+                Some(trace) => {
+                    budget.charge_rest("trace", used, trace.len())?;
+                    Some(trace.into_boxed_slice().into())
+                }
+                None => None,
             }
+        } else {
+            None
         };
+
+    let randomness_requests = if !randomness_requests.is_empty() {
+        let requests = to_vec(&randomness_requests)?;
+        budget.charge("randomness requests", requests.len())?;
+        Some(requests.into_boxed_slice().into())
+    } else {
+        None
+    };
+
+    let penalty_breakdown = {
+        let entries = penalty_breakdown(&apply_ret.penalty);
+        if !entries.is_empty() {
+            let entries = to_vec(&entries)?;
+            budget.charge("penalty breakdown", entries.len())?;
+            Some(entries.into_boxed_slice().into())
+        } else {
+            None
+        }
+    };
+
+    let state_proofs = state_proofs.map(|proofs| proofs.into_boxed_slice().into());
+
+    let receiver_state = match receiver_state {
+        Some(state) => {
+            budget.charge("receiver state", state.len())?;
+            Some(state.into_boxed_slice().into())
+        }
+        None => None,
+    };
+
+    let gc_candidates = gc_candidates
+        .iter()
+        .map(|k| {
+            let k = k.to_bytes();
+            budget.charge("gc candidates", k.len())?;
+            Ok(k.into_boxed_slice().into())
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?
+        .into_boxed_slice()
+        .into();
+
+    let failure_info = apply_ret
+        .failure_info
+        .map(|info| info.to_string().into_boxed_str().into());
+
+    // TODO: use the non-bigint token amount everywhere in the FVM
+    let penalty: u128 = apply_ret.penalty.try_into().unwrap();
+    let miner_tip: u128 = apply_ret.miner_tip.try_into().unwrap();
+    let base_fee_burn: u128 = apply_ret.base_fee_burn.try_into().unwrap();
+    let over_estimation_burn: u128 = apply_ret.over_estimation_burn.try_into().unwrap();
+    let refund: u128 = apply_ret.refund.try_into().unwrap();
+    let effective_base_fee: u128 = effective_base_fee.try_into().unwrap();
+    let effective_premium: u128 = effective_premium.try_into().unwrap();
+    let balance_margin_negative = balance_margin < TokenAmount::zero();
+    let balance_margin: u128 = if balance_margin_negative {
+        -balance_margin
+    } else {
+        balance_margin
     }
+    .try_into()
+    .unwrap();
+    let gas_refund = apply_ret.gas_refund;
+    let gas_burned = apply_ret.gas_burned;
 
-    Err(anyhow!("should have returned on an ExecutionEvent:Return"))
-}
+    let Receipt {
+        exit_code,
+        return_data,
+        gas_used,
+    } = apply_ret.msg_receipt;
 
-#[cfg(test)]
-mod test {
-    use crate::fvm::machine::build_lotus_trace;
-    use fvm::kernel::SyscallError;
-    use fvm::trace::ExecutionEvent;
-    use fvm_ipld_encoding::RawBytes;
-    use fvm_shared::address::Address;
-    use fvm_shared::econ::TokenAmount;
-    use fvm_shared::error::ErrorNumber::IllegalArgument;
-    use fvm_shared::ActorID;
+    let return_len = return_data.len() as u64;
+    let (return_data, return_truncated) =
+        cap_return_data(return_data.into(), executor.max_return_size);
+    budget.charge("return data", return_data.len())?;
+    let return_val = if return_data.is_empty() {
+        None
+    } else {
+        Some(return_data.into_boxed_slice().into())
+    };
+
+    // TODO: Do something with the backtrace.
+    let resp = FvmMachineExecuteResponse {
+        exec_id: next_exec_id(),
+        exit_code: exit_code.value() as u64,
+        exit_code_class: exit_code_class(exit_code),
+        return_val,
+        return_truncated,
+        return_len,
+        receiver_kind,
+        actor_created: created_actor_id.is_some(),
+        created_actor_id: created_actor_id.unwrap_or_default(),
+        method_name: method_name.map(|name| name.to_owned().into_boxed_str().into()),
+        params_len,
+        params_codec,
+        params_decode_failed,
+        actors_touched,
+        gas_used: gas_used as u64,
+        direct_gas: (gas_used as u64).saturating_sub(subcall_gas),
+        subcall_gas,
+        lock_wait_ns: u64::try_from(lock_wait.as_nanos()).unwrap_or(u64::MAX),
+        penalty_hi: (penalty >> u64::BITS) as u64,
+        penalty_lo: penalty as u64,
+        miner_tip_hi: (miner_tip >> u64::BITS) as u64,
+        miner_tip_lo: miner_tip as u64,
+        base_fee_burn_hi: (base_fee_burn >> u64::BITS) as u64,
+        base_fee_burn_lo: base_fee_burn as u64,
+        over_estimation_burn_hi: (over_estimation_burn >> u64::BITS) as u64,
+        over_estimation_burn_lo: over_estimation_burn as u64,
+        refund_hi: (refund >> u64::BITS) as u64,
+        refund_lo: refund as u64,
+        effective_base_fee_hi: (effective_base_fee >> u64::BITS) as u64,
+        effective_base_fee_lo: effective_base_fee as u64,
+        effective_premium_hi: (effective_premium >> u64::BITS) as u64,
+        effective_premium_lo: effective_premium as u64,
+        balance_margin_negative,
+        balance_margin_hi: (balance_margin >> u64::BITS) as u64,
+        balance_margin_lo: balance_margin as u64,
+        gas_refund,
+        gas_burned,
+        exec_trace,
+        trace_truncated,
+        failure_info,
+        randomness_requests,
+        penalty_breakdown,
+        pre_state_root: pre_state_root.map(|root| root.to_bytes().into_boxed_slice().into()),
+        post_state_root: post_state_root.map(|root| root.to_bytes().into_boxed_slice().into()),
+        state_proofs,
+        receiver_state,
+        gc_candidates,
+        invoked_methods,
+    };
+    executor.record_execution(to, method, &resp);
+    if executor.stream_summaries {
+        machine.0.externs().report_execution(
+            resp.exec_id,
+            resp.exit_code,
+            resp.gas_used,
+            num_subcalls,
+        )?;
+    }
+    if options.strict {
+        check_receipt_ok(&resp)?;
+    }
+
+    Ok(resp)
+}
+
+/// Applies the explicit messages (with their length on chain) to the executor's already locked
+/// machine with `apply_message`, the first one being charged the wait for the lock. Under
+/// `all_or_nothing`, the batch is applied in a state tree transaction, which is reverted as soon as
+/// a message doesn't succeed or fails the call.
+fn apply_batch<B, E>(
+    executor: &InnerFvmMachine,
+    machine: &mut MachineExecutor<B, E>,
+    messages: Vec<(Message, u64)>,
+    lock_wait: Duration,
+    options: FvmBatchOptions,
+) -> anyhow::Result<FvmMachineExecuteBatchResponse>
+where
+    B: Blockstore + 'static,
+    E: Externs + ExecutionExterns + 'static,
+    MachineExecutor<B, E>: Executor,
+{
+    let first_new_id = if options.created_actors {
+        Some(next_actor_id(machine.0.state_tree())?)
+    } else {
+        None
+    };
+
+    if options.all_or_nothing {
+        let execute = &options.execute;
+        if execute.state_roots || execute.state_proofs || execute.gc_candidates != 0 {
+            bail!("state roots can't be computed in an all-or-nothing batch");
+        }
+        machine.0.state_tree_mut().begin_transaction();
+    }
+    let mut results = Vec::with_capacity(messages.len());
+    let mut receiver_gas = BTreeMap::new();
+    let apply_all = |machine: &mut MachineExecutor<B, E>,
+                     results: &mut Vec<FvmMachineExecuteResponse>|
+     -> anyhow::Result<Option<usize>> {
+        let mut lock_wait = lock_wait;
+        for (index, (message, chain_len)) in messages.into_iter().enumerate() {
+            let to = message.to;
+            let resp = apply_message(
+                executor,
+                machine,
+                message,
+                ApplyKind::Explicit,
+                chain_len,
+                std::mem::take(&mut lock_wait),
+                options.execute,
+            )?;
+            if options.receiver_gas {
+                let receiver = machine
+                    .0
+                    .state_tree()
+                    .lookup_id(&to)?
+                    .unwrap_or(UNRESOLVED_ID);
+                *receiver_gas.entry(receiver).or_insert(0) += resp.gas_used;
+            }
+            let failed = resp.exit_code != ExitCode::OK.value() as u64;
+            results.push(resp);
+            if failed && options.all_or_nothing {
+                return Ok(Some(index));
+            }
+        }
+        Ok(None)
+    };
+    let failed_index = apply_all(machine, &mut results);
+    if options.all_or_nothing {
+        let revert = !matches!(failed_index, Ok(None));
+        machine.0.state_tree_mut().end_transaction(revert)?;
+    }
+    let failed_index = failed_index?;
+
+    let created_actors = match first_new_id {
+        Some(first_new_id) => created_actors(machine.0.state_tree(), first_new_id)?,
+        None => Vec::new(),
+    };
+    Ok(FvmMachineExecuteBatchResponse {
+        results: results.into_boxed_slice().into(),
+        created_actors: created_actors.into_boxed_slice().into(),
+        failed: failed_index.is_some(),
+        failed_index: failed_index.unwrap_or_default() as u64,
+        receiver_gas: receiver_gas
+            .into_iter()
+            .map(|(receiver_id, gas_used)| FvmReceiverGas {
+                receiver_id,
+                gas_used,
+            })
+            .collect::<Vec<_>>()
+            .into_boxed_slice()
+            .into(),
+    })
+}
+
+/// Applies the message in a state tree transaction, which is reverted if the execution fails or
+/// `check` rejects its result.
+fn execute_checked<K: Kernel>(
+    executor: &mut ThreadedExecutor<DefaultExecutor<K>>,
+    message: Message,
+    apply_kind: ApplyKind,
+    chain_len: usize,
+    check: impl FnOnce(&ApplyRet) -> anyhow::Result<()>,
+) -> anyhow::Result<ApplyRet>
+where
+    ThreadedExecutor<DefaultExecutor<K>>: Executor,
+{
+    executor.0.state_tree_mut().begin_transaction();
+    let result = executor
+        .execute_message(message, apply_kind, chain_len)
+        .and_then(|ret| check(&ret).map(|()| ret));
+    executor
+        .0
+        .state_tree_mut()
+        .end_transaction(result.is_err())?;
+    result
+}
+
+/// The actors a message touched, and the CIDs of the blocks it read and written.
+type AccessList = (Vec<Address>, Vec<Cid>, Vec<Cid>);
+
+/// Applies an explicit message and flushes the machine with `flush`, recording the blocks read
+/// and written meanwhile in `access_log`, which must be the log of the machine's blockstore.
+fn execute_with_access_list<K: Kernel>(
+    executor: &mut ThreadedExecutor<DefaultExecutor<K>>,
+    access_log: &AccessLog,
+    message: Message,
+    chain_len: usize,
+    flush: impl FnOnce(&mut ThreadedExecutor<DefaultExecutor<K>>) -> anyhow::Result<Cid>,
+) -> anyhow::Result<(ApplyRet, Cid, AccessList)>
+where
+    ThreadedExecutor<DefaultExecutor<K>>: Executor,
+{
+    let (from, to) = (message.from, message.to);
+    access_log.start();
+    let flushed = executor
+        .execute_message(message, ApplyKind::Explicit, chain_len)
+        .and_then(|ret| flush(executor).map(|root| (ret, root)));
+    let (reads, writes) = access_log.stop();
+    let (apply_ret, state_root) = flushed?;
+    let actors = touched_actors(executor.0.state_tree(), from, to, &apply_ret.exec_trace)?;
+    Ok((apply_ret, state_root, (actors, reads, writes)))
+}
+
+/// Reads the manifest CID from the system actor's state (nv16+), if there is a system actor.
+fn manifest_cid_from_state<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+) -> anyhow::Result<Option<Cid>> {
+    let actor = match state_tree.get_actor(&Address::new_id(SYSTEM_ACTOR_ID))? {
+        Some(actor) => actor,
+        None => return Ok(None),
+    };
+    let state = state_tree
+        .store()
+        .get(&actor.state)?
+        .ok_or_else(|| anyhow!("missing system actor state"))?;
+    let (manifest_cid,): (Cid,) = fvm_ipld_encoding::from_slice(&state)?;
+
+    Ok(Some(manifest_cid))
+}
+
+/// Reads the sequence of the actor at the address, if there is one.
+fn actor_nonce<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    address: &Address,
+) -> anyhow::Result<Option<u64>> {
+    Ok(state_tree.get_actor(address)?.map(|actor| actor.sequence))
+}
+
+/// Loads the names of the builtin actors in the (version 1) manifest, by code CID.
+pub(super) fn builtin_actor_names<BS: Blockstore>(
+    bs: &BS,
+    manifest: &Cid,
+) -> anyhow::Result<HashMap<Cid, String>> {
+    let block = bs
+        .get(manifest)?
+        .ok_or_else(|| anyhow!("missing manifest {}", manifest))?;
+    let (version, data): (u32, Cid) = fvm_ipld_encoding::from_slice(&block)?;
+    if version != 1 {
+        bail!("unsupported manifest version {}", version);
+    }
+    let block = bs
+        .get(&data)?
+        .ok_or_else(|| anyhow!("missing manifest data {}", data))?;
+    let entries: Vec<(String, Cid)> = fvm_ipld_encoding::from_slice(&block)?;
+    Ok(entries
+        .into_iter()
+        .map(|(name, code)| (code, name))
+        .collect())
+}
+
+/// Classifies an actor by looking its code up in the manifest.
+fn receiver_kind(builtin_actor_names: &HashMap<Cid, String>, code: &Cid) -> FvmReceiverKind {
+    match builtin_actor_names.get(code).map(String::as_str) {
+        None => FvmReceiverKind::Unknown,
+        Some("account") => FvmReceiverKind::Account,
+        Some("placeholder") => FvmReceiverKind::Placeholder,
+        Some("evm") => FvmReceiverKind::Evm,
+        Some(_) => FvmReceiverKind::Builtin,
+    }
+}
+
+/// Looks up the name of a method of a builtin actor, by the actor's code.
+fn builtin_method_name(
+    builtin_actor_names: &HashMap<Cid, String>,
+    code: &Cid,
+    method: MethodNum,
+) -> Option<&'static str> {
+    builtin_actor_names
+        .get(code)
+        .and_then(|name| method_name(name, method))
+}
+
+/// Returns the actors whose state a message may have changed: its sender and receiver, the reward
+/// and burnt funds actors if it paid for gas, and the participants of the traced calls.
+fn state_proof_candidates(
+    from: Address,
+    to: Address,
+    pays_gas: bool,
+    exec_trace: &[ExecutionEvent],
+) -> Vec<Address> {
+    const REWARD_ACTOR_ID: ActorID = 2;
+    const BURNT_FUNDS_ACTOR_ID: ActorID = 99;
+
+    let mut candidates = vec![from, to];
+    if pays_gas {
+        candidates.push(Address::new_id(REWARD_ACTOR_ID));
+        candidates.push(Address::new_id(BURNT_FUNDS_ACTOR_ID));
+    }
+    for event in exec_trace {
+        if let ExecutionEvent::Call { from, to, .. } = event {
+            candidates.push(Address::new_id(*from));
+            candidates.push(*to);
+        }
+    }
+    candidates
+}
+
+/// Counts the distinct actors of [`touched_actors`].
+fn count_actors_touched<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    from: Address,
+    to: Address,
+    exec_trace: &[ExecutionEvent],
+) -> anyhow::Result<u64> {
+    Ok(touched_actors(state_tree, from, to, exec_trace)?.len() as u64)
+}
+
+/// Returns the distinct actors among the sender, the receiver, and the participants of the traced
+/// calls, in the order they were first found. Addresses are resolved to IDs where possible, so that
+/// an actor isn't listed once per address it's called by. The reward and burnt funds actors paid by
+/// every message aren't listed.
+fn touched_actors<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    from: Address,
+    to: Address,
+    exec_trace: &[ExecutionEvent],
+) -> anyhow::Result<Vec<Address>> {
+    let mut touched = Vec::new();
+    let mut seen = HashSet::new();
+    for address in state_proof_candidates(from, to, false, exec_trace) {
+        let address = match state_tree.lookup_id(&address)? {
+            Some(id) => Address::new_id(id),
+            None => address,
+        };
+        if seen.insert(address) {
+            touched.push(address);
+        }
+    }
+    Ok(touched)
+}
+
+/// Returns the distinct (code, method) pairs invoked in a traced message, in the order they were
+/// first invoked. Codes are looked up in the state after the message, so invocations of actors that
+/// don't exist (anymore) are left out.
+fn invoked_methods<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    exec_trace: &[ExecutionEvent],
+) -> anyhow::Result<Vec<(Cid, MethodNum)>> {
+    let mut invoked = Vec::new();
+    let mut seen = HashSet::new();
+    for event in exec_trace {
+        if let ExecutionEvent::Call { to, method, .. } = event {
+            if let Some(actor) = state_tree.get_actor(to)? {
+                if seen.insert((actor.code, *method)) {
+                    invoked.push((actor.code, *method));
+                }
+            }
+        }
+    }
+    Ok(invoked)
+}
+
+/// Reads the state block of the actor at the address, if there's such an actor.
+fn actor_state_block<BS: Blockstore, S: Blockstore>(
+    bs: &BS,
+    state_tree: &StateTree<S>,
+    address: &Address,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    match state_tree.get_actor(address)? {
+        Some(actor) => match bs.get(&actor.state)? {
+            Some(block) => Ok(Some(block)),
+            None => bail!("missing state {} of actor {}", actor.state, address),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Returns the ID assigned to the receiver address by applying a message, if the address didn't
+/// resolve to an ID (`pre_id`) beforehand. IDs are handed out sequentially by the init actor, so
+/// the same message applied to the same state always creates the same ID.
+fn created_actor_id<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    to: &Address,
+    pre_id: Option<ActorID>,
+) -> anyhow::Result<Option<ActorID>> {
+    match pre_id {
+        Some(_) => Ok(None),
+        None => Ok(state_tree.lookup_id(to)?),
+    }
+}
+
+/// Reads the ID the init actor will assign to the next actor created.
+fn next_actor_id<BS: Blockstore>(state_tree: &StateTree<BS>) -> anyhow::Result<ActorID> {
+    let actor = state_tree
+        .get_actor(&Address::new_id(INIT_ACTOR_ID))?
+        .ok_or_else(|| anyhow!("missing init actor"))?;
+    let state = state_tree
+        .store()
+        .get(&actor.state)?
+        .ok_or_else(|| anyhow!("missing init actor state"))?;
+    let (_, next_id, _): (Cid, ActorID, String) = fvm_ipld_encoding::from_slice(&state)?;
+    Ok(next_id)
+}
+
+/// Diffs the actors in the state tree against those there were when the init actor's next ID was
+/// `first_new_id`, returning the IDs of the new actors in increasing order. Every actor gets its ID
+/// from the init actor, so the new actors are those with IDs assigned since, less the ones deleted
+/// since.
+fn created_actors<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    first_new_id: ActorID,
+) -> anyhow::Result<Vec<ActorID>> {
+    let mut created = Vec::new();
+    for id in first_new_id..next_actor_id(state_tree)? {
+        if state_tree.get_actor(&Address::new_id(id))?.is_some() {
+            created.push(id);
+        }
+    }
+    Ok(created)
+}
+
+/// Tracks the bytes captured in an execution response against a budget. Buffers are charged as
+/// they're built, so building them stops as soon as the budget is exceeded. The message has already
+/// been applied by the time the budget is charged, exceeding it only keeps the response from
+/// being returned.
+struct AllocationBudget {
+    limit: u64,
+    used: u64,
+    /// What was being captured when the budget was first exceeded.
+    exceeded: Option<&'static str>,
+}
+
+impl AllocationBudget {
+    /// Creates a budget of `limit` bytes. Zero means unlimited.
+    fn new(limit: u64) -> Self {
+        AllocationBudget {
+            limit,
+            used: 0,
+            exceeded: None,
+        }
+    }
+
+    fn charge(&mut self, what: &'static str, bytes: usize) -> anyhow::Result<()> {
+        self.used = self.used.saturating_add(bytes as u64);
+        if self.limit != 0 && self.used > self.limit && self.exceeded.is_none() {
+            self.exceeded = Some(what);
+        }
+        self.check()
+    }
+
+    /// Charges the rest of a buffer of `bytes` bytes, some of which were charged while building it
+    /// (when the budget had `used` bytes).
+    fn charge_rest(&mut self, what: &'static str, used: u64, bytes: usize) -> anyhow::Result<()> {
+        let charged = self.used - used;
+        self.charge(what, (bytes as u64).saturating_sub(charged) as usize)
+    }
+
+    /// Fails if the budget was exceeded.
+    fn check(&self) -> anyhow::Result<()> {
+        match self.exceeded {
+            Some(what) => bail!(
+                "execution exceeded the allocation budget of {} bytes while capturing the {}",
+                self.limit,
+                what
+            ),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Truncates the return data to `max_size` bytes (if non-zero), returning whether it was truncated.
+fn cap_return_data(mut data: Vec<u8>, max_size: u64) -> (Vec<u8>, bool) {
+    if max_size == 0 || data.len() as u64 <= max_size {
+        return (data, false);
+    }
+    data.truncate(max_size as usize);
+    data.shrink_to_fit();
+    (data, true)
+}
+
+/// Computes the current state root by flushing the state tree into the machine's (buffered)
+/// blockstore.
+fn state_root<BS: Blockstore>(state_tree: &mut StateTree<BS>) -> anyhow::Result<Cid> {
+    state_tree
+        .flush()
+        .map_err(|e| anyhow!("failed to compute the state root: {}", e))
+}
+
+/// Flushes the machine's state, returning the new state root. Flushing is skipped (and the previous
+/// root returned) if the machine hasn't been modified since the last flush. Fails if the machine
+/// is read-only.
+#[ffi_export]
+fn fvm_machine_flush(
+    executor: &'_ InnerFvmMachine,
+) -> repr_c::Box<Result<FvmMachineFlushResponse>> {
+    catch_panic_response("fvm_machine_flush", || {
+        executor.check_writable()?;
+        let (cid, was_noop) = flush_machine(executor, &mut executor.lock())?;
+        Ok(FvmMachineFlushResponse {
+            state_root: cid.to_bytes().into_boxed_slice().into(),
+            was_noop,
+        })
+    })
+}
+
+/// Flushes the executor's already locked machine, returning the new state root and whether the
+/// flush was skipped because nothing changed since the last one.
+fn flush_machine(
+    executor: &InnerFvmMachine,
+    machine: &mut CgoExecutor,
+) -> anyhow::Result<(Cid, bool)> {
+    let mut flushed_root = executor.flushed_root.lock().unwrap();
+    logged_flush(&executor.write_log, &mut flushed_root, || machine.0.flush())
+}
+
+/// Flushes with `flush`, recording its writes in the write log, unless `flushed_root` holds the
+/// root of a previous flush with nothing changed since. A skipped flush keeps the log of the flush
+/// that produced the root, so the log always holds the blocks written for the returned root.
+fn logged_flush(
+    write_log: &WriteLog,
+    flushed_root: &mut Option<Cid>,
+    flush: impl FnOnce() -> anyhow::Result<Cid>,
+) -> anyhow::Result<(Cid, bool)> {
+    if let Some(cid) = *flushed_root {
+        return Ok((cid, true));
+    }
+    write_log.start();
+    let flushed = flush();
+    write_log.stop();
+    let cid = flushed?;
+    *flushed_root = Some(cid);
+    Ok((cid, false))
+}
+
+/// Returns the CBOR encoded list of the `(cid, block)` pairs the last flush wrote to the caller's
+/// blockstore, in order. A skipped flush keeps the writes of the flush that produced its root. Fails
+/// unless the machine was created with a flush write log, or if the
+/// blocks exceeded its size.
+#[ffi_export]
+fn fvm_machine_last_flush_writes(
+    executor: &'_ InnerFvmMachine,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_machine_last_flush_writes", || {
+        // Don't read the log in the middle of a flush.
+        let _machine = executor.lock();
+        let writes: Vec<_> = executor
+            .write_log
+            .writes()?
+            .into_iter()
+            .map(|(k, block)| (k, RawBytes::new(block)))
+            .collect();
+        Ok(to_vec(&writes)?.into_boxed_slice().into())
+    })
+}
+
+/// Computes the number and total size of the blocks reachable from `root` in the machine's
+/// blockstore (including blocks written, but not yet flushed, by the machine).
+#[ffi_export]
+fn fvm_machine_state_size(
+    executor: &'_ InnerFvmMachine,
+    root: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmMachineStateSizeResponse>> {
+    catch_panic_response("fvm_machine_state_size", || {
+        let root = Cid::try_from(&root[..]).map_err(|err| anyhow!("invalid root: {}", err))?;
+
+        let machine = executor.lock();
+
+        let mut size = FvmMachineStateSizeResponse::default();
+        let bs = BatchedBlockstore::new(machine.0.blockstore(), &executor.read_batch);
+        walk_dag(&bs, root, MAX_DAG_DEPTH, |_, block| {
+            size.blocks += 1;
+            size.bytes += block.len() as u64;
+            Ok(())
+        })?;
+
+        Ok(size)
+    })
+}
+
+/// Checks that the state rooted at `root` in the machine's blockstore (including blocks written,
+/// but not yet flushed, by the machine) survives an export to CAR and a reimport unchanged.
+#[ffi_export]
+fn fvm_machine_verify_car_roundtrip(
+    executor: &'_ InnerFvmMachine,
+    root: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<bool>> {
+    catch_panic_response("fvm_machine_verify_car_roundtrip", || {
+        let root = Cid::try_from(&root[..]).map_err(|err| anyhow!("invalid root: {}", err))?;
+
+        let machine = executor.lock();
+        let bs = BatchedBlockstore::new(machine.0.blockstore(), &executor.read_batch);
+        car_roundtrip(&bs, root)
+    })
+}
+
+/// Dumps every actor in the state tree rooted at `root` in the machine's blockstore (including
+/// blocks written, but not yet flushed, by the machine) as JSON, leaving the machine's state tree
+/// untouched. Pass the root returned by a flush to dump the machine's current state. Fails if the
+/// dump would exceed 64MiB.
+#[ffi_export]
+fn fvm_machine_dump_state_json(
+    executor: &'_ InnerFvmMachine,
+    root: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_machine_dump_state_json", || {
+        let root = Cid::try_from(&root[..]).map_err(|err| anyhow!("invalid root: {}", err))?;
+
+        let machine = executor.lock();
+        let state_tree = StateTree::new_from_root(machine.0.blockstore(), &root)?;
+        let json = dump_state_json(
+            &state_tree,
+            &root,
+            &executor.builtin_actor_names,
+            MAX_STATE_DUMP_SIZE,
+        )?;
+
+        Ok(json.into_boxed_slice().into())
+    })
+}
+
+/// Reads a block from the machine's blockstore, including blocks written by messages that haven't
+/// been flushed yet and the machine's in-memory overlay.
+#[ffi_export]
+fn fvm_machine_get_block(
+    executor: &'_ InnerFvmMachine,
+    cid: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmMachineGetBlockResponse>> {
+    catch_panic_response("fvm_machine_get_block", || {
+        let cid = Cid::try_from(&cid[..]).map_err(|err| anyhow!("invalid cid: {}", err))?;
+        get_block(executor.lock().0.blockstore(), &cid)
+    })
+}
+
+fn get_block<BS: Blockstore>(bs: &BS, cid: &Cid) -> anyhow::Result<FvmMachineGetBlockResponse> {
+    Ok(match bs.get(cid)? {
+        Some(block) => FvmMachineGetBlockResponse {
+            found: true,
+            block: block.into_boxed_slice().into(),
+        },
+        None => FvmMachineGetBlockResponse::default(),
+    })
+}
+
+/// Builds the AMT of the receipts of a batch of messages (in message order) in the caller's
+/// blockstore, through the machine's read cache and write log, returning its root CID as
+/// referenced by block headers. Fails if the machine is read-only.
+#[ffi_export]
+fn fvm_machine_compute_receipts_root(
+    executor: &'_ InnerFvmMachine,
+    exit_codes: c_slice::Ref<u64>,
+    return_values: c_slice::Ref<c_slice::Box<u8>>,
+    gas_used: c_slice::Ref<u64>,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_machine_compute_receipts_root", || {
+        executor.check_writable()?;
+        if exit_codes.len() != return_values.len() || exit_codes.len() != gas_used.len() {
+            bail!(
+                "got {} exit codes, {} return values, and {} gas used values",
+                exit_codes.len(),
+                return_values.len(),
+                gas_used.len()
+            );
+        }
+        let receipts = exit_codes
+            .iter()
+            .zip(return_values.iter())
+            .zip(gas_used.iter())
+            .map(|((&exit_code, return_data), &gas_used)| {
+                Ok(Receipt {
+                    exit_code: ExitCode::new(
+                        u32::try_from(exit_code)
+                            .map_err(|_| anyhow!("exit code out of range: {}", exit_code))?,
+                    ),
+                    return_data: RawBytes::new(return_data.to_vec()),
+                    gas_used: i64::try_from(gas_used)
+                        .map_err(|_| anyhow!("gas used out of range: {}", gas_used))?,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let root = receipts_root(&executor.base_blockstore(), &receipts)?;
+        Ok(root.to_bytes().into_boxed_slice().into())
+    })
+}
+
+/// Returns the sequence (nonce) of the actor at the address in the machine's current state, if
+/// there is one.
+#[ffi_export]
+fn fvm_machine_actor_nonce(
+    executor: &'_ InnerFvmMachine,
+    address: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmMachineActorNonceResponse>> {
+    catch_panic_response("fvm_machine_actor_nonce", || {
+        let address = Address::from_bytes(&address)?;
+        Ok(
+            match actor_nonce(executor.lock().0.state_tree(), &address)? {
+                Some(nonce) => FvmMachineActorNonceResponse { found: true, nonce },
+                None => FvmMachineActorNonceResponse::default(),
+            },
+        )
+    })
+}
+
+/// The ID `fvm_machine_resolve_addresses` returns for addresses that don't resolve.
+const UNRESOLVED_ID: ActorID = ActorID::MAX;
+
+/// Resolves the addresses to actor IDs under a single lock, returning the IDs in the same order.
+/// Addresses that don't resolve are reported as `u64::MAX`, ID addresses resolve to their ID.
+#[ffi_export]
+fn fvm_machine_resolve_addresses(
+    executor: &'_ InnerFvmMachine,
+    addresses: c_slice::Ref<c_slice::Box<u8>>,
+) -> repr_c::Box<Result<c_slice::Box<u64>>> {
+    catch_panic_response("fvm_machine_resolve_addresses", || {
+        let addresses = addresses
+            .iter()
+            .map(|address| Address::from_bytes(address))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let ids = resolve_addresses(executor.lock().0.state_tree(), &addresses)?;
+        Ok(ids.into_boxed_slice().into())
+    })
+}
+
+fn resolve_addresses<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    addresses: &[Address],
+) -> anyhow::Result<Vec<ActorID>> {
+    addresses
+        .iter()
+        .map(|address| Ok(state_tree.lookup_id(address)?.unwrap_or(UNRESOLVED_ID)))
+        .collect()
+}
+
+/// Returns the gas charged for including a message whose canonical encoding is `size` bytes long
+/// (see `fvm_message_size`) on chain, under the machine's network version. This is part of the gas
+/// used by every explicit message.
+#[ffi_export]
+fn fvm_machine_inclusion_gas_for_size(
+    executor: &'_ InnerFvmMachine,
+    size: u64,
+) -> repr_c::Box<Result<u64>> {
+    catch_panic_response("fvm_machine_inclusion_gas_for_size", || {
+        let network_version = executor.lock().0.context().network_version;
+        inclusion_gas(network_version, size)
+    })
+}
+
+fn inclusion_gas(network_version: NetworkVersion, size: u64) -> anyhow::Result<u64> {
+    let charge =
+        price_list_by_network_version(network_version).on_chain_message(usize::try_from(size)?);
+    Ok(u64::try_from(charge.total())?)
+}
+
+/// Key parameters of a network version's gas price list, as the total (compute and storage) gas of
+/// the charges they make up.
+#[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+struct GasParams {
+    /// Including an empty message on chain.
+    pub message_base: i64,
+    /// Each additional byte of a message included on chain.
+    pub message_per_byte: i64,
+    /// Each byte of return data stored on chain.
+    pub return_value_per_byte: i64,
+    /// Sending a message without value to the send method.
+    pub send_base: i64,
+    /// The surcharge for invoking any other method.
+    pub send_invoke_method: i64,
+    /// The surcharge for transferring value along with a method invocation.
+    pub send_transfer_funds: i64,
+    pub create_actor: i64,
+    pub delete_actor: i64,
+    /// Every syscall.
+    pub syscall: i64,
+    pub verify_bls_signature: i64,
+    pub verify_secp256k1_signature: i64,
+    /// Opening a block, excluding its size.
+    pub block_open_base: i64,
+    /// Creating a block, excluding its size.
+    pub block_create_base: i64,
+}
+
+/// Returns the CBOR encoded key parameters of the gas price list for the network version, see
+/// `GasParams` for the fields.
+#[ffi_export]
+fn fvm_gas_params(network_version: u64) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_gas_params", || {
+        let network_version = NetworkVersion::try_from(network_version as u32)
+            .map_err(|_| anyhow!("unsupported network version: {}", network_version))?;
+        Ok(to_vec(&gas_params(network_version))?
+            .into_boxed_slice()
+            .into())
+    })
+}
+
+fn gas_params(network_version: NetworkVersion) -> GasParams {
+    let prices = price_list_by_network_version(network_version);
+    let zero = TokenAmount::zero();
+    let invocation = |value, method| prices.on_method_invocation(value, method).total();
+    GasParams {
+        message_base: prices.on_chain_message(0).total(),
+        message_per_byte: prices.on_chain_message(1).total() - prices.on_chain_message(0).total(),
+        return_value_per_byte: prices.on_chain_return_value(1).total()
+            - prices.on_chain_return_value(0).total(),
+        send_base: invocation(&zero, METHOD_SEND),
+        send_invoke_method: invocation(&zero, 2) - invocation(&zero, METHOD_SEND),
+        send_transfer_funds: invocation(&TokenAmount::from(1u8), 2) - invocation(&zero, 2),
+        create_actor: prices.on_create_actor().total(),
+        delete_actor: prices.on_delete_actor().total(),
+        syscall: prices.on_syscall().total(),
+        verify_bls_signature: prices.on_verify_signature(SignatureType::BLS).total(),
+        verify_secp256k1_signature: prices.on_verify_signature(SignatureType::Secp256k1).total(),
+        block_open_base: prices.on_block_open_base().total(),
+        block_create_base: prices.on_block_create(0).total(),
+    }
+}
+
+/// Returns the CID of the actor manifest the machine is using, resolving it from the system actor's
+/// state if it wasn't passed explicitly (or loaded from the builtin bundles). Returns an empty slice
+/// if the machine has no manifest.
+#[ffi_export]
+fn fvm_machine_manifest_cid(
+    executor: &'_ InnerFvmMachine,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_machine_manifest_cid", || {
+        Ok(executor
+            .effective_manifest_cid()?
+            .map(|cid| cid.to_bytes())
+            .unwrap_or_default()
+            .into_boxed_slice()
+            .into())
+    })
+}
+
+/// Returns whether the machine is using the expected actor manifest, e.g. to check that the
+/// actors it runs are the ones the operator expects. A machine without a manifest matches nothing.
+#[ffi_export]
+fn fvm_machine_verify_manifest(
+    executor: &'_ InnerFvmMachine,
+    expected: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<bool>> {
+    catch_panic_response("fvm_machine_verify_manifest", || {
+        let expected =
+            Cid::try_from(&expected[..]).map_err(|err| anyhow!("invalid manifest: {}", err))?;
+        Ok(executor.effective_manifest_cid()? == Some(expected))
+    })
+}
+
+/// Returns the CBOR encoded list of `(exec_id, to, method, exit_code, gas_used, lock_wait_ns)`
+/// summaries of the most recently applied messages, oldest first. Empty unless the machine was
+/// created with a non-zero `recent_executions` capacity.
+#[ffi_export]
+fn fvm_machine_recent_executions(
+    executor: &'_ InnerFvmMachine,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_machine_recent_executions", || {
+        let entries = executor.recent_executions.lock().unwrap().entries();
+        Ok(to_vec(&entries)?.into_boxed_slice().into())
+    })
+}
+
+/// Loads the top of the machine's initial state tree (the state root, the actors HAMT root, and the
+/// first levels of HAMT nodes) into the machine's read cache, so that executing messages doesn't
+/// need to fetch them from the client's blockstore. Returns the number of blocks loaded. The cache
+/// is bounded, so only the most recently loaded blocks remain if they don't all fit. Fails if the
+/// machine was created without a read cache.
+#[ffi_export]
+fn fvm_machine_prefetch_state(executor: &'_ InnerFvmMachine) -> repr_c::Box<Result<u64>> {
+    catch_panic_response("fvm_machine_prefetch_state", || {
+        if executor.block_cache.capacity() == 0 {
+            bail!("the machine has no read cache to prefetch into");
+        }
+        let machine = executor.lock();
+        let root = machine.0.context().initial_state_root;
+
+        let mut blocks = 0;
+        let bs = BatchedBlockstore::new(machine.0.blockstore(), &executor.read_batch);
+        walk_dag_prefix(&bs, root, PREFETCH_DEPTH, |k, block| {
+            executor.block_cache.insert(*k, block.to_vec());
+            blocks += 1;
+            Ok(())
+        })?;
+        Ok(blocks)
+    })
+}
+
+/// Compares two CBOR encoded Lotus traces depth-first, returning the path to the first frame in
+/// which they differ, along with the first differing field of that frame.
+#[ffi_export]
+fn fvm_compare_traces(
+    a: c_slice::Ref<u8>,
+    b: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmCompareTracesResponse>> {
+    catch_panic_response("fvm_compare_traces", || {
+        let a: LotusTrace = fvm_ipld_encoding::from_slice(&a)?;
+        let b: LotusTrace = fvm_ipld_encoding::from_slice(&b)?;
+
+        let mut path = Vec::new();
+        let field = compare_traces(&a, &b, &mut path)?;
+        Ok(FvmCompareTracesResponse {
+            path: path.into_boxed_slice().into(),
+            field,
+        })
+    })
+}
+
+/// Finds the frames of a CBOR encoded Lotus trace whose receiver already received one of the
+/// frames leading to it, i.e. actors re-entered within a single call chain. Returns the CBOR
+/// encoded list of `(address, path)` entries, one per re-entering frame, where `path` lists the
+/// receivers from the root down to that frame. Addresses are compared as they appear in the
+/// trace.
+#[ffi_export]
+fn fvm_trace_detect_reentrancy(trace: c_slice::Ref<u8>) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_trace_detect_reentrancy", || {
+        let trace: LotusTrace = fvm_ipld_encoding::from_slice(&trace)?;
+
+        let mut reentries = Vec::new();
+        detect_reentrancy(&trace, &mut Vec::new(), &mut reentries);
+        Ok(to_vec(&reentries)?.into_boxed_slice().into())
+    })
+}
+
+/// Pushes an entry onto `reentries` for every frame of the trace whose receiver is in `path`, or
+/// in the path from the trace's root to the frame.
+fn detect_reentrancy(
+    trace: &LotusTrace,
+    path: &mut Vec<Address>,
+    reentries: &mut Vec<(Address, Vec<Address>)>,
+) {
+    let receiver = trace.msg.to;
+    let reentered = path.contains(&receiver);
+    path.push(receiver);
+    if reentered {
+        reentries.push((receiver, path.clone()));
+    }
+    for subcall in &trace.subcalls {
+        detect_reentrancy(subcall, path, reentries);
+    }
+    path.pop();
+}
+
+/// Returns the length of the message's canonical (on-chain) CBOR encoding.
+#[ffi_export]
+fn fvm_message_size(message: c_slice::Ref<u8>) -> repr_c::Box<Result<u64>> {
+    catch_panic_response("fvm_message_size", || {
+        let message: Message = fvm_ipld_encoding::from_slice(&message)?;
+        Ok(to_vec(&message)?.len() as u64)
+    })
+}
+
+/// Builds a message from its fields, returning its canonical CBOR encoding, exactly as accepted by
+/// `fvm_machine_execute_message`. Addresses are in their byte encoding, token amounts are split
+/// into their high and low 64 bits.
+#[ffi_export]
+#[allow(clippy::too_many_arguments)]
+fn fvm_encode_message(
+    from: c_slice::Ref<u8>,
+    to: c_slice::Ref<u8>,
+    sequence: u64,
+    value_hi: u64,
+    value_lo: u64,
+    method_num: u64,
+    params: c_slice::Ref<u8>,
+    gas_limit: u64,
+    gas_fee_cap_hi: u64,
+    gas_fee_cap_lo: u64,
+    gas_premium_hi: u64,
+    gas_premium_lo: u64,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_encode_message", || {
+        let token_amount =
+            |hi: u64, lo: u64| TokenAmount::from(((hi as u128) << u64::BITS) | lo as u128);
+        let message = Message {
+            version: 0,
+            from: Address::from_bytes(&from)?,
+            to: Address::from_bytes(&to)?,
+            sequence,
+            value: token_amount(value_hi, value_lo),
+            method_num,
+            params: RawBytes::new(params.to_vec()),
+            gas_limit: i64::try_from(gas_limit)
+                .map_err(|_| anyhow!("gas limit out of range: {}", gas_limit))?,
+            gas_fee_cap: token_amount(gas_fee_cap_hi, gas_fee_cap_lo),
+            gas_premium: token_amount(gas_premium_hi, gas_premium_lo),
+        };
+        Ok(to_vec(&message)?.into_boxed_slice().into())
+    })
+}
+
+/// Returns the symbolic name of an exit code (e.g. `SYS_OUT_OF_GAS`), or an empty string if it
+/// isn't a system code or one of the common user codes, or is one of the reserved system codes.
+#[ffi_export]
+fn fvm_exit_code_name(exit_code: u64) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_exit_code_name", || {
+        let name = exit_code_name(exit_code).unwrap_or_default();
+        Ok(name.as_bytes().to_vec().into_boxed_slice().into())
+    })
+}
+
+/// Classifies the exit code by its range, system exit codes are the ones below
+/// `ExitCode::FIRST_USER_EXIT_CODE`.
+fn exit_code_class(exit_code: ExitCode) -> FvmExitCodeClass {
+    if exit_code.is_success() {
+        FvmExitCodeClass::NoError
+    } else if exit_code.is_system_error() {
+        FvmExitCodeClass::System
+    } else {
+        FvmExitCodeClass::User
+    }
+}
+
+/// The names of the exit codes defined by `fvm_shared`, by value. The reserved system codes have
+/// no name.
+fn exit_code_name(exit_code: u64) -> Option<&'static str> {
+    const NAMES: [&str; 25] = [
+        "OK",
+        "SYS_SENDER_INVALID",
+        "SYS_SENDER_STATE_INVALID",
+        "",
+        "SYS_ILLEGAL_INSTRUCTION",
+        "SYS_INVALID_RECEIVER",
+        "SYS_INSUFFICIENT_FUNDS",
+        "SYS_OUT_OF_GAS",
+        "",
+        "SYS_ILLEGAL_EXIT_CODE",
+        "SYS_ASSERTION_FAILED",
+        "SYS_MISSING_RETURN",
+        "",
+        "",
+        "",
+        "",
+        "USR_ILLEGAL_ARGUMENT",
+        "USR_NOT_FOUND",
+        "USR_FORBIDDEN",
+        "USR_INSUFFICIENT_FUNDS",
+        "USR_ILLEGAL_STATE",
+        "USR_SERIALIZATION",
+        "USR_UNHANDLED_MESSAGE",
+        "USR_UNSPECIFIED",
+        "USR_ASSERTION_FAILED",
+    ];
+    usize::try_from(exit_code)
+        .ok()
+        .and_then(|code| NAMES.get(code))
+        .copied()
+        .filter(|name| !name.is_empty())
+}
+
+destructor!(drop_fvm_machine, InnerFvmMachine);
+destructor!(
+    destroy_create_fvm_machine_response,
+    Result<FvmMachineCreateResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_execute_response,
+    Result<FvmMachineExecuteResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_flush_response,
+    Result<FvmMachineFlushResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_execute_and_flush_response,
+    Result<FvmMachineExecuteAndFlushResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_execute_for_root_response,
+    Result<FvmMachineExecuteForRootResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_execute_with_access_list_response,
+    Result<FvmMachineExecuteWithAccessListResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_execute_batch_response,
+    Result<FvmMachineExecuteBatchResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_state_size_response,
+    Result<FvmMachineStateSizeResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_dump_state_json_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_manifest_cid_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_recent_executions_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(destroy_fvm_machine_prefetch_state_response, Result<u64>);
+
+destructor!(destroy_fvm_machine_verify_manifest_response, Result<bool>);
+
+destructor!(
+    destroy_fvm_compare_traces_response,
+    Result<FvmCompareTracesResponse>
+);
+
+destructor!(
+    destroy_fvm_trace_detect_reentrancy_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_get_block_response,
+    Result<FvmMachineGetBlockResponse>
+);
+
+destructor!(destroy_fvm_message_size_response, Result<u64>);
+
+destructor!(
+    destroy_fvm_supported_network_versions_response,
+    Result<c_slice::Box<FvmSupportedNetworkVersion>>
+);
+
+destructor!(
+    destroy_fvm_encode_message_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_actor_nonce_response,
+    Result<FvmMachineActorNonceResponse>
+);
+
+destructor!(
+    destroy_fvm_machine_resolve_addresses_response,
+    Result<c_slice::Box<u64>>
+);
+
+destructor!(
+    destroy_fvm_machine_inclusion_gas_for_size_response,
+    Result<u64>
+);
+
+destructor!(destroy_fvm_gas_params_response, Result<c_slice::Box<u8>>);
+
+destructor!(
+    destroy_fvm_machine_compute_receipts_root_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_exit_code_name_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_last_flush_writes_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_machine_verify_car_roundtrip_response,
+    Result<bool>
+);
+
+destructor!(
+    destroy_fvm_empty_state_root_response,
+    Result<c_slice::Box<u8>>
+);
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+struct SignedMessage {
+    message: Message,
+    signature: Signature,
+}
+
+/// Resolves the address to the public key address of the account it refers to.
+fn resolve_key_address(machine: &CgoExecutor, addr: &Address) -> anyhow::Result<Address> {
+    if matches!(addr.protocol(), Protocol::BLS | Protocol::Secp256k1) {
+        return Ok(*addr);
+    }
+
+    let actor = machine
+        .0
+        .state_tree()
+        .get_actor(addr)?
+        .ok_or_else(|| anyhow!("sender {} not found", addr))?;
+    if !machine.0.builtin_actors().is_account_actor(&actor.code) {
+        bail!("sender {} is not an account actor", addr);
+    }
+    let state = machine
+        .0
+        .blockstore()
+        .get(&actor.state)?
+        .ok_or_else(|| anyhow!("missing state for account {}", addr))?;
+    let (key,): (Address,) = fvm_ipld_encoding::from_slice(&state)?;
+
+    Ok(key)
+}
+
+/// Verifies the message signature against the signer's public key address.
+fn verify_message_signature(
+    message: &Message,
+    signature: &Signature,
+    key: &Address,
+) -> anyhow::Result<()> {
+    let cid = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&to_vec(message)?));
+    signature
+        .verify(&cid.to_bytes(), key)
+        .map_err(|err| anyhow!("message signature verification failed: {}", err))
+}
+
+/// A part of the miner penalty charged for a message.
+#[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+struct PenaltyEntry {
+    cause: String,
+    #[serde(with = "bigint_ser")]
+    amount: TokenAmount,
+}
+
+/// Breaks the miner penalty down by cause. The FVM only reports the total, so that's the single
+/// entry of a non-zero penalty.
+fn penalty_breakdown(penalty: &TokenAmount) -> Vec<PenaltyEntry> {
+    if penalty.is_zero() {
+        return Vec::new();
+    }
+    vec![PenaltyEntry {
+        cause: "total".to_owned(),
+        amount: penalty.clone(),
+    }]
+}
+
+/// Returns the codec the receiving method expects its params in, zero if it's unknown, given the
+/// method's name if it's a known method of a builtin actor.
+///
+/// Actors don't describe their methods' params yet, but the builtin actors decode the params of all
+/// their methods as DAG-CBOR. Plain value transfers (method 0) don't take any params.
+fn params_codec(method: MethodNum, method_name: Option<&str>) -> u64 {
+    match method_name {
+        Some(_) if method != METHOD_SEND => DAG_CBOR,
+        _ => 0,
+    }
+}
+
+/// Returns whether the receiving actor aborted because it couldn't decode the message params.
+///
+/// Builtin actors abort with `USR_SERIALIZATION` when their params don't decode, and don't hit
+/// other serialization failures in practice. Plain value transfers don't decode any params, and
+/// other actors may use the exit code for anything.
+fn params_decode_failed(exit_code: ExitCode, method: MethodNum, receiver: FvmReceiverKind) -> bool {
+    exit_code == ExitCode::USR_SERIALIZATION
+        && method != METHOD_SEND
+        && receiver != FvmReceiverKind::Unknown
+}
+
+fn decode_apply_kind(apply_kind: u64) -> anyhow::Result<ApplyKind> {
+    match apply_kind {
+        0 => Ok(ApplyKind::Explicit),
+        1 => Ok(ApplyKind::Implicit),
+        _ => bail!("unknown apply kind: {}", apply_kind),
+    }
+}
+
+/// Returns the base fee and miner premium per gas unit charged to an explicit message, mirroring the
+/// FVM's gas accounting: the base fee is capped by the fee cap, and the premium by whatever's left
+/// of the fee cap after the base fee.
+fn effective_fees(
+    base_fee: &TokenAmount,
+    gas_fee_cap: &TokenAmount,
+    gas_premium: &TokenAmount,
+) -> (TokenAmount, TokenAmount) {
+    let base_fee = base_fee.min(gas_fee_cap).clone();
+    let premium = gas_premium.min(&(gas_fee_cap - &base_fee)).clone();
+    (base_fee, premium)
+}
+
+/// Returns how much of the sender's balance would be left after paying for the value and the
+/// message's maximum gas cost (gas limit times fee cap). Implicit messages don't pay for gas.
+fn balance_margin(balance: &TokenAmount, message: &Message, pays_gas: bool) -> TokenAmount {
+    let mut cost = message.value.clone();
+    if pays_gas {
+        cost += &message.gas_fee_cap * message.gas_limit;
+    }
+    balance - cost
+}
+
+/// Checks the `gas_used <= gas_limit` invariant of explicit messages.
+fn check_gas_used(gas_used: i64, gas_limit: i64) -> anyhow::Result<()> {
+    if gas_used > gas_limit {
+        error!(
+            "gas accounting bug: message used {} gas, exceeding its gas limit of {}",
+            gas_used, gas_limit
+        );
+        bail!(
+            "message used {} gas, exceeding its gas limit of {}",
+            gas_used,
+            gas_limit
+        );
+    }
+    Ok(())
+}
+
+/// Turns a non-OK receipt into an error carrying the exit code and failure info. Note that the
+/// message has still been applied.
+fn check_receipt_ok(resp: &FvmMachineExecuteResponse) -> anyhow::Result<()> {
+    if resp.exit_code == ExitCode::OK.value() as u64 {
+        return Ok(());
+    }
+    match &resp.failure_info {
+        Some(info) => bail!(
+            "message failed with exit code {}: {}",
+            resp.exit_code,
+            info.as_str()
+        ),
+        None => bail!("message failed with exit code {}", resp.exit_code),
+    }
+}
+
+/// The gas limit of gas-free messages, far more than any message can use in practice (a thousand
+/// blocks' worth) while leaving plenty of headroom below `i64::MAX` for gas arithmetic.
+const GAS_FREE_LIMIT: i64 = 10_000_000_000_000;
+
+/// Lifts the message's gas limit so that it won't run out of gas, and zeroes its fee cap and
+/// premium so that the sender doesn't need (or pay) funds for it. The receipt still reports the
+/// gas the message used. This changes the message, so it's only meant for testing actor logic.
+fn make_gas_free(message: &mut Message) {
+    message.gas_limit = GAS_FREE_LIMIT;
+    message.gas_fee_cap = TokenAmount::default();
+    message.gas_premium = TokenAmount::default();
+}
+
+/// Caps the gas limit of an implicit message at `gas_ceiling` (if non-zero). Implicit messages
+/// skip all nonce and balance checks, so this only bounds the gas they can consume.
+///
+/// Explicit messages are rejected: their gas limit is part of the signed message and determines
+/// the fees paid, so it can't be changed.
+fn apply_gas_ceiling(
+    message: &mut Message,
+    apply_kind: ApplyKind,
+    gas_ceiling: u64,
+) -> anyhow::Result<()> {
+    if gas_ceiling == 0 {
+        return Ok(());
+    }
+    if matches!(apply_kind, ApplyKind::Explicit) {
+        bail!("a gas ceiling can only be applied to implicit messages");
+    }
+    let gas_ceiling = i64::try_from(gas_ceiling).unwrap_or(i64::MAX);
+    message.gas_limit = message.gas_limit.min(gas_ceiling);
+    Ok(())
+}
+
+/// Serializes the state tree with the given root (which must have been flushed, as only flushed
+/// actors are listed) as a JSON object of the form `{"root": ..., "actors": [...]}`, failing if the
+/// output grows beyond `max_size` bytes.
+///
+/// The state of builtin actors is decoded with [`decode_json`], along with the actor's name. Other
+/// actors' state is only listed by CID.
+fn dump_state_json<BS: Blockstore>(
+    state_tree: &StateTree<BS>,
+    root: &Cid,
+    builtin_actor_names: &HashMap<Cid, String>,
+    max_size: usize,
+) -> anyhow::Result<Vec<u8>> {
+    let mut out = format!("{{\"root\":\"{}\",\"actors\":[", root).into_bytes();
+    let mut first = true;
+    state_tree.for_each(|addr, actor: &ActorState| {
+        if !first {
+            out.push(b',');
+        }
+        first = false;
+        let name = builtin_actor_names.get(&actor.code);
+        let decoded_state = match name {
+            // It's only a debugging aid, so undecodable state is left out rather than failing.
+            Some(_) if actor.state.codec() == DAG_CBOR => state_tree
+                .store()
+                .get(&actor.state)?
+                .and_then(|block| decode_json(&block).ok()),
+            _ => None,
+        };
+        serde_json::to_writer(
+            &mut out,
+            &serde_json::json!({
+                "address": addr.to_string(),
+                "code": actor.code.to_string(),
+                "name": name,
+                "state": actor.state.to_string(),
+                "decoded_state": decoded_state,
+                "nonce": actor.sequence,
+                "balance": actor.balance.to_string(),
+            }),
+        )?;
+        if out.len() > max_size {
+            bail!("state dump exceeds the maximum size of {} bytes", max_size);
+        }
+        Ok(())
+    })?;
+    out.extend_from_slice(b"]}");
+
+    Ok(out)
+}
+
+/// The network versions machines can be created for, along with the builtin actors bundled for
+/// them. Versions without a bundle read their actors from the state tree.
+const NETWORK_VERSIONS: &[(NetworkVersion, Option<&[u8]>)] = &[
+    (NetworkVersion::V15, Some(actors_v7::BUNDLE_CAR)),
+    (NetworkVersion::V16, None),
+];
+
+/// Lists the network versions machines can be created for, and where their actors come from if
+/// no manifest is passed.
+#[ffi_export]
+fn fvm_supported_network_versions() -> repr_c::Box<Result<c_slice::Box<FvmSupportedNetworkVersion>>>
+{
+    catch_panic_response("fvm_supported_network_versions", || {
+        Ok(supported_network_versions().into_boxed_slice().into())
+    })
+}
+
+fn supported_network_versions() -> Vec<FvmSupportedNetworkVersion> {
+    NETWORK_VERSIONS
+        .iter()
+        .map(|(network_version, bundle)| FvmSupportedNetworkVersion {
+            network_version: *network_version as u64,
+            actor_source: actor_source(false, bundle.is_some()),
+        })
+        .collect()
+}
+
+/// Builds a state tree holding only the system and init actors, in the blockstore, and returns
+/// its root. A machine can be created on it for the network version, with the same manifest (or
+/// none, for versions with builtin actors).
+#[ffi_export]
+fn fvm_empty_state_root(
+    network_version: u64,
+    manifest_cid: c_slice::Ref<u8>, /* empty: the builtin actors */
+    network_name: c_slice::Ref<u8>,
+    blockstore_id: u64,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_empty_state_root", || {
+        let network_version = NetworkVersion::try_from(network_version as u32)
+            .map_err(|_| anyhow!("unsupported network version: {}", network_version))?;
+        let manifest_cid = if !manifest_cid.is_empty() {
+            let cid = Cid::try_from(&manifest_cid[..])
+                .map_err(|err| anyhow!("invalid manifest: {}", err))?;
+            Some(cid)
+        } else {
+            None
+        };
+        let network_name = std::str::from_utf8(&network_name)
+            .map_err(|err| anyhow!("invalid network name: {}", err))?;
+
+        let root = empty_state_for(
+            &CgoBlockstore::new(blockstore_id),
+            network_version,
+            manifest_cid,
+            network_name,
+        )?;
+        Ok(root.to_bytes().into_boxed_slice().into())
+    })
+}
+
+pub(super) fn empty_state_for(
+    bs: &impl Blockstore,
+    network_version: NetworkVersion,
+    manifest_cid: Option<Cid>,
+    network_name: &str,
+) -> anyhow::Result<Cid> {
+    let manifest = match import_actors(bs, manifest_cid, network_version) {
+        Ok(Some(manifest)) => manifest,
+        Ok(None) => bail!(
+            "network version {} has no builtin actors, a manifest is required",
+            network_version as u32
+        ),
+        Err(err) => bail!("couldn't load builtin actors: {}", err),
+    };
+    // Machines for versions without a bundle look the manifest up in the system actor's state.
+    let bundled = NETWORK_VERSIONS
+        .iter()
+        .any(|(supported, bundle)| *supported == network_version && bundle.is_some());
+    let manifest_in_state = if bundled { None } else { Some(manifest) };
+    empty_state_root(
+        bs,
+        &builtin_actor_names(bs, &manifest)?,
+        manifest_in_state,
+        network_name,
+    )
+}
+
+/// Classifies where the actors come from, given whether the caller passed a manifest, and whether
+/// [`import_actors`] returned one.
+fn actor_source(forced_manifest: bool, imported_manifest: bool) -> FvmActorSource {
+    match (forced_manifest, imported_manifest) {
+        (true, _) => FvmActorSource::Override,
+        (false, true) => FvmActorSource::Builtin,
+        (false, false) => FvmActorSource::FromState,
+    }
+}
+
+pub(super) fn import_actors(
+    blockstore: &impl Blockstore,
+    manifest_cid: Option<Cid>,
+    network_version: NetworkVersion,
+) -> std::result::Result<Option<Cid>, &'static str> {
+    if manifest_cid.is_some() {
+        return Ok(manifest_cid);
+    }
+    let car = match NETWORK_VERSIONS
+        .iter()
+        .find(|(supported, _)| *supported == network_version)
+    {
+        Some((_, Some(bundle))) => *bundle,
+        Some((_, None)) => return Ok(None),
+        None => return Err("unsupported network version"),
+    };
+    let roots = block_on(async { load_car(blockstore, car).await.unwrap() });
+    assert_eq!(roots.len(), 1);
+    Ok(Some(roots[0]))
+}
+
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+struct LotusTrace {
+    pub msg: Message,
+    pub msg_receipt: Receipt,
+    pub error: String,
+    pub subcalls: Vec<LotusTrace>,
+}
+
+/// A [`LotusTrace`] in the schema of the legacy Lotus VM, see [`FvmLotusTraceFormat::Legacy`].
+#[derive(Clone, Debug, Serialize_tuple, Deserialize_tuple)]
+struct LegacyLotusTrace {
+    pub msg: Message,
+    pub msg_receipt: Receipt,
+    pub error: String,
+    pub duration: i64,
+    // Always empty, the element type doesn't matter.
+    pub gas_charges: Vec<RawBytes>,
+    pub subcalls: Vec<LegacyLotusTrace>,
+}
+
+impl From<LotusTrace> for LegacyLotusTrace {
+    fn from(trace: LotusTrace) -> Self {
+        LegacyLotusTrace {
+            msg: trace.msg,
+            msg_receipt: trace.msg_receipt,
+            error: trace.error,
+            duration: 0,
+            gas_charges: vec![],
+            subcalls: trace.subcalls.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// Encodes the trace in the given Lotus schema.
+fn encode_lotus_trace(trace: LotusTrace, format: FvmLotusTraceFormat) -> anyhow::Result<Vec<u8>> {
+    Ok(match format {
+        FvmLotusTraceFormat::Fvm => to_vec(&trace)?,
+        FvmLotusTraceFormat::Legacy => to_vec(&LegacyLotusTrace::from(trace))?,
+    })
+}
+
+/// Finds the first frame in which the traces differ, pushing the subcall indices leading to it
+/// onto `path`.
+fn compare_traces(
+    a: &LotusTrace,
+    b: &LotusTrace,
+    path: &mut Vec<u64>,
+) -> anyhow::Result<FvmTraceField> {
+    if to_vec(&a.msg)? != to_vec(&b.msg)? {
+        return Ok(FvmTraceField::Message);
+    }
+    if a.msg_receipt.exit_code != b.msg_receipt.exit_code {
+        return Ok(FvmTraceField::ExitCode);
+    }
+    if a.msg_receipt.gas_used != b.msg_receipt.gas_used {
+        return Ok(FvmTraceField::GasUsed);
+    }
+    if a.msg_receipt.return_data != b.msg_receipt.return_data {
+        return Ok(FvmTraceField::ReturnData);
+    }
+    if a.error != b.error {
+        return Ok(FvmTraceField::Error);
+    }
+
+    for (i, (a, b)) in a.subcalls.iter().zip(&b.subcalls).enumerate() {
+        path.push(i as u64);
+        let field = compare_traces(a, b, path)?;
+        if field != FvmTraceField::NoDivergence {
+            return Ok(field);
+        }
+        path.pop();
+    }
+    if a.subcalls.len() != b.subcalls.len() {
+        return Ok(FvmTraceField::Subcalls);
+    }
+    Ok(FvmTraceField::NoDivergence)
+}
+
+/// Builds the Lotus trace of the call starting with `new_call` from the rest of its events. Only
+/// `max_depth` levels of calls are kept (counting this one): the subcalls of the last level are
+/// dropped. The params and return data of the kept calls are charged to the budget as they're
+/// copied into the trace, stopping as soon as it's exceeded.
+fn build_lotus_trace(
+    new_call: &ExecutionEvent,
+    trace_iter: &mut impl Iterator<Item = ExecutionEvent>,
+    max_depth: u64,
+    budget: &mut AllocationBudget,
+) -> anyhow::Result<LotusTrace> {
+    let mut new_trace = LotusTrace {
+        msg: match new_call {
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => Message {
+                version: 0,
+                from: Address::new_id(*from),
+                to: *to,
+                sequence: 0,
+                value: value.clone(),
+                method_num: *method,
+                params: params.clone(),
+                gas_limit: 0,
+                gas_fee_cap: TokenAmount::default(),
+                gas_premium: TokenAmount::default(),
+            },
+            _ => {
+                return Err(anyhow!("expected ExecutionEvent of type Call"));
+            }
+        },
+        msg_receipt: Receipt {
+            exit_code: ExitCode::OK,
+            return_data: RawBytes::default(),
+            gas_used: 0,
+        },
+        error: String::new(),
+        subcalls: vec![],
+    };
+    // Calls past the depth limit are built, then dropped.
+    let kept = max_depth > 0;
+    if kept {
+        budget.charge("trace", new_trace.msg.params.bytes().len())?;
+    }
+
+    while let Some(trace) = trace_iter.next() {
+        match trace {
+            ExecutionEvent::Call { .. } => {
+                // The subcall's events are consumed even if it's dropped.
+                let subcall =
+                    build_lotus_trace(&trace, trace_iter, max_depth.saturating_sub(1), budget)?;
+                if max_depth > 1 {
+                    new_trace.subcalls.push(subcall);
+                }
+            }
+            ExecutionEvent::CallReturn(return_data) => {
+                if kept {
+                    budget.charge("trace", return_data.bytes().len())?;
+                }
+                new_trace.msg_receipt = Receipt {
+                    exit_code: ExitCode::OK,
+                    return_data,
+                    gas_used: 0,
+                };
+                return Ok(new_trace);
+            }
+            ExecutionEvent::CallAbort(exit_code) => {
+                if exit_code.is_success() {
+                    return Err(anyhow!("actor failed with status OK"));
+                }
+                new_trace.msg_receipt = Receipt {
+                    exit_code,
+                    return_data: Default::default(),
+                    gas_used: 0,
+                };
+                return Ok(new_trace);
+            }
+            ExecutionEvent::CallError(syscall_err) => {
+                // Errors indicate the message couldn't be dispatched at all
+                // (as opposed to failing during execution of the receiving actor).
+                // These errors are mapped to exit codes that persist on chain.
+                let exit_code = match syscall_err.1 {
+                    ErrorNumber::InsufficientFunds => ExitCode::SYS_INSUFFICIENT_FUNDS,
+                    ErrorNumber::NotFound => ExitCode::SYS_INVALID_RECEIVER,
+                    _ => ExitCode::SYS_ASSERTION_FAILED,
+                };
+
+                new_trace.msg_receipt = Receipt {
+                    exit_code,
+                    return_data: Default::default(),
+                    gas_used: 0,
+                };
+                return Ok(new_trace);
+            }
+        };
+    }
+
+    Err(anyhow!("should have returned on an ExecutionEvent:Return"))
+}
+
+/// Counts the subcalls made by a traced message, zero if there's no trace.
+fn count_subcalls(trace: &[ExecutionEvent]) -> u64 {
+    let calls = trace
+        .iter()
+        .filter(|event| matches!(event, ExecutionEvent::Call { .. }))
+        .count() as u64;
+    // The first call is the message itself.
+    calls.saturating_sub(1)
+}
+
+/// Returns how deeply the calls of a traced message nest, one if it made no subcalls, and zero if
+/// there's no trace.
+fn trace_depth(trace: &[ExecutionEvent]) -> u64 {
+    let (mut depth, mut max_depth) = (0u64, 0);
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { .. } => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            _ => depth = depth.saturating_sub(1),
+        }
+    }
+    max_depth
+}
+
+/// Checks that a message didn't make more than `max_subcalls` subcalls (if non-zero).
+fn check_subcalls(subcalls: u64, max_subcalls: u64) -> anyhow::Result<()> {
+    if max_subcalls != 0 && subcalls > max_subcalls {
+        bail!(
+            "message exceeded the subcall limit of {}: made {} subcalls",
+            max_subcalls,
+            subcalls
+        );
+    }
+    Ok(())
+}
+
+/// Describes the first syscall of a message that failed with an error number: the first send that
+/// failed (see [`send_error_diagnostics`]), or else the syscall error that made the message abort,
+/// with its syscall and the backtrace of the aborted frames. Returns `None` if no syscall failed
+/// that way.
+///
+/// The FVM only reports syscall errors in the trace (for sends) and in the backtrace (the last
+/// error of the frame that aborted first). The errors of other syscalls that an actor recovers
+/// from aren't reported, nor are the arguments of syscalls other than sends.
+fn syscall_error_diagnostics(
+    trace: &[ExecutionEvent],
+    failure_info: Option<&ApplyFailure>,
+) -> Option<String> {
+    if let Some(diagnostics) = send_error_diagnostics(trace) {
+        return Some(diagnostics);
+    }
+    match failure_info {
+        Some(
+            failure @ ApplyFailure::MessageBacktrace(Backtrace {
+                cause: Some(Cause::Syscall { .. }),
+                ..
+            }),
+        ) => Some(format!("syscall failed: {}", failure)),
+        _ => None,
+    }
+}
+
+/// Finds the first send that failed with a syscall error in the trace, and describes the error
+/// along with the call frame of the send. Returns `None` if no send failed that way. Only sends
+/// show up in the trace, so this doesn't see errors other syscalls return to the actor.
+fn send_error_diagnostics(trace: &[ExecutionEvent]) -> Option<String> {
+    let mut frames = Vec::new();
+    for event in trace {
+        match event {
+            ExecutionEvent::Call { .. } => frames.push(event),
+            ExecutionEvent::CallReturn(_) | ExecutionEvent::CallAbort(_) => {
+                frames.pop();
+            }
+            ExecutionEvent::CallError(syscall_err) => {
+                let frame = match frames.last() {
+                    Some(ExecutionEvent::Call {
+                        from,
+                        to,
+                        method,
+                        params,
+                        value,
+                    }) => format!(
+                        "from: {}, to: {}, method: {}, params: {} bytes, value: {}",
+                        Address::new_id(*from),
+                        to,
+                        method,
+                        params.len(),
+                        value
+                    ),
+                    _ => "unknown".to_owned(),
+                };
+                return Some(format!(
+                    "send failed with {:?} at call depth {}: {} (frame: {})",
+                    syscall_err.1,
+                    frames.len(),
+                    syscall_err.0,
+                    frame
+                ));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use crate::fvm::blockstore::{
+        AccessLog, BlockCache, OverlayBlockstore, ReadBatch, WriteLog, WriteLogBlockstore,
+    };
+    use crate::fvm::history::RecentExecutions;
+    use crate::fvm::machine::{
+        actor_nonce, actor_source, actor_state_block, apply_batch, apply_gas_ceiling,
+        apply_message, balance_margin, build_lotus_trace, builtin_actor_names, builtin_method_name,
+        cap_return_data, check_gas_used, check_receipt_ok, check_subcalls, count_actors_touched,
+        count_subcalls, decode_apply_kind, dump_state_json, effective_fees, empty_state_for,
+        encode_lotus_trace, execute_checked, execute_with_access_list, exit_code_class,
+        exit_code_name, fvm_compare_traces, fvm_encode_message, fvm_gas_params,
+        fvm_machine_execute_and_flush, fvm_machine_execute_for_root, fvm_machine_execute_message,
+        fvm_machine_flush, fvm_machine_prefetch_state, fvm_machine_verify_manifest,
+        fvm_message_size, fvm_trace_detect_reentrancy, get_block, import_actors, inclusion_gas,
+        invoked_methods, logged_flush, make_gas_free, manifest_cid_from_state, new_machine,
+        next_exec_id, params_codec, params_decode_failed, penalty_breakdown, receiver_kind,
+        resolve_addresses, send_error_diagnostics, state_root, supported_network_versions,
+        syscall_error_diagnostics, timed_lock, trace_depth, verify_message_signature,
+        AllocationBudget, GasParams, LegacyLotusTrace, LotusTrace, PenaltyEntry, ENGINES,
+        SYSTEM_ACTOR_ID, UNRESOLVED_ID,
+    };
+    use crate::fvm::scripted::{
+        scripted_executor, scripted_executor_with_access_log, self_send, ScriptedExecutor,
+        ScriptedExterns,
+    };
+    use crate::fvm::trace::{decode_compact_trace, encode_compact_trace};
+    use crate::fvm::types::{
+        FvmActorSource, FvmBatchOptions, FvmExecuteOptions, FvmExitCodeClass, FvmLotusTraceFormat,
+        FvmMachineExecuteResponse, FvmReceiverGas, FvmReceiverKind, FvmTraceField, InnerFvmMachine,
+    };
+    use bls_signatures::{PrivateKey, Serialize};
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::blockstore::BufferedBlockstore;
+    use fvm::call_manager::backtrace::{Backtrace, Cause};
+    use fvm::executor::{ApplyFailure, ApplyKind, Executor, ThreadedExecutor};
+    use fvm::kernel::SyscallError;
+    use fvm::machine::{Machine, NetworkConfig};
+    use fvm::state_tree::{ActorState, StateTree};
+    use fvm::trace::ExecutionEvent;
+    use fvm_ipld_blockstore::{Block, Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::address::Address;
+    use fvm_shared::crypto::signature::Signature;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ErrorNumber::IllegalArgument;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::message::Message;
+    use fvm_shared::receipt::Receipt;
+    use fvm_shared::state::StateTreeVersion;
+    use fvm_shared::version::NetworkVersion;
+    use fvm_shared::ActorID;
+    use fvm_shared::{MethodNum, METHOD_SEND};
+    use num_traits::Zero;
+    use safer_ffi::prelude::c_slice;
+    use std::collections::HashMap;
+    use std::sync::{mpsc, Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     fn test_lotus_trace() {
@@ -379,28 +2722,2303 @@ mod test {
             to: Address::new_id(0),
             value: TokenAmount::default(),
         };
-        let return_result =
-            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "illegal"));
-        let trace = vec![
-            call_event.clone(),
-            call_event.clone(),
-            return_result.clone(),
-            call_event.clone(),
-            call_event,
-            return_result.clone(),
-            return_result.clone(),
-            return_result,
+        let return_result =
+            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "illegal"));
+        let trace = vec![
+            call_event.clone(),
+            call_event.clone(),
+            return_result.clone(),
+            call_event.clone(),
+            call_event,
+            return_result.clone(),
+            return_result.clone(),
+            return_result,
+        ];
+
+        let mut trace_iter = trace.into_iter();
+
+        let lotus_trace = build_lotus_trace(
+            &trace_iter.next().unwrap(),
+            &mut trace_iter,
+            u64::MAX,
+            &mut AllocationBudget::new(0),
+        )
+        .unwrap();
+
+        assert!(trace_iter.next().is_none());
+
+        assert_eq!(lotus_trace.subcalls.len(), 2);
+        assert_eq!(lotus_trace.subcalls[0].subcalls.len(), 0);
+        assert_eq!(lotus_trace.subcalls[1].subcalls.len(), 1);
+        assert_eq!(lotus_trace.subcalls[1].subcalls[0].subcalls.len(), 0);
+    }
+
+    #[test]
+    fn test_trace_depth_limit() {
+        let call_event = |to| ExecutionEvent::Call {
+            from: 100,
+            method: 2,
+            params: RawBytes::default(),
+            to: Address::new_id(to),
+            value: TokenAmount::default(),
+        };
+        // 101 calls 102 twice, and the second call calls 103.
+        let trace = vec![
+            call_event(101),
+            call_event(102),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(102),
+            call_event(103),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            ExecutionEvent::CallAbort(ExitCode::USR_FORBIDDEN),
+            ExecutionEvent::CallReturn(RawBytes::new(vec![0x42])),
+        ];
+        assert_eq!(trace_depth(&trace), 3);
+        assert_eq!(trace_depth(&trace[..3]), 2);
+        assert_eq!(trace_depth(&[]), 0);
+
+        let mut trace_iter = trace.into_iter();
+        let trace = build_lotus_trace(
+            &trace_iter.next().unwrap(),
+            &mut trace_iter,
+            2,
+            &mut AllocationBudget::new(0),
+        )
+        .unwrap();
+        assert!(trace_iter.next().is_none());
+        assert_eq!(trace.msg_receipt.return_data, RawBytes::new(vec![0x42]));
+        assert_eq!(trace.subcalls.len(), 2);
+        assert!(trace.subcalls[0].subcalls.is_empty());
+        assert_eq!(
+            trace.subcalls[1].msg_receipt.exit_code,
+            ExitCode::USR_FORBIDDEN
+        );
+        assert!(trace.subcalls[1].subcalls.is_empty());
+    }
+
+    #[test]
+    fn test_lotus_trace_formats() {
+        let call_event = |to| ExecutionEvent::Call {
+            from: 100,
+            method: 2,
+            params: RawBytes::default(),
+            to: Address::new_id(to),
+            value: TokenAmount::default(),
+        };
+        let trace = vec![
+            call_event(101),
+            call_event(102),
+            ExecutionEvent::CallReturn(RawBytes::new(vec![0x42])),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+        ];
+        let mut trace_iter = trace.into_iter();
+        let trace = build_lotus_trace(
+            &trace_iter.next().unwrap(),
+            &mut trace_iter,
+            u64::MAX,
+            &mut AllocationBudget::new(0),
+        )
+        .unwrap();
+
+        let current = encode_lotus_trace(trace.clone(), FvmLotusTraceFormat::Fvm).unwrap();
+        let legacy = encode_lotus_trace(trace.clone(), FvmLotusTraceFormat::Legacy).unwrap();
+        // Both are CBOR lists, of four and six fields.
+        assert_eq!(current[0], 0x84);
+        assert_eq!(legacy[0], 0x86);
+        assert_eq!(current, fvm_ipld_encoding::to_vec(&trace).unwrap());
+
+        let legacy: LegacyLotusTrace = fvm_ipld_encoding::from_slice(&legacy).unwrap();
+        assert_eq!(legacy.msg.to, Address::new_id(101));
+        assert_eq!(legacy.duration, 0);
+        assert!(legacy.gas_charges.is_empty());
+        assert_eq!(legacy.subcalls.len(), 1);
+        assert_eq!(legacy.subcalls[0].msg.to, Address::new_id(102));
+        assert_eq!(
+            legacy.subcalls[0].msg_receipt.return_data,
+            RawBytes::new(vec![0x42])
+        );
+        assert_eq!(legacy.subcalls[0].duration, 0);
+    }
+
+    #[test]
+    fn test_compact_trace() {
+        let call_event = |from, method| ExecutionEvent::Call {
+            from,
+            method,
+            params: RawBytes::new(vec![0x81, 0x01]),
+            to: Address::new_id(from + 1),
+            value: TokenAmount::from(1000u16),
+        };
+        let trace = vec![
+            call_event(100, 2),
+            call_event(101, 3),
+            ExecutionEvent::CallReturn(RawBytes::new(vec![0x42])),
+            call_event(101, 4),
+            ExecutionEvent::CallAbort(ExitCode::USR_FORBIDDEN),
+            call_event(101, 5),
+            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "illegal")),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+        ];
+        let lotus_trace = |trace: Vec<ExecutionEvent>| {
+            let mut trace_iter = trace.into_iter();
+            let trace = build_lotus_trace(
+                &trace_iter.next().unwrap(),
+                &mut trace_iter,
+                u64::MAX,
+                &mut AllocationBudget::new(0),
+            )
+            .unwrap();
+            fvm_ipld_encoding::to_vec(&trace).unwrap()
+        };
+
+        let compact = encode_compact_trace(&trace, |_| Ok(())).unwrap();
+        let decoded = decode_compact_trace(&compact).unwrap();
+        assert_eq!(decoded.len(), trace.len());
+        assert_eq!(lotus_trace(decoded), lotus_trace(trace));
+
+        assert!(decode_compact_trace(&compact[..compact.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_compare_traces() {
+        let trace = |exit_code, subcalls| LotusTrace {
+            msg: test_message(),
+            msg_receipt: Receipt {
+                exit_code,
+                return_data: RawBytes::default(),
+                gas_used: 0,
+            },
+            error: String::new(),
+            subcalls,
+        };
+        let build = |nested_exit_code| {
+            let nested = trace(nested_exit_code, vec![]);
+            let subcalls = vec![
+                trace(ExitCode::OK, vec![]),
+                trace(ExitCode::OK, vec![nested]),
+            ];
+            fvm_ipld_encoding::to_vec(&trace(ExitCode::OK, subcalls)).unwrap()
+        };
+        let (ours, theirs) = (build(ExitCode::OK), build(ExitCode::USR_NOT_FOUND));
+
+        let resp = fvm_compare_traces(ours[..].into(), ours[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(resp.value.field, FvmTraceField::NoDivergence);
+        assert!(resp.value.path.is_empty());
+
+        let resp = fvm_compare_traces(ours[..].into(), theirs[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(resp.value.field, FvmTraceField::ExitCode);
+        assert_eq!(&resp.value.path[..], &[1, 0]);
+
+        let resp = fvm_compare_traces(ours[..].into(), theirs[1..].into());
+        assert!(!resp.error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_detect_reentrancy() {
+        let trace = |to, subcalls| LotusTrace {
+            msg: Message {
+                to: Address::new_id(to),
+                ..test_message()
+            },
+            msg_receipt: Receipt {
+                exit_code: ExitCode::OK,
+                return_data: RawBytes::default(),
+                gas_used: 0,
+            },
+            error: String::new(),
+            subcalls,
+        };
+        let detect = |trace: &LotusTrace| {
+            let trace = fvm_ipld_encoding::to_vec(trace).unwrap();
+            let resp = fvm_trace_detect_reentrancy(trace[..].into());
+            assert!(resp.error_msg.is_empty());
+            let reentries: Vec<(Address, Vec<Address>)> =
+                fvm_ipld_encoding::from_slice(&resp.value).unwrap();
+            reentries
+        };
+
+        // 101 calls 102 twice, which calls back into 101 the second time. Calling 102 again is
+        // not a re-entry, it returned first.
+        let reentrant = trace(
+            101,
+            vec![trace(102, vec![]), trace(102, vec![trace(101, vec![])])],
+        );
+        let ids = |ids: &[u64]| -> Vec<_> { ids.iter().map(|id| Address::new_id(*id)).collect() };
+        assert_eq!(
+            detect(&reentrant),
+            vec![(Address::new_id(101), ids(&[101, 102, 101]))]
+        );
+
+        let flat = trace(
+            101,
+            vec![trace(102, vec![trace(103, vec![])]), trace(103, vec![])],
+        );
+        assert!(detect(&flat).is_empty());
+
+        let resp = fvm_trace_detect_reentrancy([0xff][..].into());
+        assert!(!resp.error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_send_error_diagnostics() {
+        let call_event = |method| ExecutionEvent::Call {
+            from: 100,
+            method,
+            params: RawBytes::new(vec![1, 2, 3]),
+            to: Address::new_id(101),
+            value: TokenAmount::default(),
+        };
+        let ok_trace = vec![
+            call_event(2),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+        ];
+        assert!(send_error_diagnostics(&ok_trace).is_none());
+
+        let trace = vec![
+            call_event(2),
+            call_event(3),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(4),
+            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "illegal cid")),
+            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "second error")),
+        ];
+        let diagnostics = send_error_diagnostics(&trace).unwrap();
+        assert!(diagnostics.contains("IllegalArgument"));
+        assert!(diagnostics.contains("illegal cid"));
+        assert!(diagnostics.contains("call depth 2"));
+        assert!(diagnostics.contains("method: 4"));
+        assert!(diagnostics.contains("params: 3 bytes"));
+        assert!(!diagnostics.contains("second error"));
+
+        // Sending to an ID address no actor has fails the send with NotFound.
+        let mut executor =
+            scripted_executor(NetworkVersion::V15, ScriptedExterns::default()).unwrap();
+        let message = Message {
+            to: Address::new_id(999),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let apply_ret = executor
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap();
+        assert_eq!(
+            apply_ret.msg_receipt.exit_code,
+            ExitCode::SYS_INVALID_RECEIVER
+        );
+        let diagnostics = send_error_diagnostics(&apply_ret.exec_trace).unwrap();
+        assert!(diagnostics.contains("NotFound"));
+        assert!(diagnostics.contains("call depth 1"));
+        assert!(diagnostics.contains("to: f0999"));
+    }
+
+    #[test]
+    fn test_syscall_error_diagnostics() {
+        assert!(syscall_error_diagnostics(&[], None).is_none());
+        let pre_validation = ApplyFailure::PreValidation("not enough funds".into());
+        assert!(syscall_error_diagnostics(&[], Some(&pre_validation)).is_none());
+
+        // A message aborting on a syscall error that isn't a send's.
+        let backtrace = ApplyFailure::MessageBacktrace(Backtrace {
+            frames: vec![],
+            cause: Some(Cause::from_syscall(
+                "ipld",
+                "block_open",
+                SyscallError::new(IllegalArgument, "invalid cid"),
+            )),
+        });
+        let diagnostics = syscall_error_diagnostics(&[], Some(&backtrace)).unwrap();
+        assert!(diagnostics.contains("ipld::block_open"));
+        assert!(diagnostics.contains("invalid cid"));
+
+        // A failed send takes precedence over the cause of the abort.
+        let mut executor =
+            scripted_executor(NetworkVersion::V15, ScriptedExterns::default()).unwrap();
+        let message = Message {
+            to: Address::new_id(999),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let apply_ret = executor
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap();
+        let diagnostics =
+            syscall_error_diagnostics(&apply_ret.exec_trace, Some(&backtrace)).unwrap();
+        assert!(diagnostics.contains("NotFound"));
+        assert!(!diagnostics.contains("invalid cid"));
+    }
+
+    #[test]
+    fn test_check_subcalls() {
+        let call_event = |to| ExecutionEvent::Call {
+            from: 100,
+            method: 2,
+            params: RawBytes::default(),
+            to: Address::new_id(to),
+            value: TokenAmount::default(),
+        };
+        // A message fanning out to 3 actors.
+        let mut trace = vec![call_event(101)];
+        for to in 102..105 {
+            trace.push(call_event(to));
+            trace.push(ExecutionEvent::CallReturn(RawBytes::default()));
+        }
+        trace.push(ExecutionEvent::CallReturn(RawBytes::default()));
+
+        let subcalls = count_subcalls(&trace);
+        assert_eq!(subcalls, 3);
+        check_subcalls(subcalls, 0).unwrap();
+        check_subcalls(subcalls, 3).unwrap();
+        assert_eq!(
+            check_subcalls(subcalls, 2).unwrap_err().to_string(),
+            "message exceeded the subcall limit of 2: made 3 subcalls"
+        );
+        // Without tracing, there's nothing to count.
+        assert_eq!(count_subcalls(&[]), 0);
+    }
+
+    #[test]
+    fn test_count_actors_touched() {
+        let bs = MemoryBlockstore::new();
+        let state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        let call_event = |from, to| ExecutionEvent::Call {
+            from,
+            method: 2,
+            params: RawBytes::default(),
+            to: Address::new_id(to),
+            value: TokenAmount::default(),
+        };
+        let (from, to) = (Address::new_id(100), Address::new_id(101));
+
+        // The receiver calls a third actor, and then back into the sender.
+        let trace = vec![
+            call_event(100, 101),
+            call_event(101, 102),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(101, 100),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+        ];
+        assert_eq!(
+            count_actors_touched(&state_tree, from, to, &trace).unwrap(),
+            3
+        );
+        // Without tracing, only the sender and receiver are known.
+        assert_eq!(count_actors_touched(&state_tree, from, to, &[]).unwrap(), 2);
+        assert_eq!(
+            count_actors_touched(&state_tree, from, from, &[]).unwrap(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_invoked_methods() {
+        let bs = MemoryBlockstore::new();
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        let code = |name: &str| Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(name.as_bytes()));
+        for (id, name) in [(101, "multisig"), (102, "miner")] {
+            let actor = ActorState::new(code(name), code("state"), TokenAmount::from(0u8), 0);
+            state_tree.set_actor(&Address::new_id(id), actor).unwrap();
+        }
+        let call_event = |from, to, method| ExecutionEvent::Call {
+            from,
+            method,
+            params: RawBytes::default(),
+            to: Address::new_id(to),
+            value: TokenAmount::default(),
+        };
+
+        // The receiver calls two methods of a third actor (one of them twice), and a missing actor.
+        let trace = vec![
+            call_event(100, 101, 2),
+            call_event(101, 102, 3),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(101, 102, 4),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(101, 102, 3),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+            call_event(101, 103, 2),
+            ExecutionEvent::CallError(SyscallError::new(IllegalArgument, "missing")),
+            ExecutionEvent::CallReturn(RawBytes::default()),
+        ];
+        assert_eq!(
+            invoked_methods(&state_tree, &trace).unwrap(),
+            vec![
+                (code("multisig"), 2),
+                (code("miner"), 3),
+                (code("miner"), 4)
+            ]
+        );
+        assert!(invoked_methods(&state_tree, &[]).unwrap().is_empty());
+    }
+
+    fn test_message() -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            sequence: 7,
+            value: TokenAmount::from(1_000u32),
+            method_num: 2,
+            params: RawBytes::new(vec![0x81, 0x01]),
+            gas_limit: 10_000_000_000,
+            gas_fee_cap: TokenAmount::from(100u32),
+            gas_premium: TokenAmount::from(10u32),
+        }
+    }
+
+    /// Creates a scripted executor, wrapped like the machine's.
+    fn scripted_machine() -> ThreadedExecutor<ScriptedExecutor> {
+        ThreadedExecutor(
+            scripted_executor(NetworkVersion::V15, ScriptedExterns::default()).unwrap(),
+        )
+    }
+
+    /// Creates an account for a new BLS key made of `seed`, returning the key. The accounts of a
+    /// scripted executor are assigned IDs from 100, in order of creation.
+    fn create_account(machine: &mut ThreadedExecutor<ScriptedExecutor>, seed: u8) -> Address {
+        let key = Address::new_bls(&[seed; 48]).unwrap();
+        let message = Message {
+            to: key,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let apply_ret = machine
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap();
+        assert_eq!(apply_ret.msg_receipt.exit_code, ExitCode::OK);
+        key
+    }
+
+    /// Applies the message to a scripted executor with `apply_message`, as the FFI would to the
+    /// machine of `executor`. Explicit messages are charged for their encoded size.
+    fn apply(
+        executor: &InnerFvmMachine,
+        machine: &mut ThreadedExecutor<ScriptedExecutor>,
+        message: Message,
+        apply_kind: ApplyKind,
+        options: FvmExecuteOptions,
+    ) -> anyhow::Result<FvmMachineExecuteResponse> {
+        let chain_len = match apply_kind {
+            ApplyKind::Explicit => fvm_ipld_encoding::to_vec(&message)?.len() as u64,
+            ApplyKind::Implicit => 0,
+        };
+        apply_message(
+            executor,
+            machine,
+            message,
+            apply_kind,
+            chain_len,
+            Duration::ZERO,
+            options,
+        )
+    }
+
+    /// Pairs explicit messages with their encoded length, for `apply_batch`.
+    fn with_chain_lens(messages: Vec<Message>) -> Vec<(Message, u64)> {
+        messages
+            .into_iter()
+            .map(|message| {
+                let chain_len = fvm_ipld_encoding::to_vec(&message).unwrap().len() as u64;
+                (message, chain_len)
+            })
+            .collect()
+    }
+
+    /// The account method returning the account's key address.
+    const PUBKEY_ADDRESS_METHOD: MethodNum = 2;
+
+    #[test]
+    fn test_make_gas_free() {
+        // A gas limit too small for any message to succeed.
+        let mut message = test_message();
+        message.gas_limit = 1;
+
+        make_gas_free(&mut message);
+        assert!(message.gas_limit >= 1_000 * 10_000_000_000);
+        assert_eq!(message.gas_fee_cap, TokenAmount::from(0u8));
+        assert_eq!(message.gas_premium, TokenAmount::from(0u8));
+
+        // Creating an account takes far more than 10k gas.
+        let mut machine = scripted_machine();
+        let executor = InnerFvmMachine::default();
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        let message = Message {
+            to: key,
+            gas_limit: 10_000,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message.clone(),
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::SYS_OUT_OF_GAS.value() as u64);
+        assert_eq!(machine.0.state_tree().lookup_id(&key).unwrap(), None);
+
+        let options = FvmExecuteOptions {
+            gas_free: true,
+            ..Default::default()
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        assert!(resp.gas_used > 10_000);
+        assert_eq!(machine.0.state_tree().lookup_id(&key).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn test_apply_gas_ceiling() {
+        let mut message = test_message();
+
+        apply_gas_ceiling(&mut message, ApplyKind::Implicit, 0).unwrap();
+        assert_eq!(message.gas_limit, 10_000_000_000);
+
+        apply_gas_ceiling(&mut message, ApplyKind::Implicit, 1_000).unwrap();
+        assert_eq!(message.gas_limit, 1_000);
+
+        // The ceiling never raises the limit.
+        apply_gas_ceiling(&mut message, ApplyKind::Implicit, u64::MAX).unwrap();
+        assert_eq!(message.gas_limit, 1_000);
+
+        assert!(apply_gas_ceiling(&mut message, ApplyKind::Explicit, 1_000).is_err());
+        apply_gas_ceiling(&mut message, ApplyKind::Explicit, 0).unwrap();
+
+        // Creating an account takes far more than 10k gas, but well within the message's limit.
+        let mut machine = scripted_machine();
+        let executor = InnerFvmMachine::default();
+        let message = Message {
+            to: Address::new_bls(&[1; 48]).unwrap(),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let options = FvmExecuteOptions {
+            gas_ceiling: 10_000,
+            ..Default::default()
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message.clone(),
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::SYS_OUT_OF_GAS.value() as u64);
+        assert!(resp.gas_used <= 10_000);
+
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        assert!(resp.gas_used > 10_000);
+    }
+
+    #[test]
+    fn test_dump_state_json() {
+        let code = Cid::new_v1(
+            fvm_ipld_encoding::IPLD_RAW,
+            Code::Identity.digest(b"fil/7/system"),
+        );
+        let state = Cid::new_v1(
+            fvm_ipld_encoding::DAG_CBOR,
+            Code::Blake2b256.digest(b"state"),
+        );
+
+        let mut state_tree = StateTree::new(MemoryBlockstore::new(), StateTreeVersion::V4).unwrap();
+        state_tree
+            .set_actor(
+                &Address::new_id(0),
+                ActorState::new(code, state, TokenAmount::from(10u8), 3),
+            )
+            .unwrap();
+        let root = state_tree.flush().unwrap();
+        let names = HashMap::new();
+
+        let json = dump_state_json(&state_tree, &root, &names, usize::MAX).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json["root"], root.to_string());
+        let actors = json["actors"].as_array().unwrap();
+        assert_eq!(actors.len(), 1);
+        assert_eq!(actors[0]["address"], "f00");
+        assert_eq!(actors[0]["code"], code.to_string());
+        assert_eq!(actors[0]["name"], serde_json::Value::Null);
+        assert_eq!(actors[0]["state"], state.to_string());
+        assert_eq!(actors[0]["decoded_state"], serde_json::Value::Null);
+        assert_eq!(actors[0]["nonce"], 3);
+        assert_eq!(actors[0]["balance"], "10");
+
+        assert!(dump_state_json(&state_tree, &root, &names, 10).is_err());
+    }
+
+    #[test]
+    fn test_dump_builtin_state_json() {
+        let bs = MemoryBlockstore::new();
+        let root = empty_state_for(&bs, NetworkVersion::V15, None, "localnet").unwrap();
+        let manifest = import_actors(&bs, None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(&bs, &manifest).unwrap();
+
+        let state_tree = StateTree::new_from_root(&bs, &root).unwrap();
+        let json = dump_state_json(&state_tree, &root, &names, usize::MAX).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        let actors = json["actors"].as_array().unwrap();
+        let actor = |name: &str| {
+            actors
+                .iter()
+                .find(|actor| actor["name"] == name)
+                .unwrap()
+                .clone()
+        };
+        // The system actor's state is empty, and the init actor's is [address map, next id,
+        // network name].
+        assert_eq!(actor("system")["decoded_state"], serde_json::json!([]));
+        let init = actor("init")["decoded_state"].clone();
+        assert!(init[0]["/"].is_string());
+        assert_eq!(init[2], "localnet");
+    }
+
+    #[test]
+    fn test_state_root() {
+        let mut machine = scripted_machine();
+        let executor = InnerFvmMachine::default();
+        let options = FvmExecuteOptions {
+            state_roots: true,
+            ..Default::default()
+        };
+        let root = |root: Option<c_slice::Box<u8>>| Cid::try_from(&root.unwrap()[..]).unwrap();
+
+        // Two messages, each creating an account.
+        let mut roots = Vec::new();
+        for seed in 1..3 {
+            let message = Message {
+                to: Address::new_bls(&[seed; 48]).unwrap(),
+                ..self_send(SYSTEM_ACTOR_ID)
+            };
+            let resp = apply(
+                &executor,
+                &mut machine,
+                message,
+                ApplyKind::Implicit,
+                options,
+            )
+            .unwrap();
+            let (pre, post) = (root(resp.pre_state_root), root(resp.post_state_root));
+            assert_ne!(pre, post);
+            roots.push((pre, post));
+        }
+        assert_eq!(roots[1].0, roots[0].1);
+        assert_eq!(state_root(machine.0.state_tree_mut()).unwrap(), roots[1].1);
+    }
+
+    #[test]
+    fn test_allocation_budget() {
+        let mut unlimited = AllocationBudget::new(0);
+        unlimited.charge("return data", usize::MAX).unwrap();
+        unlimited.charge("trace", usize::MAX).unwrap();
+
+        let mut budget = AllocationBudget::new(1024);
+        budget.charge("trace", 1000).unwrap();
+        let err = budget.charge("return data", 1 << 20).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "execution exceeded the allocation budget of 1024 bytes while capturing the return data"
+        );
+
+        // A message returning an account's key address. The compact trace is charged exactly
+        // its size, so the trace and the return data take all of the response's budget.
+        let mut machine = scripted_machine();
+        create_account(&mut machine, 1);
+        let message = Message {
+            to: Address::new_id(100),
+            method_num: PUBKEY_ADDRESS_METHOD,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let executor = InnerFvmMachine {
+            compact_trace: true,
+            ..Default::default()
+        };
+        let mut apply_with_budget = |allocation_budget| {
+            let options = FvmExecuteOptions {
+                allocation_budget,
+                ..Default::default()
+            };
+            apply(
+                &executor,
+                &mut machine,
+                message.clone(),
+                ApplyKind::Implicit,
+                options,
+            )
+        };
+        let resp = apply_with_budget(0).unwrap();
+        let needed = resp.exec_trace.unwrap().len() as u64 + resp.return_len;
+        apply_with_budget(needed).unwrap();
+        let err = apply_with_budget(needed - 1).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "execution exceeded the allocation budget of {} bytes while capturing the return data",
+                needed - 1
+            )
+        );
+    }
+
+    #[test]
+    fn test_logged_flush() {
+        let write_log = WriteLog::new(1024);
+        let bs = WriteLogBlockstore::new(MemoryBlockstore::new(), write_log.clone());
+        let block = Block::new(fvm_ipld_encoding::IPLD_RAW, &b"flushed"[..]);
+        let mut flushed_root = None;
+
+        let (root, was_noop) = logged_flush(&write_log, &mut flushed_root, || {
+            bs.put(Code::Blake2b256, &block)
+        })
+        .unwrap();
+        assert!(!was_noop);
+        let writes = write_log.writes().unwrap();
+        assert_eq!(writes, vec![(root, b"flushed".to_vec())]);
+
+        // Nothing changed, so the flush is skipped and the log still holds the root's blocks.
+        let (cached, was_noop) =
+            logged_flush(&write_log, &mut flushed_root, || panic!("flushed again")).unwrap();
+        assert_eq!((cached, was_noop), (root, true));
+        assert_eq!(write_log.writes().unwrap(), writes);
+
+        // Writes outside of a flush aren't recorded.
+        bs.put(
+            Code::Blake2b256,
+            &Block::new(fvm_ipld_encoding::IPLD_RAW, &b"other"[..]),
+        )
+        .unwrap();
+        assert_eq!(write_log.writes().unwrap(), writes);
+    }
+
+    #[test]
+    fn test_trace_budget() {
+        let call = ExecutionEvent::Call {
+            from: 0,
+            to: Address::new_id(1),
+            method: 1,
+            params: RawBytes::new(vec![0; 1024]),
+            value: TokenAmount::default(),
+        };
+        let ret = ExecutionEvent::CallReturn(RawBytes::new(vec![0; 1024]));
+        // A call making 100 subcalls, each with 1KiB of params and return data.
+        let mut trace = vec![call.clone()];
+        for _ in 0..100 {
+            trace.extend([call.clone(), ret.clone()]);
+        }
+        trace.push(ret);
+
+        let mut consumed = 0;
+        let mut trace_iter = trace.iter().cloned().inspect(|_| consumed += 1);
+        let first = trace_iter.next().unwrap();
+        let mut budget = AllocationBudget::new(10 * 1024);
+        let err = build_lotus_trace(&first, &mut trace_iter, u64::MAX, &mut budget).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "execution exceeded the allocation budget of 10240 bytes while capturing the trace"
+        );
+        assert!(budget.check().is_err());
+        // Building stopped at the call that exceeded the budget.
+        assert_eq!(consumed, 11);
+
+        // Subcalls past the depth limit aren't charged.
+        let mut trace_iter = trace.into_iter();
+        let first = trace_iter.next().unwrap();
+        let mut budget = AllocationBudget::new(10 * 1024);
+        build_lotus_trace(&first, &mut trace_iter, 1, &mut budget).unwrap();
+        assert_eq!(budget.used, 2048);
+    }
+
+    #[test]
+    fn test_cap_return_data() {
+        let data = vec![0xab; 1 << 20];
+        assert_eq!(cap_return_data(data.clone(), 0), (data.clone(), false));
+        assert_eq!(
+            cap_return_data(data.clone(), data.len() as u64),
+            (data.clone(), false)
+        );
+
+        let (capped, truncated) = cap_return_data(data.clone(), 16);
+        assert!(truncated);
+        assert_eq!(capped, &data[..16]);
+
+        // An account's key address, returned in more than 16 bytes.
+        let mut machine = scripted_machine();
+        let key = create_account(&mut machine, 1);
+        let message = Message {
+            to: Address::new_id(100),
+            method_num: PUBKEY_ADDRESS_METHOD,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let executor = InnerFvmMachine {
+            max_return_size: 16,
+            ..Default::default()
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        let encoded = fvm_ipld_encoding::to_vec(&key).unwrap();
+        assert!(resp.return_truncated);
+        assert_eq!(resp.return_len, encoded.len() as u64);
+        assert_eq!(&resp.return_val.unwrap()[..], &encoded[..16]);
+    }
+
+    #[test]
+    fn test_manifest_cid() {
+        let bs = MemoryBlockstore::new();
+
+        // Pre-nv16, the manifest comes from the builtin bundle.
+        let manifest = import_actors(&bs, None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        assert!(bs.has(&manifest).unwrap());
+
+        // From nv16 onwards, it's read from the system actor's state.
+        assert_eq!(import_actors(&bs, None, NetworkVersion::V16).unwrap(), None);
+        let mut state_tree = StateTree::new(bs, StateTreeVersion::V4).unwrap();
+        assert_eq!(manifest_cid_from_state(&state_tree).unwrap(), None);
+
+        let state = state_tree
+            .store()
+            .put(
+                Code::Blake2b256,
+                &Block::new(DAG_CBOR, fvm_ipld_encoding::to_vec(&(manifest,)).unwrap()),
+            )
+            .unwrap();
+        state_tree
+            .set_actor(
+                &Address::new_id(0),
+                ActorState::new(manifest, state, TokenAmount::from(0u8), 0),
+            )
+            .unwrap();
+        assert_eq!(
+            manifest_cid_from_state(&state_tree).unwrap(),
+            Some(manifest)
+        );
+    }
+
+    #[test]
+    fn test_actor_nonce() {
+        let mut machine = scripted_machine();
+        let address = Address::new_id(100);
+        assert_eq!(actor_nonce(machine.0.state_tree(), &address).unwrap(), None);
+
+        let key = create_account(&mut machine, 1);
+        assert_eq!(
+            actor_nonce(machine.0.state_tree(), &address).unwrap(),
+            Some(0)
+        );
+
+        // Applying an explicit message from the account bumps its sequence, which can also be
+        // read through its key address.
+        let message = self_send(100);
+        let chain_len = fvm_ipld_encoding::to_vec(&message).unwrap().len();
+        let apply_ret = machine
+            .execute_message(message, ApplyKind::Explicit, chain_len)
+            .unwrap();
+        assert_eq!(apply_ret.msg_receipt.exit_code, ExitCode::OK);
+        assert_eq!(
+            actor_nonce(machine.0.state_tree(), &address).unwrap(),
+            Some(1)
+        );
+        assert_eq!(actor_nonce(machine.0.state_tree(), &key).unwrap(), Some(1));
+    }
+
+    /// Creates a state tree holding an init actor with an empty address map, which assigns IDs
+    /// from 100.
+    fn state_tree_with_init_actor(bs: &MemoryBlockstore) -> StateTree<&MemoryBlockstore> {
+        // An empty HAMT node.
+        let empty_map = bs
+            .put(
+                Code::Blake2b256,
+                &Block::new(DAG_CBOR, &[0x82, 0x40, 0x80][..]),
+            )
+            .unwrap();
+        let init_state = bs
+            .put(
+                Code::Blake2b256,
+                &Block::new(
+                    DAG_CBOR,
+                    fvm_ipld_encoding::to_vec(&(empty_map, 100u64, "test")).unwrap(),
+                ),
+            )
+            .unwrap();
+        let code = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"init"));
+        let mut state_tree = StateTree::new(bs, StateTreeVersion::V4).unwrap();
+        state_tree
+            .set_actor(
+                &Address::new_id(1),
+                ActorState::new(code, init_state, TokenAmount::from(0u8), 0),
+            )
+            .unwrap();
+        state_tree
+    }
+
+    #[test]
+    fn test_created_actor_id() {
+        let executor = InnerFvmMachine::default();
+        let options = FvmExecuteOptions {
+            receiver_info: true,
+            state_roots: true,
+            ..Default::default()
+        };
+        let key = Address::new_secp256k1(&[1; 65]).unwrap();
+        let message = Message {
+            to: key,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+
+        // Creating an account for the same key address from the same root assigns the same ID.
+        let mut machines = Vec::new();
+        for _ in 0..2 {
+            let mut machine = scripted_machine();
+            let resp = apply(
+                &executor,
+                &mut machine,
+                message.clone(),
+                ApplyKind::Implicit,
+                options,
+            )
+            .unwrap();
+            assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+            assert!(resp.actor_created);
+            assert_eq!(resp.created_actor_id, 100);
+            machines.push((resp.pre_state_root.unwrap().to_vec(), machine));
+        }
+        assert_eq!(machines[0].0, machines[1].0);
+        let mut machine = machines.pop().unwrap().1;
+        assert_eq!(machine.0.state_tree().lookup_id(&key).unwrap(), Some(100));
+
+        // Messages to addresses that already resolve don't create anything.
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap();
+        assert!(!resp.actor_created);
+        assert_eq!(resp.created_actor_id, 0);
+    }
+
+    #[test]
+    fn test_created_actors() {
+        let executor = InnerFvmMachine::default();
+        let mut machine = scripted_machine();
+        let sender = create_account(&mut machine, 1);
+        let keys = [
+            Address::new_bls(&[2; 48]).unwrap(),
+            Address::new_bls(&[3; 48]).unwrap(),
+        ];
+        // Two messages creating accounts, and one to an existing actor.
+        let messages = vec![
+            Message {
+                to: keys[0],
+                ..self_send(100)
+            },
+            Message {
+                to: keys[1],
+                sequence: 1,
+                ..self_send(100)
+            },
+            Message {
+                to: sender,
+                sequence: 2,
+                ..self_send(100)
+            },
+        ];
+        let options = FvmBatchOptions {
+            created_actors: true,
+            ..Default::default()
+        };
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(messages),
+            Duration::ZERO,
+            options,
+        )
+        .unwrap();
+        assert_eq!(resp.results.len(), 3);
+        assert!(resp
+            .results
+            .iter()
+            .all(|result| result.exit_code == ExitCode::OK.value() as u64));
+        assert_eq!(&resp.created_actors[..], &[101, 102]);
+        for (key, id) in keys.iter().zip([101, 102]) {
+            assert_eq!(machine.0.state_tree().lookup_id(key).unwrap(), Some(id));
+        }
+
+        // Nothing is created by a batch of existing receivers, and nothing is returned unless asked.
+        let message = Message {
+            to: keys[0],
+            sequence: 3,
+            ..self_send(100)
+        };
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(vec![message]),
+            Duration::ZERO,
+            options,
+        )
+        .unwrap();
+        assert!(resp.created_actors.is_empty());
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(vec![Message {
+                to: Address::new_bls(&[4; 48]).unwrap(),
+                sequence: 4,
+                ..self_send(100)
+            }]),
+            Duration::ZERO,
+            FvmBatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.results[0].exit_code, ExitCode::OK.value() as u64);
+        assert!(resp.created_actors.is_empty());
+    }
+
+    #[test]
+    fn test_all_or_nothing_batch() {
+        let executor = InnerFvmMachine::default();
+        let mut machine = scripted_machine();
+        create_account(&mut machine, 1);
+        let key = Address::new_bls(&[2; 48]).unwrap();
+        // Creating an account, then sending to a missing actor, which fails.
+        let messages = vec![
+            Message {
+                to: key,
+                ..self_send(100)
+            },
+            Message {
+                to: Address::new_id(999),
+                sequence: 1,
+                ..self_send(100)
+            },
+            Message {
+                sequence: 2,
+                ..self_send(100)
+            },
         ];
+        let options = FvmBatchOptions {
+            all_or_nothing: true,
+            created_actors: true,
+            ..Default::default()
+        };
 
-        let mut trace_iter = trace.into_iter();
+        let root = state_root(machine.0.state_tree_mut()).unwrap();
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(messages.clone()),
+            Duration::ZERO,
+            options,
+        )
+        .unwrap();
+        assert!(resp.failed);
+        assert_eq!(resp.failed_index, 1);
+        assert_eq!(resp.results.len(), 2);
+        assert_eq!(resp.results[0].exit_code, ExitCode::OK.value() as u64);
+        assert_eq!(
+            resp.results[1].exit_code,
+            ExitCode::SYS_INVALID_RECEIVER.value() as u64
+        );
+        assert!(resp.created_actors.is_empty());
+        assert_eq!(state_root(machine.0.state_tree_mut()).unwrap(), root);
+        assert_eq!(machine.0.state_tree().lookup_id(&key).unwrap(), None);
+        let sender = Address::new_id(100);
+        assert_eq!(
+            actor_nonce(machine.0.state_tree(), &sender).unwrap(),
+            Some(0)
+        );
 
-        let lotus_trace = build_lotus_trace(&trace_iter.next().unwrap(), &mut trace_iter).unwrap();
+        // Without the flag, every message is applied.
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(messages),
+            Duration::ZERO,
+            FvmBatchOptions::default(),
+        )
+        .unwrap();
+        assert!(!resp.failed);
+        assert_eq!(resp.results.len(), 3);
+        assert_eq!(
+            actor_nonce(machine.0.state_tree(), &sender).unwrap(),
+            Some(3)
+        );
+        assert_eq!(machine.0.state_tree().lookup_id(&key).unwrap(), Some(101));
 
-        assert!(trace_iter.next().is_none());
+        // The state tree can't be flushed inside the batch's transaction.
+        let options = FvmBatchOptions {
+            execute: FvmExecuteOptions {
+                state_roots: true,
+                ..Default::default()
+            },
+            ..options
+        };
+        let err = apply_batch(&executor, &mut machine, vec![], Duration::ZERO, options)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("all-or-nothing"));
+    }
 
-        assert_eq!(lotus_trace.subcalls.len(), 2);
-        assert_eq!(lotus_trace.subcalls[0].subcalls.len(), 0);
-        assert_eq!(lotus_trace.subcalls[1].subcalls.len(), 1);
-        assert_eq!(lotus_trace.subcalls[1].subcalls[0].subcalls.len(), 0);
+    #[test]
+    fn test_receiver_gas() {
+        let executor = InnerFvmMachine::default();
+        let mut machine = scripted_machine();
+        let sender = create_account(&mut machine, 1);
+        let other = create_account(&mut machine, 2);
+        // Two messages to the sender itself, one to another account, and one to a missing actor.
+        let messages = [sender, other, sender, Address::new_id(999)]
+            .into_iter()
+            .enumerate()
+            .map(|(sequence, to)| Message {
+                to,
+                sequence: sequence as u64,
+                ..self_send(100)
+            })
+            .collect();
+        let options = FvmBatchOptions {
+            receiver_gas: true,
+            ..Default::default()
+        };
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(messages),
+            Duration::ZERO,
+            options,
+        )
+        .unwrap();
+        let gas: Vec<_> = resp.results.iter().map(|result| result.gas_used).collect();
+        assert!(gas.iter().all(|&gas| gas > 0));
+        assert_eq!(
+            &resp.receiver_gas[..],
+            &[
+                FvmReceiverGas {
+                    receiver_id: 100,
+                    gas_used: gas[0] + gas[2],
+                },
+                FvmReceiverGas {
+                    receiver_id: 101,
+                    gas_used: gas[1],
+                },
+                FvmReceiverGas {
+                    receiver_id: UNRESOLVED_ID,
+                    gas_used: gas[3],
+                },
+            ]
+        );
+
+        let resp = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(vec![Message {
+                sequence: 4,
+                ..self_send(100)
+            }]),
+            Duration::ZERO,
+            FvmBatchOptions::default(),
+        )
+        .unwrap();
+        assert!(resp.receiver_gas.is_empty());
+    }
+
+    #[test]
+    fn test_batch_miner_tips() {
+        // A machine with a funded f0100, and a reward actor to pay the tips to. Its code and state
+        // don't matter, paying it doesn't run it.
+        let funded_machine = || {
+            let mut machine = scripted_machine();
+            create_account(&mut machine, 1);
+            let state_tree = machine.0.state_tree_mut();
+            let sender = Address::new_id(100);
+            let mut actor = state_tree.get_actor(&sender).unwrap().unwrap();
+            actor.balance = TokenAmount::from(100_000_000u64);
+            state_tree.set_actor(&sender, actor.clone()).unwrap();
+            let reward = ActorState::new(actor.code, actor.state, TokenAmount::from(0u8), 0);
+            state_tree.set_actor(&Address::new_id(2), reward).unwrap();
+            machine
+        };
+        let messages: Vec<_> = [1u8, 2]
+            .into_iter()
+            .enumerate()
+            .map(|(sequence, premium)| Message {
+                sequence: sequence as u64,
+                gas_limit: 1_000_000,
+                gas_fee_cap: TokenAmount::from(10u8),
+                gas_premium: TokenAmount::from(premium),
+                ..self_send(100)
+            })
+            .collect();
+
+        let executor = InnerFvmMachine::default();
+        let mut machine = funded_machine();
+        let batch = apply_batch(
+            &executor,
+            &mut machine,
+            with_chain_lens(messages.clone()),
+            Duration::ZERO,
+            FvmBatchOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(batch.results.len(), 2);
+
+        // Each message is tipped as if it was applied on its own.
+        let mut machine = funded_machine();
+        for (message, result) in messages.into_iter().zip(batch.results.iter()) {
+            let gas_limit = message.gas_limit as u64;
+            let premium = message.gas_premium.clone();
+            let single = apply(
+                &executor,
+                &mut machine,
+                message,
+                ApplyKind::Explicit,
+                FvmExecuteOptions::default(),
+            )
+            .unwrap();
+            assert_eq!(single.exit_code, ExitCode::OK.value() as u64);
+            assert_eq!(result.exit_code, ExitCode::OK.value() as u64);
+            assert_eq!(
+                (result.miner_tip_hi, result.miner_tip_lo),
+                (single.miner_tip_hi, single.miner_tip_lo)
+            );
+            assert_eq!(TokenAmount::from(result.miner_tip_lo), premium * gas_limit);
+        }
+    }
+
+    #[test]
+    fn test_resolve_addresses() {
+        let bs = MemoryBlockstore::new();
+        let mut state_tree = state_tree_with_init_actor(&bs);
+        let key = Address::new_secp256k1(&[1; 65]).unwrap();
+        let id = state_tree.register_new_address(&key).unwrap();
+
+        let unknown = Address::new_secp256k1(&[2; 65]).unwrap();
+        assert_eq!(
+            resolve_addresses(&state_tree, &[key, unknown, Address::new_id(5), key]).unwrap(),
+            vec![id, UNRESOLVED_ID, 5, id]
+        );
+        assert!(resolve_addresses(&state_tree, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_inclusion_gas() {
+        let gas = |size| inclusion_gas(NetworkVersion::V15, size).unwrap();
+        // A fixed base, plus 1300 gas per byte of storage.
+        assert_eq!(gas(0), 38863 + 36 * 1300);
+        assert_eq!(gas(1) - gas(0), 1300);
+        let size = fvm_ipld_encoding::to_vec(&test_message()).unwrap().len() as u64;
+        assert_eq!(gas(size), gas(0) + size * 1300);
+
+        // Value transfers that only differ in their (ignored) params all cost the same to execute,
+        // on top of their inclusion gas.
+        let mut machine = scripted_machine();
+        create_account(&mut machine, 1);
+        let mut execution_gas = Vec::new();
+        for (sequence, params_len) in [0, 100, 1_000].into_iter().enumerate() {
+            let message = Message {
+                sequence: sequence as u64,
+                params: RawBytes::new(vec![0; params_len]),
+                ..self_send(100)
+            };
+            let size = fvm_ipld_encoding::to_vec(&message).unwrap().len();
+            let apply_ret = machine
+                .execute_message(message, ApplyKind::Explicit, size)
+                .unwrap();
+            assert_eq!(apply_ret.msg_receipt.exit_code, ExitCode::OK);
+            let gas_used = apply_ret.msg_receipt.gas_used as u64;
+            assert!(gas_used > gas(size as u64));
+            execution_gas.push(gas_used - gas(size as u64));
+        }
+        assert!(execution_gas.windows(2).all(|w| w[0] == w[1]));
+    }
+
+    #[test]
+    fn test_subcall_gas() {
+        let mut machine = scripted_machine();
+        let signer = create_account(&mut machine, 1);
+        let executor = InnerFvmMachine::default();
+
+        // A call to an account makes no subcalls.
+        let resp = apply(
+            &executor,
+            &mut machine,
+            Message {
+                to: signer,
+                method_num: PUBKEY_ADDRESS_METHOD,
+                ..self_send(SYSTEM_ACTOR_ID)
+            },
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        assert_eq!(resp.subcall_gas, 0);
+        assert_eq!(resp.direct_gas, resp.gas_used);
+
+        // Creating a multisig through the init actor, which calls the multisig's constructor.
+        let manifest = import_actors(machine.0.blockstore(), None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let multisig_code = builtin_actor_names(machine.0.blockstore(), &manifest)
+            .unwrap()
+            .into_iter()
+            .find_map(|(code, name)| (name == "multisig").then(|| code))
+            .unwrap();
+        let constructor_params = RawBytes::serialize((vec![signer], 1u64, 0i64, 0i64)).unwrap();
+        let resp = apply(
+            &executor,
+            &mut machine,
+            Message {
+                to: Address::new_id(1),
+                method_num: 2,
+                params: RawBytes::serialize((multisig_code, constructor_params)).unwrap(),
+                ..self_send(SYSTEM_ACTOR_ID)
+            },
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        assert!(resp.direct_gas > 0);
+        assert!(resp.subcall_gas > 0);
+        assert_eq!(resp.direct_gas + resp.subcall_gas, resp.gas_used);
+    }
+
+    #[test]
+    fn test_gas_params() {
+        let params = |nv: NetworkVersion| {
+            let resp = fvm_gas_params(nv as u64);
+            assert!(resp.error_msg.is_empty());
+            fvm_ipld_encoding::from_slice::<GasParams>(&resp.value).unwrap()
+        };
+        let (v15, v16) = (params(NetworkVersion::V15), params(NetworkVersion::V16));
+        assert_eq!(v15.message_per_byte, 1300);
+        assert_eq!(
+            v15.message_base,
+            inclusion_gas(NetworkVersion::V15, 0).unwrap() as i64
+        );
+        assert!(v15.send_invoke_method > 0);
+        // nv16 introduced syscall gas.
+        assert_eq!(v15.syscall, 0);
+        assert!(v16.syscall > 0);
+        assert_ne!(v15, v16);
+
+        assert!(!fvm_gas_params(u64::MAX).error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_actor_state_block() {
+        let bs = MemoryBlockstore::new();
+        let state = (Address::new_id(101), 42u64);
+        let state_cid = bs
+            .put(
+                Code::Blake2b256,
+                &Block::new(DAG_CBOR, fvm_ipld_encoding::to_vec(&state).unwrap()),
+            )
+            .unwrap();
+        let code = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"account"));
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        let address = Address::new_id(100);
+        state_tree
+            .set_actor(
+                &address,
+                ActorState::new(code, state_cid, TokenAmount::from(0u8), 0),
+            )
+            .unwrap();
+
+        let block = actor_state_block(&bs, &state_tree, &address)
+            .unwrap()
+            .unwrap();
+        let decoded: (Address, u64) = fvm_ipld_encoding::from_slice(&block).unwrap();
+        assert_eq!(decoded, state);
+        assert_eq!(
+            actor_state_block(&bs, &state_tree, &Address::new_id(101)).unwrap(),
+            None
+        );
+
+        // An account's state is its key address.
+        let mut machine = scripted_machine();
+        let executor = InnerFvmMachine::default();
+        let options = FvmExecuteOptions {
+            receiver_state: true,
+            ..Default::default()
+        };
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        let message = Message {
+            to: key,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::OK.value() as u64);
+        let (address,): (Address,) =
+            fvm_ipld_encoding::from_slice(&resp.receiver_state.unwrap()).unwrap();
+        assert_eq!(address, key);
+
+        // Messages to missing actors have no receiver state.
+        let message = Message {
+            to: Address::new_id(999),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap();
+        assert!(resp.receiver_state.is_none());
+    }
+
+    #[test]
+    fn test_receiver_kind() {
+        let bs = MemoryBlockstore::new();
+        let manifest = import_actors(&bs, None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(&bs, &manifest).unwrap();
+        let code = |name: &str| *names.iter().find(|(_, n)| *n == name).unwrap().0;
+
+        assert_eq!(
+            receiver_kind(&names, &code("account")),
+            FvmReceiverKind::Account
+        );
+        assert_eq!(
+            receiver_kind(&names, &code("init")),
+            FvmReceiverKind::Builtin
+        );
+        let unknown = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"user actor"));
+        assert_eq!(receiver_kind(&names, &unknown), FvmReceiverKind::Unknown);
+    }
+
+    #[test]
+    fn test_builtin_method_name() {
+        let bs = MemoryBlockstore::new();
+        let manifest = import_actors(&bs, None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(&bs, &manifest).unwrap();
+        let code = |name: &str| *names.iter().find(|(_, n)| *n == name).unwrap().0;
+
+        let miner = code("storageminer");
+        assert_eq!(builtin_method_name(&names, &miner, 0), Some("Send"));
+        assert_eq!(builtin_method_name(&names, &miner, 1), Some("Constructor"));
+        assert_eq!(
+            builtin_method_name(&names, &miner, 5),
+            Some("SubmitWindowedPoSt")
+        );
+        assert_eq!(builtin_method_name(&names, &miner, 1000), None);
+        assert_eq!(
+            builtin_method_name(&names, &code("storagemarket"), 4),
+            Some("PublishStorageDeals")
+        );
+
+        let unknown = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"user actor"));
+        assert_eq!(builtin_method_name(&names, &unknown, 0), None);
+    }
+
+    #[test]
+    fn test_import_actors_explicit_manifest() {
+        let bs = MemoryBlockstore::new();
+        let manifest = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"manifest"));
+        for nv in [NetworkVersion::V15, NetworkVersion::V16] {
+            assert_eq!(
+                import_actors(&bs, Some(manifest), nv).unwrap(),
+                Some(manifest)
+            );
+        }
+    }
+
+    #[test]
+    fn test_supported_network_versions() {
+        let bs = MemoryBlockstore::new();
+        let versions = supported_network_versions();
+        assert_eq!(
+            versions
+                .iter()
+                .map(|version| version.network_version)
+                .collect::<Vec<_>>(),
+            vec![15, 16]
+        );
+        for version in &versions {
+            let nv = NetworkVersion::try_from(version.network_version as u32).unwrap();
+            let imported = import_actors(&bs, None, nv).unwrap();
+            assert_eq!(
+                version.actor_source,
+                actor_source(false, imported.is_some())
+            );
+        }
+        assert!(import_actors(&bs, None, NetworkVersion::V14).is_err());
+    }
+
+    #[test]
+    fn test_empty_state_for() {
+        let bs = MemoryBlockstore::new();
+        let root = empty_state_for(&bs, NetworkVersion::V15, None, "localnet").unwrap();
+        let manifest = import_actors(&bs, None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(&bs, &manifest).unwrap();
+
+        // v7 actors, the manifest comes from the bundle.
+        let state_tree = StateTree::new_from_root(&bs, &root).unwrap();
+        let system = state_tree
+            .get_actor(&Address::new_id(SYSTEM_ACTOR_ID))
+            .unwrap()
+            .unwrap();
+        assert_eq!(names.get(&system.code).map(String::as_str), Some("system"));
+        assert_eq!(bs.get(&system.state).unwrap().unwrap(), vec![0x80]);
+        let init = state_tree.get_actor(&Address::new_id(1)).unwrap().unwrap();
+        assert_eq!(names.get(&init.code).map(String::as_str), Some("init"));
+
+        // Without a bundle, machines read the manifest from the state.
+        assert!(empty_state_for(&bs, NetworkVersion::V16, None, "localnet").is_err());
+        let root = empty_state_for(&bs, NetworkVersion::V16, Some(manifest), "localnet").unwrap();
+        let state_tree = StateTree::new_from_root(&bs, &root).unwrap();
+        assert_eq!(
+            manifest_cid_from_state(&state_tree).unwrap(),
+            Some(manifest)
+        );
+
+        assert!(empty_state_for(&bs, NetworkVersion::V14, None, "localnet").is_err());
+    }
+
+    #[test]
+    fn test_execute_checked() {
+        let executor = scripted_executor(NetworkVersion::V15, ScriptedExterns::default()).unwrap();
+        let mut executor = ThreadedExecutor(executor);
+        // Sending to a new key address creates an account actor.
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        let message = Message {
+            to: key,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+
+        // Rejecting the execution reverts the message, even though it succeeded.
+        let err = execute_checked(
+            &mut executor,
+            message.clone(),
+            ApplyKind::Implicit,
+            0,
+            |ret| {
+                assert_eq!(ret.msg_receipt.exit_code, ExitCode::OK);
+                Err(anyhow::anyhow!("rejected"))
+            },
+        )
+        .unwrap_err();
+        assert_eq!(err.to_string(), "rejected");
+        assert_eq!(executor.0.state_tree().lookup_id(&key).unwrap(), None);
+
+        execute_checked(&mut executor, message, ApplyKind::Implicit, 0, |_| Ok(())).unwrap();
+        assert_eq!(executor.0.state_tree().lookup_id(&key).unwrap(), Some(100));
+    }
+
+    #[test]
+    fn test_execute_with_access_list() {
+        fn actor_state(bs: &impl Blockstore, root: &Cid, id: u64) -> Cid {
+            let state_tree = StateTree::new_from_root(bs, root).unwrap();
+            state_tree
+                .get_actor(&Address::new_id(id))
+                .unwrap()
+                .unwrap()
+                .state
+        }
+        let access_log = AccessLog::default();
+        let executor = scripted_executor_with_access_log(
+            NetworkVersion::V15,
+            ScriptedExterns::default(),
+            access_log.clone(),
+        )
+        .unwrap();
+        let mut executor = ThreadedExecutor(executor);
+        // An account to send from, at f0100.
+        let sender = Address::new_bls(&[1; 48]).unwrap();
+        let message = Message {
+            to: sender,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        executor
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap();
+        let pre_root = executor.0.flush().unwrap();
+        let pre_init_state = actor_state(executor.0.blockstore(), &pre_root, 1);
+
+        // Sending to a new key address has the init actor assign it f0101.
+        let message = Message {
+            to: Address::new_bls(&[2; 48]).unwrap(),
+            ..self_send(100)
+        };
+        let (apply_ret, root, (actors, reads, writes)) =
+            execute_with_access_list(&mut executor, &access_log, message, 0, |executor| {
+                executor.0.flush()
+            })
+            .unwrap();
+        assert_eq!(apply_ret.msg_receipt.exit_code, ExitCode::OK);
+        assert!(actors.contains(&Address::new_id(100)));
+        assert!(actors.contains(&Address::new_id(101)));
+
+        // It read the init actor's state, and the flush wrote its new state, along with the new
+        // account's and the state root.
+        assert!(reads.contains(&pre_init_state));
+        assert!(!writes.contains(&pre_init_state));
+        assert!(writes.contains(&actor_state(executor.0.blockstore(), &root, 1)));
+        assert!(writes.contains(&actor_state(executor.0.blockstore(), &root, 101)));
+        assert!(writes.contains(&root));
+        assert!(!reads.contains(&root));
+    }
+
+    #[test]
+    fn test_empty_state_machine() {
+        let mut executor =
+            scripted_executor(NetworkVersion::V15, ScriptedExterns::default()).unwrap();
+
+        // Sending to a new key address has the init actor assign it the first free ID, and
+        // constructs an account actor there.
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        let message = Message {
+            to: key,
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let apply_ret = executor
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap();
+        assert_eq!(apply_ret.msg_receipt.exit_code, ExitCode::OK);
+
+        let root = executor.flush().unwrap();
+        let state_tree = StateTree::new_from_root(executor.blockstore(), &root).unwrap();
+        assert_eq!(state_tree.lookup_id(&key).unwrap(), Some(100));
+        let manifest = import_actors(executor.blockstore(), None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(executor.blockstore(), &manifest).unwrap();
+        let account = state_tree
+            .get_actor(&Address::new_id(100))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            names.get(&account.code).map(String::as_str),
+            Some("account")
+        );
+    }
+
+    #[test]
+    fn test_actor_source() {
+        let bs = MemoryBlockstore::new();
+        let manifest = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"manifest"));
+        let source = |manifest_cid: Option<Cid>, nv| {
+            let imported = import_actors(&bs, manifest_cid, nv).unwrap();
+            actor_source(manifest_cid.is_some(), imported.is_some())
+        };
+
+        assert_eq!(
+            source(Some(manifest), NetworkVersion::V15),
+            FvmActorSource::Override
+        );
+        assert_eq!(
+            source(Some(manifest), NetworkVersion::V16),
+            FvmActorSource::Override
+        );
+        assert_eq!(source(None, NetworkVersion::V15), FvmActorSource::Builtin);
+        assert_eq!(source(None, NetworkVersion::V16), FvmActorSource::FromState);
+    }
+
+    #[test]
+    fn test_get_unflushed_block() {
+        // The same blockstore stack as the machine's.
+        let base = MemoryBlockstore::new();
+        let bs = BufferedBlockstore::new(OverlayBlockstore::new(HashMap::new(), &base));
+
+        let block = Block::new(fvm_ipld_encoding::IPLD_RAW, &b"written"[..]);
+        let cid = bs.put(Code::Blake2b256, &block).unwrap();
+        assert_eq!(bs.get(&cid).unwrap(), Some(b"written".to_vec()));
+        assert!(!base.has(&cid).unwrap());
+
+        // The state of an account created by a message, which stays in the machine's buffered
+        // blockstore until it's flushed.
+        let access_log = AccessLog::default();
+        let executor = scripted_executor_with_access_log(
+            NetworkVersion::V15,
+            ScriptedExterns::default(),
+            access_log.clone(),
+        )
+        .unwrap();
+        let mut machine = ThreadedExecutor(executor);
+        access_log.start();
+        let key = create_account(&mut machine, 1);
+        let state = machine
+            .0
+            .state_tree()
+            .get_actor(&key)
+            .unwrap()
+            .unwrap()
+            .state;
+        let resp = get_block(machine.0.blockstore(), &state).unwrap();
+        assert!(resp.found);
+        let (address,): (Address,) = fvm_ipld_encoding::from_slice(&resp.block).unwrap();
+        assert_eq!(address, key);
+        assert!(!access_log.stop().1.contains(&state));
+
+        let missing = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"missing"));
+        assert!(!get_block(machine.0.blockstore(), &missing).unwrap().found);
+    }
+
+    #[test]
+    fn test_message_size() {
+        let message = fvm_ipld_encoding::to_vec(&test_message()).unwrap();
+        let resp = fvm_message_size(message[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(resp.value, message.len() as u64);
+
+        let resp = fvm_message_size(message[1..].into());
+        assert!(!resp.error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_encode_message() {
+        let message = test_message();
+        let resp = fvm_encode_message(
+            message.from.to_bytes()[..].into(),
+            message.to.to_bytes()[..].into(),
+            message.sequence,
+            0,
+            1_000,
+            message.method_num,
+            message.params.bytes().into(),
+            message.gas_limit as u64,
+            0,
+            100,
+            0,
+            10,
+        );
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(
+            &resp.value[..],
+            &fvm_ipld_encoding::to_vec(&message).unwrap()[..]
+        );
+
+        // The encoding is accepted by the execute path's decoding.
+        let decoded: Message = fvm_ipld_encoding::from_slice(&resp.value).unwrap();
+        assert_eq!(decoded, message);
+        assert!(fvm_message_size(resp.value[..].into()).error_msg.is_empty());
+
+        let resp = fvm_encode_message(
+            message.from.to_bytes()[..].into(),
+            [0xffu8][..].into(),
+            0,
+            0,
+            0,
+            0,
+            [0u8; 0][..].into(),
+            u64::MAX,
+            0,
+            0,
+            0,
+            0,
+        );
+        assert!(!resp.error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_verify_message_signature() {
+        let message = test_message();
+        let cid = Cid::new_v1(
+            fvm_ipld_encoding::DAG_CBOR,
+            Code::Blake2b256.digest(&fvm_ipld_encoding::to_vec(&message).unwrap()),
+        );
+
+        let private_key = PrivateKey::generate(&mut rand::rngs::OsRng);
+        let key = Address::new_bls(&private_key.public_key().as_bytes()).unwrap();
+        let signature = Signature::new_bls(private_key.sign(cid.to_bytes()).as_bytes());
+        verify_message_signature(&message, &signature, &key).unwrap();
+
+        let mut other = message;
+        other.sequence += 1;
+        let err = verify_message_signature(&other, &signature, &key).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("message signature verification failed"));
+    }
+
+    #[test]
+    fn test_timed_lock() {
+        let mutex = Arc::new(Mutex::new(()));
+        let (guard, wait) = timed_lock(&mutex);
+        assert!(wait < Duration::from_secs(1));
+
+        // Hold the lock while another thread waits for it.
+        let (locked_tx, locked_rx) = mpsc::channel();
+        let waiter = {
+            let mutex = mutex.clone();
+            thread::spawn(move || {
+                locked_tx.send(()).unwrap();
+                timed_lock(&mutex).1
+            })
+        };
+        locked_rx.recv().unwrap();
+        thread::sleep(Duration::from_millis(50));
+        drop(guard);
+        assert!(waiter.join().unwrap() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn test_exec_ids() {
+        let executor = InnerFvmMachine {
+            machine: None,
+            flushed_root: Mutex::new(None),
+            max_return_size: 0,
+            compact_trace: false,
+            lotus_trace_format: FvmLotusTraceFormat::Fvm,
+            manifest_cid: None,
+            builtin_actor_names: HashMap::new(),
+            recent_executions: Mutex::new(RecentExecutions::new(3)),
+            stream_summaries: false,
+            block_cache: BlockCache::default(),
+            read_batch: ReadBatch::default(),
+            blockstore_id: 0,
+            read_only: false,
+            write_log: WriteLog::default(),
+            access_log: AccessLog::default(),
+        };
+
+        let ids: Vec<_> = (0..3).map(|_| next_exec_id()).collect();
+        assert!(ids[0] > 0);
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        for &exec_id in &ids {
+            let resp = FvmMachineExecuteResponse {
+                exec_id,
+                ..Default::default()
+            };
+            executor.record_execution(Address::new_id(100), 0, &resp);
+        }
+
+        let recorded: Vec<_> = executor
+            .recent_executions
+            .lock()
+            .unwrap()
+            .entries()
+            .into_iter()
+            .map(|summary| summary.exec_id)
+            .collect();
+        assert_eq!(recorded, ids);
+    }
+
+    #[test]
+    fn test_verify_manifest() {
+        let manifest = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"manifest"));
+        let executor = InnerFvmMachine {
+            machine: None,
+            flushed_root: Mutex::new(None),
+            max_return_size: 0,
+            compact_trace: false,
+            lotus_trace_format: FvmLotusTraceFormat::Fvm,
+            manifest_cid: Some(manifest),
+            builtin_actor_names: HashMap::new(),
+            recent_executions: Mutex::new(RecentExecutions::default()),
+            stream_summaries: false,
+            block_cache: BlockCache::default(),
+            read_batch: ReadBatch::default(),
+            blockstore_id: 0,
+            read_only: false,
+            write_log: WriteLog::default(),
+            access_log: AccessLog::default(),
+        };
+
+        let resp = fvm_machine_verify_manifest(&executor, manifest.to_bytes()[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert!(resp.value);
+
+        let other = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"other manifest"));
+        let resp = fvm_machine_verify_manifest(&executor, other.to_bytes()[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert!(!resp.value);
+
+        let resp = fvm_machine_verify_manifest(&executor, [0xffu8][..].into());
+        assert!(!resp.error_msg.is_empty());
+    }
+
+    #[test]
+    fn test_read_only() {
+        let manifest = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"manifest"));
+        let executor = InnerFvmMachine {
+            manifest_cid: Some(manifest),
+            read_only: true,
+            ..Default::default()
+        };
+        let read_only = |error_msg: &[u8]| assert_eq!(error_msg, b"the machine is read-only");
+
+        let message = fvm_ipld_encoding::to_vec(&test_message()).unwrap();
+        let resp = fvm_machine_execute_message(
+            &executor,
+            message[..].into(),
+            0,
+            0,
+            FvmExecuteOptions::default(),
+        );
+        read_only(&resp.error_msg);
+        let resp = fvm_machine_execute_and_flush(&executor, message[..].into(), 0);
+        read_only(&resp.error_msg);
+        let resp = fvm_machine_execute_for_root(&executor, message[..].into(), 0);
+        read_only(&resp.error_msg);
+        read_only(&fvm_machine_flush(&executor).error_msg);
+
+        // Queries still work.
+        let resp = fvm_machine_verify_manifest(&executor, manifest.to_bytes()[..].into());
+        assert!(resp.error_msg.is_empty());
+        assert!(resp.value);
+    }
+
+    #[test]
+    fn test_prefetch_without_read_cache() {
+        let resp = fvm_machine_prefetch_state(&InnerFvmMachine::default());
+        assert_eq!(
+            &resp.error_msg[..],
+            b"the machine has no read cache to prefetch into"
+        );
+    }
+
+    #[test]
+    fn test_penalty_breakdown() {
+        assert!(penalty_breakdown(&TokenAmount::from(0u32)).is_empty());
+
+        // A penalty spanning both halves of the response's hi/lo split.
+        let penalty = TokenAmount::from((1u128 << u64::BITS) | 2);
+        let entries = penalty_breakdown(&penalty);
+        assert_eq!(
+            entries,
+            vec![PenaltyEntry {
+                cause: "total".to_owned(),
+                amount: penalty.clone(),
+            }]
+        );
+
+        let decoded: Vec<PenaltyEntry> =
+            fvm_ipld_encoding::from_slice(&fvm_ipld_encoding::to_vec(&entries).unwrap()).unwrap();
+        let total: TokenAmount = decoded.into_iter().map(|entry| entry.amount).sum();
+        assert_eq!(total, penalty);
+    }
+
+    #[test]
+    fn test_params_codec() {
+        assert_eq!(params_codec(2, Some("Exec")), DAG_CBOR);
+        // Plain value transfers, and methods we know nothing about.
+        assert_eq!(params_codec(0, Some("Send")), 0);
+        assert_eq!(params_codec(2, None), 0);
+
+        let code = |name: &[u8]| Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(name));
+        let (init, evm) = (code(b"init"), code(b"evm"));
+        let names = HashMap::from([(init, "init".to_owned())]);
+        let method_name = |code: &Cid, method| builtin_method_name(&names, code, method);
+        assert_eq!(params_codec(2, method_name(&init, 2)), DAG_CBOR);
+        assert_eq!(params_codec(3, method_name(&init, 3)), 0);
+        assert_eq!(params_codec(2, method_name(&evm, 2)), 0);
+    }
+
+    #[test]
+    fn test_decode_apply_kind() {
+        assert!(matches!(decode_apply_kind(0), Ok(ApplyKind::Explicit)));
+        assert!(matches!(decode_apply_kind(1), Ok(ApplyKind::Implicit)));
+        assert_eq!(
+            decode_apply_kind(2).unwrap_err().to_string(),
+            "unknown apply kind: 2"
+        );
+    }
+
+    #[test]
+    fn test_exit_code_name() {
+        for (code, name) in [
+            (ExitCode::OK, "OK"),
+            (ExitCode::SYS_SENDER_INVALID, "SYS_SENDER_INVALID"),
+            (ExitCode::SYS_OUT_OF_GAS, "SYS_OUT_OF_GAS"),
+            (ExitCode::USR_ILLEGAL_ARGUMENT, "USR_ILLEGAL_ARGUMENT"),
+            (ExitCode::USR_NOT_FOUND, "USR_NOT_FOUND"),
+            (ExitCode::USR_ASSERTION_FAILED, "USR_ASSERTION_FAILED"),
+        ] {
+            assert_eq!(exit_code_name(code.value() as u64), Some(name));
+        }
+        for reserved in [3, 8, 12, 13, 14, 15] {
+            assert_eq!(exit_code_name(reserved), None);
+        }
+        assert_eq!(exit_code_name(25), None);
+        assert_eq!(exit_code_name(u64::MAX), None);
+    }
+
+    #[test]
+    fn test_exit_code_class() {
+        for (code, class) in [
+            (ExitCode::OK, FvmExitCodeClass::NoError),
+            (ExitCode::SYS_OUT_OF_GAS, FvmExitCodeClass::System),
+            (ExitCode::new(15), FvmExitCodeClass::System),
+            (ExitCode::USR_ILLEGAL_ARGUMENT, FvmExitCodeClass::User),
+            (ExitCode::USR_FORBIDDEN, FvmExitCodeClass::User),
+            (ExitCode::new(1000), FvmExitCodeClass::User),
+        ] {
+            assert_eq!(exit_code_class(code), class);
+        }
+    }
+
+    #[test]
+    fn test_params_decode_failed() {
+        // An account's pubkey address method, sent garbage params.
+        assert!(params_decode_failed(
+            ExitCode::USR_SERIALIZATION,
+            2,
+            FvmReceiverKind::Account
+        ));
+        assert!(!params_decode_failed(
+            ExitCode::USR_ILLEGAL_ARGUMENT,
+            2,
+            FvmReceiverKind::Account
+        ));
+        assert!(!params_decode_failed(
+            ExitCode::OK,
+            2,
+            FvmReceiverKind::Account
+        ));
+        assert!(!params_decode_failed(
+            ExitCode::USR_SERIALIZATION,
+            METHOD_SEND,
+            FvmReceiverKind::Account
+        ));
+        assert!(!params_decode_failed(
+            ExitCode::USR_SERIALIZATION,
+            2,
+            FvmReceiverKind::Unknown
+        ));
+
+        // Calling the init actor's exec method with params that aren't CBOR, without asking for
+        // the receiver info.
+        let mut machine = scripted_machine();
+        let manifest = import_actors(machine.0.blockstore(), None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let executor = InnerFvmMachine {
+            builtin_actor_names: builtin_actor_names(machine.0.blockstore(), &manifest).unwrap(),
+            ..Default::default()
+        };
+        let exec = |params| Message {
+            to: Address::new_id(1),
+            method_num: 2,
+            params: RawBytes::new(params),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            exec(vec![0xff]),
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(resp.exit_code, ExitCode::USR_SERIALIZATION.value() as u64);
+        assert!(resp.params_decode_failed);
+        assert_eq!(resp.receiver_kind, FvmReceiverKind::Unknown);
+
+        // Well-formed params that the actor rejects for another reason: there's no such code.
+        let params = RawBytes::serialize((
+            Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"no actor")),
+            RawBytes::default(),
+        ))
+        .unwrap();
+        let resp = apply(
+            &executor,
+            &mut machine,
+            exec(params.into()),
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_ne!(resp.exit_code, ExitCode::OK.value() as u64);
+        assert!(!resp.params_decode_failed);
+    }
+
+    #[test]
+    fn test_effective_fees() {
+        let fees = |base_fee: u32, fee_cap: u32, premium: u32| {
+            let (base_fee, premium) = effective_fees(
+                &TokenAmount::from(base_fee),
+                &TokenAmount::from(fee_cap),
+                &TokenAmount::from(premium),
+            );
+            (
+                u32::try_from(base_fee).unwrap(),
+                u32::try_from(premium).unwrap(),
+            )
+        };
+
+        // The fee cap covers the base fee and the premium.
+        assert_eq!(fees(100, 200, 10), (100, 10));
+        // The fee cap covers the base fee, but only part of the premium.
+        assert_eq!(fees(100, 105, 10), (100, 5));
+        // The fee cap is below the base fee: the base fee is clamped and no premium is paid.
+        assert_eq!(fees(100, 80, 10), (80, 0));
+    }
+
+    #[test]
+    fn test_balance_margin() {
+        let message = test_message();
+        let max_cost = &message.value + &message.gas_fee_cap * message.gas_limit;
+
+        // A sender that can barely afford the message.
+        let balance = &max_cost + TokenAmount::from(5u8);
+        assert_eq!(
+            balance_margin(&balance, &message, true),
+            TokenAmount::from(5u8)
+        );
+        assert_eq!(
+            balance_margin(&max_cost, &message, true),
+            TokenAmount::zero()
+        );
+        // One that can't.
+        let balance = &max_cost - TokenAmount::from(5u8);
+        assert_eq!(
+            balance_margin(&balance, &message, true),
+            TokenAmount::from(-5)
+        );
+        // Implicit messages only need to cover the value.
+        assert_eq!(
+            balance_margin(&message.value, &message, false),
+            TokenAmount::zero()
+        );
+    }
+
+    #[test]
+    fn test_check_gas_used() {
+        let gas_limit = test_message().gas_limit;
+        check_gas_used(gas_limit / 2, gas_limit).unwrap();
+        check_gas_used(gas_limit, gas_limit).unwrap();
+        assert!(check_gas_used(gas_limit + 1, gas_limit).is_err());
+    }
+
+    #[test]
+    fn test_check_receipt_ok() {
+        let mut resp = FvmMachineExecuteResponse::default();
+        check_receipt_ok(&resp).unwrap();
+
+        resp.exit_code = ExitCode::USR_FORBIDDEN.value() as u64;
+        resp.failure_info = Some("caller not allowed".to_owned().into_boxed_str().into());
+        let err = check_receipt_ok(&resp).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "message failed with exit code 18: caller not allowed"
+        );
+
+        // A message to a missing actor, which fails with SYS_INVALID_RECEIVER (5).
+        let mut machine = scripted_machine();
+        let executor = InnerFvmMachine {
+            recent_executions: Mutex::new(RecentExecutions::new(2)),
+            ..Default::default()
+        };
+        let message = Message {
+            to: Address::new_id(999),
+            ..self_send(SYSTEM_ACTOR_ID)
+        };
+        let resp = apply(
+            &executor,
+            &mut machine,
+            message.clone(),
+            ApplyKind::Implicit,
+            FvmExecuteOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            resp.exit_code,
+            ExitCode::SYS_INVALID_RECEIVER.value() as u64
+        );
+
+        let options = FvmExecuteOptions {
+            strict: true,
+            ..Default::default()
+        };
+        let err = apply(
+            &executor,
+            &mut machine,
+            message,
+            ApplyKind::Implicit,
+            options,
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("message failed with exit code 5"));
+        // The message was still applied, and recorded.
+        assert_eq!(
+            executor.recent_executions.lock().unwrap().entries().len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_new_machine_state_root() {
+        // The machine takes its blockstore, so every case gets its own with the actors imported.
+        let create = |bs: MemoryBlockstore, root: Cid| {
+            let manifest = import_actors(&bs, None, NetworkVersion::V15)
+                .unwrap()
+                .unwrap();
+            let mut network_config = NetworkConfig::new(NetworkVersion::V15);
+            network_config.override_actors(manifest);
+            let engine = ENGINES.get(&network_config).unwrap();
+            let context = network_config.for_epoch(0, root);
+            new_machine(&engine, &context, bs, ScriptedExterns::default()).map(|_| ())
+        };
+
+        // A root missing from the blockstore fails the creation, naming the root.
+        let missing = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&[0x80]));
+        let err = create(MemoryBlockstore::new(), missing)
+            .unwrap_err()
+            .to_string();
+        assert_eq!(
+            err,
+            format!("state root {} not found in the blockstore", missing)
+        );
+
+        // So does a root holding something other than a state tree.
+        let bs = MemoryBlockstore::new();
+        let unreadable = bs
+            .put(Code::Blake2b256, &Block::new(DAG_CBOR, [0x80]))
+            .unwrap();
+        let err = create(bs, unreadable).unwrap_err().to_string();
+        assert!(
+            err.starts_with(&format!("state root {} is unreadable: ", unreadable)),
+            "{}",
+            err
+        );
+
+        let bs = MemoryBlockstore::new();
+        let root = empty_state_for(&bs, NetworkVersion::V15, None, "scripted").unwrap();
+        create(bs, root).unwrap();
     }
 }
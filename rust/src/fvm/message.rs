@@ -0,0 +1,249 @@
+//! Machine-independent message checks.
+
+use fvm_ipld_encoding::tuple::Deserialize_tuple;
+use fvm_ipld_encoding::{BytesDe, RawBytes};
+use fvm_shared::address::Address;
+use fvm_shared::bigint::bigint_ser;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::MethodNum;
+use num_traits::Signed;
+use safer_ffi::prelude::*;
+
+use super::types::*;
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+/// The maximum amount of gas a single block (and therefore a single message) may use.
+const BLOCK_GAS_LIMIT: i64 = 10_000_000_000;
+
+/// The total amount of FIL that will ever exist, in attoFIL.
+const TOTAL_FILECOIN: u128 = 2_000_000_000 * 1_000_000_000_000_000_000;
+
+/// A message as encoded on-chain, but with the addresses left undecoded so that we can report
+/// invalid addresses instead of failing to decode the entire message.
+#[derive(Deserialize_tuple)]
+struct RawMessage {
+    version: i64,
+    to: BytesDe,
+    from: BytesDe,
+    #[allow(dead_code)]
+    sequence: u64,
+    #[serde(with = "bigint_ser")]
+    value: TokenAmount,
+    gas_limit: i64,
+    #[serde(with = "bigint_ser")]
+    gas_fee_cap: TokenAmount,
+    #[serde(with = "bigint_ser")]
+    gas_premium: TokenAmount,
+    #[allow(dead_code)]
+    method_num: MethodNum,
+    #[allow(dead_code)]
+    params: RawBytes,
+}
+
+/// Checks that the message is structurally valid for inclusion in a block, returning the first
+/// rule it violates. This doesn't check anything that depends on the state (nonce, balance,
+/// whether the addresses exist, etc.).
+#[ffi_export]
+fn fvm_validate_message_syntax(
+    message: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmValidateMessageSyntaxResponse>> {
+    catch_panic_response("fvm_validate_message_syntax", || {
+        let reason = validate_message_syntax(&message);
+        Ok(FvmValidateMessageSyntaxResponse {
+            valid: reason == FvmMessageSyntaxError::NoError,
+            reason,
+        })
+    })
+}
+
+destructor!(
+    destroy_fvm_validate_message_syntax_response,
+    Result<FvmValidateMessageSyntaxResponse>
+);
+
+/// Checks that the gas limits of a batch of messages (e.g. a block's) add up to at most the block
+/// gas limit, without applying them. The messages are only decoded as far as needed to read their
+/// gas limits.
+#[ffi_export]
+fn fvm_validate_batch_gas(
+    messages: c_slice::Ref<c_slice::Box<u8>>,
+    block_gas_limit: u64,
+) -> repr_c::Box<Result<FvmValidateBatchGasResponse>> {
+    catch_panic_response("fvm_validate_batch_gas", || {
+        let gas_limits = messages
+            .iter()
+            .map(|message| Ok(fvm_ipld_encoding::from_slice::<RawMessage>(message)?.gas_limit))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(batch_gas(&gas_limits, block_gas_limit))
+    })
+}
+
+destructor!(
+    destroy_fvm_validate_batch_gas_response,
+    Result<FvmValidateBatchGasResponse>
+);
+
+fn batch_gas(gas_limits: &[i64], block_gas_limit: u64) -> FvmValidateBatchGasResponse {
+    let total: i128 = gas_limits.iter().copied().map(i128::from).sum();
+    let overage = total - i128::from(block_gas_limit);
+    FvmValidateBatchGasResponse {
+        fits: overage <= 0,
+        overage: u64::try_from(overage.max(0)).unwrap_or(u64::MAX),
+    }
+}
+
+fn validate_message_syntax(message: &[u8]) -> FvmMessageSyntaxError {
+    use FvmMessageSyntaxError::*;
+
+    let msg: RawMessage = match fvm_ipld_encoding::from_slice(message) {
+        Ok(msg) => msg,
+        Err(_) => return InvalidEncoding,
+    };
+
+    if msg.version != 0 {
+        return InvalidVersion;
+    }
+    if Address::from_bytes(&msg.to.0).is_err() {
+        return InvalidToAddress;
+    }
+    if Address::from_bytes(&msg.from.0).is_err() {
+        return InvalidFromAddress;
+    }
+    if msg.value.is_negative() {
+        return NegativeValue;
+    }
+    if msg.value > TokenAmount::from(TOTAL_FILECOIN) {
+        return ValueTooLarge;
+    }
+    if msg.gas_fee_cap.is_negative() {
+        return NegativeGasFeeCap;
+    }
+    if msg.gas_premium.is_negative() {
+        return NegativeGasPremium;
+    }
+    if msg.gas_premium > msg.gas_fee_cap {
+        return GasPremiumAboveFeeCap;
+    }
+    if msg.gas_limit <= 0 {
+        return GasLimitNotPositive;
+    }
+    if msg.gas_limit > BLOCK_GAS_LIMIT {
+        return GasLimitTooLarge;
+    }
+    NoError
+}
+
+#[cfg(test)]
+mod test {
+    use fvm_ipld_encoding::{to_vec, RawBytes};
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::message::Message;
+    use safer_ffi::prelude::c_slice;
+
+    use super::{
+        batch_gas, fvm_validate_batch_gas, fvm_validate_message_syntax, validate_message_syntax,
+        BLOCK_GAS_LIMIT,
+    };
+    use crate::fvm::types::{FvmMessageSyntaxError, FvmValidateBatchGasResponse};
+
+    fn message() -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            sequence: 0,
+            value: TokenAmount::from(1u8),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from(100u8),
+            gas_premium: TokenAmount::from(10u8),
+        }
+    }
+
+    #[test]
+    fn test_validate_message_syntax() {
+        let resp = fvm_validate_message_syntax(to_vec(&message()).unwrap()[..].into());
+        assert!(resp.valid);
+        assert_eq!(resp.reason, FvmMessageSyntaxError::NoError);
+
+        let mut msg = message();
+        msg.value = TokenAmount::from(-1);
+        let resp = fvm_validate_message_syntax(to_vec(&msg).unwrap()[..].into());
+        assert!(!resp.valid);
+        assert_eq!(resp.reason, FvmMessageSyntaxError::NegativeValue);
+
+        let mut msg = message();
+        msg.gas_limit = 0;
+        assert_eq!(
+            validate_message_syntax(&to_vec(&msg).unwrap()),
+            FvmMessageSyntaxError::GasLimitNotPositive
+        );
+
+        // The "to" address (f0101) is encoded as the bytes [0x00, 0x65]. Replace the ID protocol
+        // with an unknown one.
+        let mut encoded = to_vec(&message()).unwrap();
+        let to = encoded
+            .windows(3)
+            .position(|w| w == [0x42, 0x00, 0x65])
+            .unwrap();
+        encoded[to + 1] = 0x09;
+        assert_eq!(
+            validate_message_syntax(&encoded),
+            FvmMessageSyntaxError::InvalidToAddress
+        );
+
+        assert_eq!(
+            validate_message_syntax(&encoded[1..]),
+            FvmMessageSyntaxError::InvalidEncoding
+        );
+    }
+
+    #[test]
+    fn test_validate_batch_gas() {
+        let batch = |gas_limits: &[i64]| -> Vec<c_slice::Box<u8>> {
+            gas_limits
+                .iter()
+                .map(|&gas_limit| {
+                    let msg = Message {
+                        gas_limit,
+                        ..message()
+                    };
+                    to_vec(&msg).unwrap().into_boxed_slice().into()
+                })
+                .collect()
+        };
+        let limit = BLOCK_GAS_LIMIT as u64;
+
+        let full = batch(&[4_000_000_000, 4_000_000_000, 2_000_000_000]);
+        let resp = fvm_validate_batch_gas(full[..].into(), limit);
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(
+            resp.value,
+            FvmValidateBatchGasResponse {
+                fits: true,
+                overage: 0
+            }
+        );
+
+        let over = batch(&[4_000_000_000, 4_000_000_000, 2_000_000_001]);
+        let resp = fvm_validate_batch_gas(over[..].into(), limit);
+        assert!(resp.error_msg.is_empty());
+        assert_eq!(
+            resp.value,
+            FvmValidateBatchGasResponse {
+                fits: false,
+                overage: 1
+            }
+        );
+
+        assert!(batch_gas(&[], 0).fits);
+        assert_eq!(batch_gas(&[i64::MAX; 3], 0).overage, u64::MAX);
+
+        let garbage: Vec<c_slice::Box<u8>> = vec![vec![0xff].into_boxed_slice().into()];
+        let resp = fvm_validate_batch_gas(garbage[..].into(), limit);
+        assert!(!resp.error_msg.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//! The names of the builtin actors' exported methods, as they're defined in the v7 and v8 actors.
+
+use fvm_shared::{MethodNum, METHOD_SEND};
+
+const ACCOUNT: &[&str] = &["Constructor", "PubkeyAddress"];
+const CRON: &[&str] = &["Constructor", "EpochTick"];
+const INIT: &[&str] = &["Constructor", "Exec"];
+const MULTISIG: &[&str] = &[
+    "Constructor",
+    "Propose",
+    "Approve",
+    "Cancel",
+    "AddSigner",
+    "RemoveSigner",
+    "SwapSigner",
+    "ChangeNumApprovalsThreshold",
+    "LockBalance",
+];
+const PAYMENT_CHANNEL: &[&str] = &["Constructor", "UpdateChannelState", "Settle", "Collect"];
+const REWARD: &[&str] = &[
+    "Constructor",
+    "AwardBlockReward",
+    "ThisEpochReward",
+    "UpdateNetworkKPI",
+];
+const STORAGE_MARKET: &[&str] = &[
+    "Constructor",
+    "AddBalance",
+    "WithdrawBalance",
+    "PublishStorageDeals",
+    "VerifyDealsForActivation",
+    "ActivateDeals",
+    "OnMinerSectorsTerminate",
+    "ComputeDataCommitment",
+    "CronTick",
+];
+const STORAGE_MINER: &[&str] = &[
+    "Constructor",
+    "ControlAddresses",
+    "ChangeWorkerAddress",
+    "ChangePeerID",
+    "SubmitWindowedPoSt",
+    "PreCommitSector",
+    "ProveCommitSector",
+    "ExtendSectorExpiration",
+    "TerminateSectors",
+    "DeclareFaults",
+    "DeclareFaultsRecovered",
+    "OnDeferredCronEvent",
+    "CheckSectorProven",
+    "ApplyRewards",
+    "ReportConsensusFault",
+    "WithdrawBalance",
+    "ConfirmSectorProofsValid",
+    "ChangeMultiaddrs",
+    "CompactPartitions",
+    "CompactSectorNumbers",
+    "ConfirmUpdateWorkerKey",
+    "RepayDebt",
+    "ChangeOwnerAddress",
+    "DisputeWindowedPoSt",
+    "PreCommitSectorBatch",
+    "ProveCommitAggregate",
+    "ProveReplicaUpdates",
+];
+const STORAGE_POWER: &[&str] = &[
+    "Constructor",
+    "CreateMiner",
+    "UpdateClaimedPower",
+    "EnrollCronEvent",
+    "OnEpochTickEnd",
+    "UpdatePledgeTotal",
+    "OnConsensusFault",
+    "SubmitPoRepForBulkVerify",
+    "CurrentTotalPower",
+];
+const SYSTEM: &[&str] = &["Constructor"];
+const VERIFIED_REGISTRY: &[&str] = &[
+    "Constructor",
+    "AddVerifier",
+    "RemoveVerifier",
+    "AddVerifiedClient",
+    "UseBytes",
+    "RestoreBytes",
+    "RemoveVerifiedClientDataCap",
+];
+
+/// Returns the name of a builtin actor's method, given the actor's name in the manifest. Every
+/// actor accepts plain value transfers (`Send`).
+pub fn method_name(actor_name: &str, method: MethodNum) -> Option<&'static str> {
+    if method == METHOD_SEND {
+        return Some("Send");
+    }
+    let methods = match actor_name {
+        "account" => ACCOUNT,
+        "cron" => CRON,
+        "init" => INIT,
+        "multisig" => MULTISIG,
+        "paymentchannel" => PAYMENT_CHANNEL,
+        "reward" => REWARD,
+        "storagemarket" => STORAGE_MARKET,
+        "storageminer" => STORAGE_MINER,
+        "storagepower" => STORAGE_POWER,
+        "system" => SYSTEM,
+        "verifiedregistry" => VERIFIED_REGISTRY,
+        _ => return None,
+    };
+    // Method numbers start at 1 (the constructor).
+    usize::try_from(method - 1)
+        .ok()
+        .and_then(|i| methods.get(i))
+        .copied()
+}
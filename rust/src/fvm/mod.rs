@@ -1,6 +1,21 @@
+mod benchmark;
 mod blockstore;
+mod car;
 mod cgo;
+mod dag;
 mod externs;
+mod fees;
+mod genesis;
+mod history;
+mod kernel;
+mod message;
+mod methods;
+mod proofs;
+mod receipts;
+mod result;
+mod scripted;
+mod selfcheck;
+mod trace;
 
 pub mod machine;
 pub mod types;
@@ -0,0 +1,166 @@
+//! Inclusion proofs for actors in the state tree.
+//!
+//! An actor's proof is the set of blocks read when it's looked up from a state root: the root
+//! itself and the HAMT nodes on the path to the actor's entry. Looking the actor up from the same
+//! root, in a blockstore holding only those blocks, yields the proven actor state. Anything else
+//! (a different root, a missing or altered block) fails the lookup.
+
+use std::cell::RefCell;
+
+use anyhow::{bail, Result};
+use cid::Cid;
+use fvm::state_tree::StateTree;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+
+/// The proof of an actor's inclusion in a state tree.
+#[derive(Clone, Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+pub struct ActorProof {
+    pub address: Address,
+    pub blocks: Vec<(Cid, RawBytes)>,
+}
+
+/// A read-only blockstore recording the blocks read through it.
+struct RecordingBlockstore<'a, BS> {
+    base: &'a BS,
+    read: RefCell<Vec<(Cid, RawBytes)>>,
+}
+
+impl<'a, BS> Blockstore for RecordingBlockstore<'a, BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let block = self.base.get(k)?;
+        if let Some(block) = &block {
+            self.read
+                .borrow_mut()
+                .push((*k, RawBytes::new(block.clone())));
+        }
+        Ok(block)
+    }
+
+    fn put_keyed(&self, _k: &Cid, _block: &[u8]) -> Result<()> {
+        bail!("can't write to a recording blockstore")
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.base.has(k)
+    }
+}
+
+/// Builds the inclusion proof of the actor at the address in the state tree with the given root.
+/// Returns `None` if there's no such actor.
+pub fn actor_proof<BS: Blockstore>(
+    bs: &BS,
+    root: &Cid,
+    address: &Address,
+) -> Result<Option<ActorProof>> {
+    let recording = RecordingBlockstore {
+        base: bs,
+        read: RefCell::default(),
+    };
+    if StateTree::new_from_root(&recording, root)?
+        .get_actor(address)?
+        .is_none()
+    {
+        return Ok(None);
+    }
+    Ok(Some(ActorProof {
+        address: *address,
+        blocks: recording.read.into_inner(),
+    }))
+}
+
+/// Returns the candidates whose actor state differs between the two state trees, by ID address
+/// where it can be resolved. Actors outside of the candidates aren't considered.
+pub fn changed_actors<BS: Blockstore>(
+    pre: &StateTree<BS>,
+    post: &StateTree<BS>,
+    candidates: impl IntoIterator<Item = Address>,
+) -> Result<Vec<Address>> {
+    let mut changed = Vec::new();
+    for address in candidates {
+        let address = match post.lookup_id(&address)? {
+            Some(id) => Address::new_id(id),
+            None => address,
+        };
+        if changed.contains(&address) {
+            continue;
+        }
+        if pre.get_actor(&address)? != post.get_actor(&address)? {
+            changed.push(address);
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::state_tree::{ActorState, StateTree};
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::DAG_CBOR;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::{actor_proof, changed_actors};
+
+    #[test]
+    fn test_actor_proof() {
+        let bs = MemoryBlockstore::new();
+        let code = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"account"));
+        let (sender, receiver, bystander) = (
+            Address::new_id(100),
+            Address::new_id(101),
+            Address::new_id(102),
+        );
+
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        for address in [sender, receiver, bystander] {
+            let actor = ActorState::new(code, code, TokenAmount::from(1_000u32), 0);
+            state_tree.set_actor(&address, actor).unwrap();
+        }
+        let pre_root = state_tree.flush().unwrap();
+
+        // A value transfer from the sender to the receiver.
+        let mut from = state_tree.get_actor(&sender).unwrap().unwrap();
+        from.sequence += 1;
+        from.balance -= TokenAmount::from(10u32);
+        state_tree.set_actor(&sender, from).unwrap();
+        let mut to = state_tree.get_actor(&receiver).unwrap().unwrap();
+        to.balance += TokenAmount::from(10u32);
+        state_tree.set_actor(&receiver, to.clone()).unwrap();
+        let post_root = state_tree.flush().unwrap();
+
+        let pre = StateTree::new_from_root(&bs, &pre_root).unwrap();
+        let post = StateTree::new_from_root(&bs, &post_root).unwrap();
+        assert_eq!(
+            changed_actors(&pre, &post, [sender, receiver, bystander, receiver]).unwrap(),
+            vec![sender, receiver]
+        );
+
+        // The proof alone is enough to look the receiver up from the new root.
+        let proof = actor_proof(&bs, &post_root, &receiver).unwrap().unwrap();
+        assert_eq!(proof.address, receiver);
+        let proof_bs = MemoryBlockstore::new();
+        for (cid, block) in &proof.blocks {
+            proof_bs.put_keyed(cid, block.bytes()).unwrap();
+        }
+        let proven = StateTree::new_from_root(&proof_bs, &post_root).unwrap();
+        assert_eq!(proven.get_actor(&receiver).unwrap(), Some(to));
+        // But not from the old one.
+        assert!(StateTree::new_from_root(&proof_bs, &pre_root)
+            .and_then(|tree| tree.get_actor(&receiver))
+            .is_err());
+
+        assert_eq!(
+            actor_proof(&bs, &post_root, &Address::new_id(103)).unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! The receipts AMT referenced by block headers (`ParentMessageReceipts`).
+
+use cid::multihash::Code;
+use cid::Cid;
+use fvm_ipld_blockstore::{Block, Blockstore};
+use fvm_ipld_encoding::tuple::Serialize_tuple;
+use fvm_ipld_encoding::{to_vec, DAG_CBOR};
+use fvm_shared::receipt::Receipt;
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+
+/// The (fixed) number of entries in each node of a legacy AMT.
+const WIDTH: usize = 8;
+
+/// A node of a legacy AMT. The receipts AMT is dense, so the first entries of every node are set.
+#[derive(Serialize_tuple)]
+struct Node<'a> {
+    bitmap: ByteBuf,
+    links: &'a [Cid],
+    values: &'a [Receipt],
+}
+
+impl<'a> Node<'a> {
+    fn new(links: &'a [Cid], values: &'a [Receipt]) -> Self {
+        let len = links.len().max(values.len());
+        Node {
+            bitmap: ByteBuf::from(vec![((1u16 << len) - 1) as u8]),
+            links,
+            values,
+        }
+    }
+}
+
+/// The root of a legacy AMT, which (unlike the current format) doesn't record the width.
+#[derive(Serialize_tuple)]
+struct Root<'a> {
+    height: u64,
+    count: u64,
+    node: Node<'a>,
+}
+
+/// Builds the AMT of the receipts (in message order) in the blockstore, returning its root.
+///
+/// Block headers reference receipts in the legacy (v0) AMT format, which the AMT crate can't build,
+/// so it's built here bottom up: full leaves of receipts, then layers of full nodes linking to
+/// them, up to the root.
+pub fn receipts_root<BS: Blockstore>(bs: &BS, receipts: &[Receipt]) -> anyhow::Result<Cid> {
+    fn put<BS: Blockstore, T: Serialize>(bs: &BS, value: &T) -> anyhow::Result<Cid> {
+        bs.put(Code::Blake2b256, &Block::new(DAG_CBOR, to_vec(value)?))
+    }
+
+    let count = receipts.len() as u64;
+    if receipts.len() <= WIDTH {
+        let node = Node::new(&[], receipts);
+        return put(
+            bs,
+            &Root {
+                height: 0,
+                count,
+                node,
+            },
+        );
+    }
+
+    let mut height = 1;
+    let mut links = receipts
+        .chunks(WIDTH)
+        .map(|values| put(bs, &Node::new(&[], values)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    while links.len() > WIDTH {
+        height += 1;
+        links = links
+            .chunks(WIDTH)
+            .map(|links| put(bs, &Node::new(links, &[])))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+    }
+    put(
+        bs,
+        &Root {
+            height,
+            count,
+            node: Node::new(&links, &[]),
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use cid::Cid;
+    use fvm_ipld_blockstore::{Blockstore, MemoryBlockstore};
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::receipt::Receipt;
+    use serde_bytes::ByteBuf;
+
+    use super::receipts_root;
+
+    fn receipt(i: u32) -> Receipt {
+        Receipt {
+            exit_code: ExitCode::new(i % 2),
+            return_data: RawBytes::new(vec![0x41, i as u8]),
+            gas_used: 1_000 + i as i64,
+        }
+    }
+
+    type Node = (ByteBuf, Vec<Cid>, Vec<Receipt>);
+
+    #[test]
+    fn test_receipts_root() {
+        let receipts: Vec<_> = (0..20).map(receipt).collect();
+
+        let bs = MemoryBlockstore::default();
+        let root = receipts_root(&bs, &receipts).unwrap();
+        assert_eq!(
+            root,
+            receipts_root(&MemoryBlockstore::default(), &receipts).unwrap()
+        );
+        assert_ne!(root, receipts_root(&bs, &receipts[1..]).unwrap());
+
+        // Legacy root: [height, count, node]. 20 receipts need two levels of 8-wide nodes.
+        let block = bs.get(&root).unwrap().unwrap();
+        let (height, count, (bitmap, links, values)): (u64, u64, Node) =
+            fvm_ipld_encoding::from_slice(&block).unwrap();
+        assert_eq!((height, count), (1, 20));
+        assert_eq!((bitmap.as_slice(), links.len()), (&[0b111][..], 3));
+        assert!(values.is_empty());
+
+        // The last leaf holds the last 4 receipts.
+        let leaf = bs.get(&links[2]).unwrap().unwrap();
+        let (bitmap, links, values): Node = fvm_ipld_encoding::from_slice(&leaf).unwrap();
+        assert_eq!(bitmap.as_slice(), &[0b1111]);
+        assert!(links.is_empty());
+        assert_eq!(values[3].gas_used, 1_019);
+    }
+
+    #[test]
+    fn test_receipts_root_shape() {
+        let root = |count: u32| {
+            let bs = MemoryBlockstore::default();
+            let receipts: Vec<_> = (0..count).map(receipt).collect();
+            let block = bs.get(&receipts_root(&bs, &receipts).unwrap()).unwrap();
+            let (height, count, (bitmap, _, _)): (u64, u64, Node) =
+                fvm_ipld_encoding::from_slice(&block.unwrap()).unwrap();
+            (height, count, bitmap.into_vec())
+        };
+        assert_eq!(root(0), (0, 0, vec![0]));
+        assert_eq!(root(8), (0, 8, vec![0xff]));
+        assert_eq!(root(9), (1, 9, vec![0b11]));
+        assert_eq!(root(64), (1, 64, vec![0xff]));
+        assert_eq!(root(65), (2, 65, vec![0b11]));
+    }
+
+    #[test]
+    fn test_receipts_root_cid() {
+        // Fixed roots of the legacy (go-amt-ipld v2) array Lotus builds receipts with, computed
+        // independently of this module: one and two levels of links above the leaves.
+        let root = |count: u32| {
+            let receipts: Vec<_> = (0..count).map(receipt).collect();
+            receipts_root(&MemoryBlockstore::default(), &receipts)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            root(20),
+            "bafy2bzacedyruzr23jrktrvimi7cwldll5y4fnpkikaw2q6pjxvek3cxd5rq2"
+        );
+        assert_eq!(
+            root(65),
+            "bafy2bzacedefzq4qoqbjzqfobx57fylw2efqaoaulzplf7ixzoyudmta665r6"
+        );
+    }
+}
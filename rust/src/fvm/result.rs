@@ -0,0 +1,146 @@
+//! A self-contained serialization of the outcome of applying a message, for callers caching
+//! replays keyed by (state root, message).
+//!
+//! The blob is the CBOR tuple `(receipt, exec_trace, state_root)`, where the trace is kept in
+//! whatever format the machine produced it, and is empty if there was none. The FVM doesn't emit
+//! events yet, so there are none to store.
+
+use std::convert::TryFrom;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_ipld_encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use fvm_ipld_encoding::{to_vec, RawBytes};
+use fvm_shared::error::ExitCode;
+use fvm_shared::receipt::Receipt;
+use safer_ffi::prelude::*;
+
+use super::types::FvmExecutionResult;
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+#[derive(Debug, PartialEq, Serialize_tuple, Deserialize_tuple)]
+struct ExecutionResult {
+    receipt: Receipt,
+    exec_trace: RawBytes,
+    state_root: Cid,
+}
+
+/// Serializes the outcome of applying a message: its receipt, its execution trace (may be empty),
+/// and the state root after it was applied.
+#[ffi_export]
+fn fvm_serialize_execution_result(
+    exit_code: u64,
+    return_val: c_slice::Ref<u8>,
+    gas_used: u64,
+    exec_trace: c_slice::Ref<u8>,
+    state_root: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<c_slice::Box<u8>>> {
+    catch_panic_response("fvm_serialize_execution_result", || {
+        let result = ExecutionResult {
+            receipt: Receipt {
+                exit_code: ExitCode::new(
+                    u32::try_from(exit_code)
+                        .map_err(|_| anyhow!("exit code out of range: {}", exit_code))?,
+                ),
+                return_data: RawBytes::new(return_val.to_vec()),
+                gas_used: i64::try_from(gas_used)
+                    .map_err(|_| anyhow!("gas used out of range: {}", gas_used))?,
+            },
+            exec_trace: RawBytes::new(exec_trace.to_vec()),
+            state_root: Cid::try_from(&state_root[..])?,
+        };
+        Ok(to_vec(&result)?.into_boxed_slice().into())
+    })
+}
+
+/// Reads back an execution result serialized by `fvm_serialize_execution_result`.
+#[ffi_export]
+fn fvm_deserialize_execution_result(
+    result: c_slice::Ref<u8>,
+) -> repr_c::Box<Result<FvmExecutionResult>> {
+    catch_panic_response("fvm_deserialize_execution_result", || {
+        let ExecutionResult {
+            receipt,
+            exec_trace,
+            state_root,
+        } = fvm_ipld_encoding::from_slice(&result)?;
+
+        let optional = |bytes: Vec<u8>| {
+            if bytes.is_empty() {
+                None
+            } else {
+                Some(bytes.into_boxed_slice().into())
+            }
+        };
+        Ok(FvmExecutionResult {
+            exit_code: receipt.exit_code.value() as u64,
+            return_val: optional(receipt.return_data.into()),
+            gas_used: receipt.gas_used as u64,
+            exec_trace: optional(exec_trace.into()),
+            state_root: state_root.to_bytes().into_boxed_slice().into(),
+        })
+    })
+}
+
+destructor!(
+    destroy_fvm_serialize_execution_result_response,
+    Result<c_slice::Box<u8>>
+);
+
+destructor!(
+    destroy_fvm_deserialize_execution_result_response,
+    Result<FvmExecutionResult>
+);
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm_ipld_encoding::DAG_CBOR;
+
+    use super::{fvm_deserialize_execution_result, fvm_serialize_execution_result};
+
+    #[test]
+    fn test_execution_result_round_trip() {
+        let root = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&[0x80])).to_bytes();
+        let resp = fvm_serialize_execution_result(
+            16,
+            [0x81, 0x01][..].into(),
+            1234,
+            [0x01, 0x02, 0x03][..].into(),
+            root[..].into(),
+        );
+        assert!(resp.error_msg.is_empty());
+
+        let result = fvm_deserialize_execution_result(resp.value[..].into());
+        assert!(result.error_msg.is_empty());
+        assert_eq!(result.value.exit_code, 16);
+        assert_eq!(
+            &result.value.return_val.as_ref().unwrap()[..],
+            &[0x81, 0x01]
+        );
+        assert_eq!(result.value.gas_used, 1234);
+        assert_eq!(
+            &result.value.exec_trace.as_ref().unwrap()[..],
+            &[0x01, 0x02, 0x03]
+        );
+        assert_eq!(&result.value.state_root[..], &root[..]);
+
+        // Empty return data and traces come back as none.
+        let resp = fvm_serialize_execution_result(
+            0,
+            [0u8; 0][..].into(),
+            0,
+            [0u8; 0][..].into(),
+            root[..].into(),
+        );
+        let result = fvm_deserialize_execution_result(resp.value[..].into());
+        assert!(result.error_msg.is_empty());
+        assert!(result.value.return_val.is_none());
+        assert!(result.value.exec_trace.is_none());
+
+        let result = fvm_deserialize_execution_result(resp.value[1..].into());
+        assert!(!result.error_msg.is_empty());
+    }
+}
@@ -0,0 +1,382 @@
+//! Externs returning scripted results, and in-memory machines using them. The selfcheck runs on
+//! them, and tests use them to check how the FVM handles what the node returns without going
+//! through Go.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail};
+use fvm::call_manager::DefaultCallManager;
+use fvm::executor::DefaultExecutor;
+use fvm::externs::{Consensus, Externs, Rand};
+use fvm::machine::{DefaultMachine, NetworkConfig};
+use fvm::DefaultKernel;
+use fvm_ipld_blockstore::MemoryBlockstore;
+use fvm_ipld_encoding::RawBytes;
+use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::ActorID;
+
+use super::blockstore::{AccessLog, AccessLogBlockstore};
+use super::externs::{ExecutionExterns, RandomnessRequest, RecordingRand};
+use super::kernel::{SubcallGas, SubcallGasKernel};
+use super::machine::{empty_state_for, import_actors, new_machine, ENGINES};
+
+pub type ScriptedMachine = DefaultMachine<AccessLogBlockstore<MemoryBlockstore>, ScriptedExterns>;
+pub type ScriptedExecutor =
+    DefaultExecutor<SubcallGasKernel<DefaultKernel<DefaultCallManager<ScriptedMachine>>>>;
+
+/// Externs answering consensus fault verifications with scripted results, in order. Verifying a
+/// fault once the script is exhausted fails, as do randomness lookups, so the default externs fail
+/// everything. The headers of every verification are recorded, as are the randomness lookups.
+#[derive(Default)]
+pub struct ScriptedExterns {
+    consensus_faults: Mutex<VecDeque<(Option<ConsensusFault>, i64)>>,
+    consensus_fault_queries: Mutex<Vec<ConsensusFaultQuery>>,
+    rand: RecordingRand<NoRand>,
+    subcall_gas: SubcallGas,
+}
+
+/// Randomness lookups that always fail.
+#[derive(Default)]
+struct NoRand;
+
+/// The two block headers and the extra data of a consensus fault verification.
+pub type ConsensusFaultQuery = (Vec<u8>, Vec<u8>, Vec<u8>);
+
+impl ScriptedExterns {
+    /// Scripts the next consensus fault verification to find `fault` (if any), using `gas_used`.
+    #[cfg(test)]
+    pub fn with_consensus_fault(mut self, fault: Option<ConsensusFault>, gas_used: i64) -> Self {
+        self.consensus_faults
+            .get_mut()
+            .unwrap()
+            .push_back((fault, gas_used));
+        self
+    }
+
+    /// Returns (and forgets) the consensus fault verifications made since the last call.
+    #[cfg(test)]
+    pub fn take_consensus_fault_queries(&self) -> Vec<ConsensusFaultQuery> {
+        std::mem::take(&mut *self.consensus_fault_queries.lock().unwrap())
+    }
+}
+
+impl Rand for NoRand {
+    fn get_chain_randomness(
+        &self,
+        _pers: i64,
+        _round: ChainEpoch,
+        _entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        bail!("no scripted chain randomness")
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        _pers: i64,
+        _round: ChainEpoch,
+        _entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        bail!("no scripted beacon randomness")
+    }
+}
+
+impl Rand for ScriptedExterns {
+    fn get_chain_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.rand.get_chain_randomness(pers, round, entropy)
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: i64,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; 32]> {
+        self.rand.get_beacon_randomness(pers, round, entropy)
+    }
+}
+
+impl Consensus for ScriptedExterns {
+    fn verify_consensus_fault(
+        &self,
+        h1: &[u8],
+        h2: &[u8],
+        extra: &[u8],
+    ) -> anyhow::Result<(Option<ConsensusFault>, i64)> {
+        self.consensus_fault_queries.lock().unwrap().push((
+            h1.to_vec(),
+            h2.to_vec(),
+            extra.to_vec(),
+        ));
+        self.consensus_faults
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow!("no scripted consensus fault left"))
+    }
+}
+
+impl Externs for ScriptedExterns {}
+
+impl ExecutionExterns for ScriptedExterns {
+    fn take_randomness_requests(&self) -> Vec<RandomnessRequest> {
+        self.rand.take_requests()
+    }
+
+    fn subcall_gas(&self) -> &SubcallGas {
+        &self.subcall_gas
+    }
+
+    /// Summaries aren't streamed anywhere.
+    fn report_execution(
+        &self,
+        _exec_id: u64,
+        _exit_code: u64,
+        _gas_used: u64,
+        _num_subcalls: u64,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates an executor over an in-memory state holding only the system and init actors of the
+/// builtin actors for `network_version`, using the scripted externs. Tracing is on, so tests can
+/// check the execution trace.
+pub fn scripted_executor(
+    network_version: NetworkVersion,
+    externs: ScriptedExterns,
+) -> anyhow::Result<ScriptedExecutor> {
+    scripted_executor_with_access_log(network_version, externs, AccessLog::default())
+}
+
+/// Creates a scripted executor like [`scripted_executor`], whose blockstore records its accesses
+/// in `access_log`.
+pub fn scripted_executor_with_access_log(
+    network_version: NetworkVersion,
+    externs: ScriptedExterns,
+    access_log: AccessLog,
+) -> anyhow::Result<ScriptedExecutor> {
+    let blockstore = AccessLogBlockstore::new(MemoryBlockstore::new(), access_log);
+    let state_root = empty_state_for(&blockstore, network_version, None, "scripted")?;
+    let manifest = import_actors(&blockstore, None, network_version)
+        .map_err(|err| anyhow!("couldn't load builtin actors: {}", err))?
+        .ok_or_else(|| {
+            anyhow!(
+                "no builtin actors for network version {:?}",
+                network_version
+            )
+        })?;
+
+    let mut network_config = NetworkConfig::new(network_version);
+    network_config.override_actors(manifest);
+    let mut machine_context = network_config.for_epoch(0, state_root);
+    machine_context.enable_tracing();
+    let engine = ENGINES
+        .get(&network_config)
+        .map_err(|err| anyhow!("failed to create engine: {}", err))?;
+    let machine = new_machine(&engine, &machine_context, blockstore, externs)?;
+    Ok(ScriptedExecutor::new(machine))
+}
+
+/// A zero-value send of method 0 from `actor` to itself, which only needs the actor to exist.
+pub fn self_send(actor: ActorID) -> Message {
+    Message {
+        version: 0,
+        from: Address::new_id(actor),
+        to: Address::new_id(actor),
+        sequence: 0,
+        value: TokenAmount::from(0u8),
+        method_num: 0,
+        params: RawBytes::default(),
+        gas_limit: 10_000_000_000,
+        gas_fee_cap: TokenAmount::from(0u8),
+        gas_premium: TokenAmount::from(0u8),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm::executor::{ApplyKind, Executor};
+    use fvm::machine::Machine;
+    use fvm::state_tree::ActorState;
+    use fvm_ipld_encoding::{RawBytes, DAG_CBOR};
+    use fvm_shared::address::Address;
+    use fvm_shared::consensus::{ConsensusFault, ConsensusFaultType};
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::message::Message;
+    use fvm_shared::sector::RegisteredPoStProof;
+    use fvm_shared::version::NetworkVersion;
+    use fvm_shared::ActorID;
+    use serde_bytes::ByteBuf;
+
+    use super::{scripted_executor, self_send, ScriptedExecutor, ScriptedExterns};
+    use crate::fvm::machine::{builtin_actor_names, import_actors, SYSTEM_ACTOR_ID};
+
+    const INIT_ACTOR_ID: ActorID = 1;
+    const REWARD_ACTOR_ID: ActorID = 2;
+    const POWER_ACTOR_ID: ActorID = 4;
+    const MINER_ACTOR_ID: ActorID = 1000;
+    const REPORT_CONSENSUS_FAULT: u64 = 15;
+
+    /// Applies an implicit message from `from` calling `method` on `to`, returning its exit code.
+    fn call(
+        executor: &mut ScriptedExecutor,
+        from: ActorID,
+        to: Address,
+        method: u64,
+        params: RawBytes,
+    ) -> ExitCode {
+        let message = Message {
+            to,
+            method_num: method,
+            params,
+            ..self_send(from)
+        };
+        executor
+            .execute_message(message, ApplyKind::Implicit, 0)
+            .unwrap()
+            .msg_receipt
+            .exit_code
+    }
+
+    /// Adds an actor of the builtin actor `name` to the state at `id`, and runs its constructor
+    /// from `caller`.
+    fn construct(
+        executor: &mut ScriptedExecutor,
+        caller: ActorID,
+        id: ActorID,
+        name: &str,
+        params: RawBytes,
+    ) {
+        let manifest = import_actors(executor.blockstore(), None, NetworkVersion::V15)
+            .unwrap()
+            .unwrap();
+        let names = builtin_actor_names(executor.blockstore(), &manifest).unwrap();
+        let code = *names.iter().find(|(_, n)| *n == name).unwrap().0;
+        // Constructors expect the state of the new actor to be the empty array.
+        let empty_array = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&[0x80]));
+        executor
+            .state_tree_mut()
+            .set_actor(
+                &Address::new_id(id),
+                ActorState::new(code, empty_array, TokenAmount::from(0u8), 0),
+            )
+            .unwrap();
+        let exit_code = call(executor, caller, Address::new_id(id), 1, params);
+        assert_eq!(exit_code, ExitCode::OK, "constructing the {} actor", name);
+    }
+
+    fn actor_state(executor: &ScriptedExecutor, id: ActorID) -> Cid {
+        executor
+            .state_tree()
+            .get_actor(&Address::new_id(id))
+            .unwrap()
+            .unwrap()
+            .state
+    }
+
+    #[test]
+    fn test_scripted_consensus_fault() {
+        let fault = ConsensusFault {
+            target: Address::new_id(MINER_ACTOR_ID),
+            epoch: -1,
+            fault_type: ConsensusFaultType::DoubleForkMining,
+        };
+        let externs = ScriptedExterns::default()
+            .with_consensus_fault(Some(fault), 100)
+            .with_consensus_fault(None, 200);
+        let mut executor = scripted_executor(NetworkVersion::V15, externs).unwrap();
+
+        // A miner owned by an account, with the reward and power actors it reports to.
+        let key = Address::new_bls(&[1; 48]).unwrap();
+        let exit_code = call(&mut executor, SYSTEM_ACTOR_ID, key, 0, RawBytes::default());
+        assert_eq!(exit_code, ExitCode::OK);
+        let account = executor.state_tree().lookup_id(&key).unwrap().unwrap();
+        let no_baseline = RawBytes::serialize(Option::<()>::None).unwrap();
+        construct(
+            &mut executor,
+            SYSTEM_ACTOR_ID,
+            REWARD_ACTOR_ID,
+            "reward",
+            no_baseline,
+        );
+        construct(
+            &mut executor,
+            SYSTEM_ACTOR_ID,
+            POWER_ACTOR_ID,
+            "storagepower",
+            RawBytes::default(),
+        );
+        let owner = Address::new_id(account);
+        let miner_params = RawBytes::serialize((
+            owner,
+            owner,
+            Vec::<Address>::new(),
+            RegisteredPoStProof::StackedDRGWindow32GiBV1,
+            ByteBuf::from(b"peer".to_vec()),
+            Vec::<ByteBuf>::new(),
+        ))
+        .unwrap();
+        construct(
+            &mut executor,
+            INIT_ACTOR_ID,
+            MINER_ACTOR_ID,
+            "storageminer",
+            miner_params,
+        );
+        let miner = Address::new_id(MINER_ACTOR_ID);
+        let before = actor_state(&executor, MINER_ACTOR_ID);
+
+        // The first report verifies the fault, which penalizes the miner.
+        let report = RawBytes::serialize((
+            ByteBuf::from(b"header 1".to_vec()),
+            ByteBuf::from(b"header 2".to_vec()),
+            ByteBuf::from(b"extra".to_vec()),
+        ))
+        .unwrap();
+        let exit_code = call(
+            &mut executor,
+            account,
+            miner,
+            REPORT_CONSENSUS_FAULT,
+            report.clone(),
+        );
+        assert_eq!(exit_code, ExitCode::OK);
+        let penalized = actor_state(&executor, MINER_ACTOR_ID);
+        assert_ne!(penalized, before);
+
+        // The second finds no fault, which the miner rejects.
+        let exit_code = call(
+            &mut executor,
+            account,
+            miner,
+            REPORT_CONSENSUS_FAULT,
+            report,
+        );
+        assert_eq!(exit_code, ExitCode::USR_ILLEGAL_ARGUMENT);
+        assert_eq!(actor_state(&executor, MINER_ACTOR_ID), penalized);
+
+        let query = (
+            b"header 1".to_vec(),
+            b"header 2".to_vec(),
+            b"extra".to_vec(),
+        );
+        assert_eq!(
+            executor.externs().take_consensus_fault_queries(),
+            vec![query.clone(), query]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+//! An end-to-end check of the FVM, run against a minimal in-memory state.
+
+use anyhow::bail;
+use fvm::executor::{ApplyKind, Executor};
+use fvm::machine::Machine;
+use fvm_ipld_blockstore::Blockstore;
+use fvm_shared::error::ExitCode;
+use fvm_shared::version::NetworkVersion;
+use safer_ffi::prelude::*;
+
+use super::machine::SYSTEM_ACTOR_ID;
+use super::scripted::{scripted_executor, self_send, ScriptedExterns};
+use crate::destructor;
+use crate::util::types::{catch_panic_response, Result};
+
+/// Checks the whole pipeline end-to-end: creates an in-memory machine with the builtin actors and
+/// an empty state holding only the system and init actors, applies an implicit zero-value
+/// self-send from the system actor, and flushes the state. Fails with a diagnostic if any step
+/// doesn't succeed. The message never needs randomness or consensus fault verification, so the
+/// externs fail everything.
+#[ffi_export]
+fn fvm_selfcheck() -> repr_c::Box<Result<()>> {
+    catch_panic_response("fvm_selfcheck", selfcheck)
+}
+
+destructor!(destroy_fvm_selfcheck_response, Result<()>);
+
+fn selfcheck() -> anyhow::Result<()> {
+    let mut executor = scripted_executor(NetworkVersion::V15, ScriptedExterns::default())?;
+    let apply_ret = executor.execute_message(self_send(SYSTEM_ACTOR_ID), ApplyKind::Implicit, 0)?;
+    if apply_ret.msg_receipt.exit_code != ExitCode::OK {
+        bail!(
+            "selfcheck message failed with exit code {}: {}",
+            apply_ret.msg_receipt.exit_code.value(),
+            apply_ret
+                .failure_info
+                .map(|info| info.to_string())
+                .unwrap_or_default()
+        );
+    }
+
+    let root = executor.flush()?;
+    if !executor.blockstore().has(&root)? {
+        bail!("flushed state root {} is missing from the blockstore", root);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::selfcheck;
+
+    #[test]
+    fn test_selfcheck() {
+        selfcheck().unwrap();
+    }
+}
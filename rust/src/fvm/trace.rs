@@ -0,0 +1,136 @@
+//! A compact binary encoding of execution traces.
+//!
+//! The Lotus trace is a nested CBOR tree that's comparatively expensive to build and parse. The
+//! compact trace is instead the flat sequence of execution events, in the order they happened.
+//! Each event is a single record: a one byte tag followed by that tag's fields. All integers are
+//! fixed width and little-endian, all variable length fields are prefixed with their length as a
+//! `u32`.
+//!
+//! ```text
+//! 0x00 call:   from: u64, method: u64, value: u128, to: bytes, params: bytes
+//! 0x01 return: data: bytes
+//! 0x02 abort:  exit_code: u32
+//! 0x03 error:  errno: u32, message: bytes (utf-8)
+//! ```
+//!
+//! Every call record is eventually followed by the matching return, abort, or error record, with
+//! the records of any subcalls in between. The FVM doesn't currently report gas charges in the
+//! execution trace, so they're not encoded.
+
+use std::convert::TryFrom;
+
+use anyhow::{anyhow, Result};
+use fvm::trace::ExecutionEvent;
+
+const CALL: u8 = 0x00;
+const RETURN: u8 = 0x01;
+const ABORT: u8 = 0x02;
+const ERROR: u8 = 0x03;
+
+/// Encodes the execution events into the compact trace format. The bytes of each encoded event are
+/// passed to `charge` as the trace grows, encoding stops if it fails.
+pub fn encode_compact_trace<F>(events: &[ExecutionEvent], mut charge: F) -> Result<Vec<u8>>
+where
+    F: FnMut(usize) -> Result<()>,
+{
+    fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) -> Result<()> {
+        let len = u32::try_from(bytes.len()).map_err(|_| anyhow!("trace field too large"))?;
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    let mut buf = Vec::new();
+    for event in events {
+        let start = buf.len();
+        match event {
+            ExecutionEvent::Call {
+                from,
+                to,
+                method,
+                params,
+                value,
+            } => {
+                let value = u128::try_from(value)
+                    .map_err(|_| anyhow!("call value out of range: {}", value))?;
+                buf.push(CALL);
+                buf.extend_from_slice(&from.to_le_bytes());
+                buf.extend_from_slice(&method.to_le_bytes());
+                buf.extend_from_slice(&value.to_le_bytes());
+                put_bytes(&mut buf, &to.to_bytes())?;
+                put_bytes(&mut buf, params.bytes())?;
+            }
+            ExecutionEvent::CallReturn(data) => {
+                buf.push(RETURN);
+                put_bytes(&mut buf, data.bytes())?;
+            }
+            ExecutionEvent::CallAbort(exit_code) => {
+                buf.push(ABORT);
+                buf.extend_from_slice(&exit_code.value().to_le_bytes());
+            }
+            ExecutionEvent::CallError(err) => {
+                buf.push(ERROR);
+                buf.extend_from_slice(&(err.1 as u32).to_le_bytes());
+                put_bytes(&mut buf, err.0.as_bytes())?;
+            }
+        }
+        charge(buf.len() - start)?;
+    }
+    Ok(buf)
+}
+
+/// Decodes a compact trace back into execution events.
+#[cfg(test)]
+pub fn decode_compact_trace(mut buf: &[u8]) -> Result<Vec<ExecutionEvent>> {
+    use anyhow::bail;
+    use fvm::kernel::SyscallError;
+    use fvm_ipld_encoding::RawBytes;
+    use fvm_shared::address::Address;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::error::{ErrorNumber, ExitCode};
+    use num_traits::FromPrimitive;
+
+    fn take<'a>(buf: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+        if buf.len() < n {
+            bail!("unexpected end of trace");
+        }
+        let (bytes, rest) = buf.split_at(n);
+        *buf = rest;
+        Ok(bytes)
+    }
+    fn take_u32(buf: &mut &[u8]) -> Result<u32> {
+        Ok(u32::from_le_bytes(take(buf, 4)?.try_into()?))
+    }
+    fn take_u64(buf: &mut &[u8]) -> Result<u64> {
+        Ok(u64::from_le_bytes(take(buf, 8)?.try_into()?))
+    }
+    fn take_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+        let len = take_u32(buf)? as usize;
+        take(buf, len)
+    }
+
+    let mut events = Vec::new();
+    while !buf.is_empty() {
+        let event = match take(&mut buf, 1)?[0] {
+            CALL => ExecutionEvent::Call {
+                from: take_u64(&mut buf)?,
+                method: take_u64(&mut buf)?,
+                value: TokenAmount::from(u128::from_le_bytes(take(&mut buf, 16)?.try_into()?)),
+                to: Address::from_bytes(take_bytes(&mut buf)?)?,
+                params: RawBytes::new(take_bytes(&mut buf)?.to_vec()),
+            },
+            RETURN => ExecutionEvent::CallReturn(RawBytes::new(take_bytes(&mut buf)?.to_vec())),
+            ABORT => ExecutionEvent::CallAbort(ExitCode::new(take_u32(&mut buf)?)),
+            ERROR => {
+                let errno = take_u32(&mut buf)?;
+                let errno = ErrorNumber::from_u32(errno)
+                    .ok_or_else(|| anyhow!("invalid error number: {}", errno))?;
+                let message = String::from_utf8(take_bytes(&mut buf)?.to_vec())?;
+                ExecutionEvent::CallError(SyscallError(message, errno))
+            }
+            tag => bail!("invalid trace record: {}", tag),
+        };
+        events.push(event);
+    }
+    Ok(events)
+}
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
 use std::sync::Mutex;
 
+use cid::Cid;
 use safer_ffi::prelude::*;
 
+use super::blockstore::{AccessLog, BlockCache, ReadBatch, WriteLog};
+use super::history::RecentExecutions;
 use super::machine::CgoExecutor;
 
 #[derive_ReprC]
@@ -16,17 +20,101 @@ pub enum FvmRegisteredVersion {
 #[derive(Default)]
 pub struct InnerFvmMachine {
     pub(crate) machine: Option<Mutex<CgoExecutor>>,
+    /// The root returned by the last flush, cleared whenever the machine may have been modified.
+    /// Always lock `machine` before locking this.
+    pub(crate) flushed_root: Mutex<Option<Cid>>,
+    /// The maximum size of the return data captured in execution responses. Zero means no cap.
+    pub(crate) max_return_size: u64,
+    /// Whether execution traces are returned in the compact binary format instead of as Lotus
+    /// traces.
+    pub(crate) compact_trace: bool,
+    /// The schema Lotus traces are encoded in, unless `compact_trace` is set.
+    pub(crate) lotus_trace_format: FvmLotusTraceFormat,
+    /// The manifest passed to (or loaded by) the machine constructor. `None` if the machine
+    /// loads the manifest from state.
+    pub(crate) manifest_cid: Option<Cid>,
+    /// The names of the builtin actors in the machine's manifest, by code CID.
+    pub(crate) builtin_actor_names: HashMap<Cid, String>,
+    /// Summaries of the most recently applied messages.
+    pub(crate) recent_executions: Mutex<RecentExecutions>,
+    /// Whether a summary of every applied message is streamed to the externs.
+    pub(crate) stream_summaries: bool,
+    /// The cache of the machine's blockstore, filled by `fvm_machine_prefetch_state`.
+    pub(crate) block_cache: BlockCache,
+    /// The batch reads of the machine's blockstore take part in, so bulk reads through its buffered
+    /// blockstore reach the caller's blockstore in a single call.
+    pub(crate) read_batch: ReadBatch,
+    /// The handle of the caller's blockstore, for writes that must bypass the machine's buffered
+    /// blockstore (which only persists blocks reachable from the state root).
+    pub(crate) blockstore_id: u64,
+    /// Whether calls that apply messages or write to the blockstore are rejected.
+    pub(crate) read_only: bool,
+    /// The blocks the last flush wrote to the caller's blockstore, if recording them is enabled.
+    pub(crate) write_log: WriteLog,
+    /// The blocks read and written by the message being applied with its access list.
+    pub(crate) access_log: AccessLog,
 }
 
 pub type FvmMachine = Option<repr_c::Box<InnerFvmMachine>>;
 
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineCreateResponse {
+    pub machine: FvmMachine,
+    /// Whether the machine uses the manifest passed by the caller, overriding the builtin bundles
+    /// (pre-nv16) or the manifest in the state (nv16+).
+    pub forced_manifest: bool,
+    /// Where the machine's actors come from.
+    pub actor_source: FvmActorSource,
+}
+
 #[derive_ReprC]
 #[repr(C)]
 #[derive(Default)]
 pub struct FvmMachineExecuteResponse {
+    /// The ID of this execution, unique and increasing within the process.
+    pub exec_id: u64,
     pub exit_code: u64,
+    /// Whether `exit_code` is a system or a user (actor) error.
+    pub exit_code_class: FvmExitCodeClass,
     pub return_val: Option<c_slice::Box<u8>>,
+    /// Whether `return_val` was truncated to the machine's maximum return size.
+    pub return_truncated: bool,
+    /// The length of the return data before any truncation.
+    pub return_len: u64,
+    /// The kind of actor that received the message (after it was applied), unknown unless
+    /// requested with `receiver_info`.
+    pub receiver_kind: FvmReceiverKind,
+    /// Whether applying the message created an actor at the receiver address, e.g. an account
+    /// for a previously unknown key address, and if so the ID it was assigned. Only set if
+    /// requested with `receiver_info`.
+    pub actor_created: bool,
+    pub created_actor_id: u64,
+    /// The name of the invoked method, if the receiver is a builtin actor exporting it. Only set if
+    /// requested with `receiver_info`.
+    pub method_name: Option<str::Box>,
+    /// The length of the message params.
+    pub params_len: u64,
+    /// The IPLD codec the receiving method expects its params in, or 0 if unknown. Only set if
+    /// requested with `receiver_info`.
+    pub params_codec: u64,
+    /// Whether the receiving builtin actor aborted because it couldn't decode the params. Unlike the
+    /// other receiver fields, this doesn't need `receiver_info`.
+    pub params_decode_failed: bool,
+    /// The number of distinct actors involved in the message: its sender and receiver, and (if the
+    /// machine is tracing) the participants of its subcalls. Only set if requested with
+    /// `actors_touched`.
+    pub actors_touched: u64,
     pub gas_used: u64,
+    /// The part of `gas_used` not used by the subcalls of the receiver: its own execution, and the
+    /// inclusion of an explicit message.
+    pub direct_gas: u64,
+    /// The gas used by the subcalls the receiver made, including their own subcalls.
+    pub subcall_gas: u64,
+    /// How long the call waited for the machine's lock before applying the message, in
+    /// nanoseconds.
+    pub lock_wait_ns: u64,
     pub penalty_hi: u64,
     pub penalty_lo: u64,
     pub miner_tip_hi: u64,
@@ -37,8 +125,421 @@ pub struct FvmMachineExecuteResponse {
     pub over_estimation_burn_lo: u64,
     pub refund_hi: u64,
     pub refund_lo: u64,
+    /// The base fee per gas unit actually charged, i.e. the base fee clamped to the fee cap.
+    pub effective_base_fee_hi: u64,
+    pub effective_base_fee_lo: u64,
+    /// The premium per gas unit actually paid to the miner, i.e. the premium clamped to what's
+    /// left of the fee cap after the base fee.
+    pub effective_premium_hi: u64,
+    pub effective_premium_lo: u64,
+    /// The sender's balance before the message was applied, minus the value and the most the
+    /// message could pay for gas (its gas limit at the fee cap). Negative if the sender couldn't
+    /// cover that, in which case `balance_margin_hi/lo` hold the absolute value. Only set if
+    /// requested with `balance_margin`.
+    pub balance_margin_negative: bool,
+    pub balance_margin_hi: u64,
+    pub balance_margin_lo: u64,
     pub gas_refund: i64,
     pub gas_burned: i64,
     pub exec_trace: Option<c_slice::Box<u8>>,
+    /// Whether subcalls were dropped from `exec_trace` to fit the requested trace depth.
+    pub trace_truncated: bool,
     pub failure_info: Option<str::Box>,
+    /// The CBOR encoded list of randomness lookups performed by the message.
+    pub randomness_requests: Option<c_slice::Box<u8>>,
+    /// The CBOR encoded list of (cause, amount) entries the penalty is made of, if there's one.
+    pub penalty_breakdown: Option<c_slice::Box<u8>>,
+    /// The state root before the message was applied, if requested.
+    pub pre_state_root: Option<c_slice::Box<u8>>,
+    /// The state root after the message was applied, if requested.
+    pub post_state_root: Option<c_slice::Box<u8>>,
+    /// The CBOR encoded list of (address, blocks) inclusion proofs of the actors changed by the
+    /// message against the post state root, if requested.
+    pub state_proofs: Option<c_slice::Box<u8>>,
+    /// The receiver's state block after the message was applied, if requested and the receiver
+    /// exists.
+    pub receiver_state: Option<c_slice::Box<u8>>,
+    /// The blocks referenced by the pre state root but not by the post state root, if requested.
+    pub gc_candidates: c_slice::Box<c_slice::Box<u8>>,
+    /// The CBOR encoded list of distinct (code, method) pairs invoked by the message, if requested
+    /// and the machine is tracing.
+    pub invoked_methods: Option<c_slice::Box<u8>>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineStateSizeResponse {
+    pub blocks: u64,
+    pub bytes: u64,
+}
+
+/// The first syntax rule violated by a message.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmMessageSyntaxError {
+    NoError,
+    InvalidEncoding,
+    InvalidVersion,
+    InvalidToAddress,
+    InvalidFromAddress,
+    NegativeValue,
+    ValueTooLarge,
+    NegativeGasFeeCap,
+    NegativeGasPremium,
+    GasPremiumAboveFeeCap,
+    GasLimitNotPositive,
+    GasLimitTooLarge,
+}
+
+impl Default for FvmMessageSyntaxError {
+    fn default() -> Self {
+        FvmMessageSyntaxError::NoError
+    }
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmValidateMessageSyntaxResponse {
+    pub valid: bool,
+    pub reason: FvmMessageSyntaxError,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Default, PartialEq)]
+pub struct FvmValidateBatchGasResponse {
+    /// Whether the gas limits of the messages add up to at most the block gas limit.
+    pub fits: bool,
+    /// How much gas the messages' gas limits exceed the block gas limit by, zero if they fit.
+    pub overage: u64,
+}
+
+/// The kind of actor that received a message, according to the machine's actor manifest.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmReceiverKind {
+    /// The receiver doesn't exist, or its code isn't in the manifest.
+    Unknown,
+    Account,
+    Placeholder,
+    Evm,
+    /// Any other builtin actor.
+    Builtin,
+}
+
+impl Default for FvmReceiverKind {
+    fn default() -> Self {
+        FvmReceiverKind::Unknown
+    }
+}
+
+/// The range an exit code falls in.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmExitCodeClass {
+    /// The message succeeded.
+    NoError,
+    /// An error raised by the VM, e.g. running out of gas or an invalid sender.
+    System,
+    /// An error returned by an actor.
+    User,
+}
+
+impl Default for FvmExitCodeClass {
+    fn default() -> Self {
+        FvmExitCodeClass::NoError
+    }
+}
+
+/// Where a machine's actors come from.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmActorSource {
+    /// The builtin bundle for the network version (pre-nv16).
+    Builtin,
+    /// The manifest passed by the caller.
+    Override,
+    /// The manifest in the system actor's state (nv16+).
+    FromState,
+}
+
+impl Default for FvmActorSource {
+    fn default() -> Self {
+        FvmActorSource::FromState
+    }
+}
+
+/// The schema of the Lotus traces returned in execution responses.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmLotusTraceFormat {
+    /// The FVM execution trace of current Lotus releases: `(msg, msg_receipt, error, subcalls)`.
+    Fvm,
+    /// The execution trace of the legacy Lotus VM (up to Lotus v1.15): `(msg, msg_receipt, error,
+    /// duration, gas_charges, subcalls)`. The FVM doesn't time calls or trace gas charges, so the
+    /// duration is always zero and there are no gas charges.
+    Legacy,
+}
+
+impl Default for FvmLotusTraceFormat {
+    fn default() -> Self {
+        FvmLotusTraceFormat::Fvm
+    }
+}
+
+/// The options of `create_fvm_machine`, fixed for the machine's lifetime.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FvmMachineOptions {
+    pub tracing: bool,
+    /// Return traces in the compact binary format.
+    pub compact_trace: bool,
+    pub lotus_trace_format: FvmLotusTraceFormat,
+    /// Report each applied message to the externs.
+    pub stream_summaries: bool,
+    /// The maximum size of the return data captured in execution responses, zero means no cap.
+    pub max_return_size: u64,
+    /// The number of recent executions to record, zero disables recording.
+    pub recent_executions: u64,
+    /// The number of blocks read from the blockstore to cache, zero disables the cache.
+    pub read_cache_size: u64,
+    /// Reject executions and blockstore writes.
+    pub read_only: bool,
+    /// The maximum bytes of flushed blocks to record, zero disables recording.
+    pub flush_write_log: u64,
+}
+
+/// The options of `fvm_machine_execute_message` and `fvm_machine_execute_signed_message`.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FvmExecuteOptions {
+    /// Cap the message's gas limit at this (implicit messages only), zero means no ceiling.
+    pub gas_ceiling: u64,
+    /// Fail if the receipt isn't OK. The message is still applied.
+    pub strict: bool,
+    /// Flush the state tree (without committing it to the underlying blockstore) before and after
+    /// the message to compute the pre and post state roots.
+    pub state_roots: bool,
+    /// The maximum number of bytes of return data, trace, randomness requests, penalty breakdown,
+    /// state proofs, receiver state, GC candidates, and invoked methods captured in the response.
+    /// Zero means unlimited. Exceeding it fails the call, but leaves the message applied.
+    pub allocation_budget: u64,
+    /// Testing only: the message can't run out of gas, and pays no fees. Excludes `gas_ceiling`.
+    pub gas_free: bool,
+    /// Prove the inclusion of the actors changed by the message in the post state root (implies
+    /// `state_roots`). The changed actors are found among the participants of the message: the
+    /// sender, the receiver, the actors paid gas fees, and (if the machine is tracing) the actors
+    /// in any subcalls.
+    pub state_proofs: bool,
+    /// The maximum number of subcalls the message may make, zero means unlimited. Subcalls are
+    /// counted in the execution trace, so the limit is only enforced if the machine is tracing.
+    /// The FVM can't be interrupted, so the limit is checked once the message has run, reverting
+    /// it if exceeded.
+    pub max_subcalls: u64,
+    /// Return the receiver's state block after the message was applied.
+    pub receiver_state: bool,
+    /// Return the blocks the message made unreferenced: the blocks reachable from the pre state
+    /// root but not from the post state root (implies `state_roots`). This walks the whole post
+    /// state, reading at most this many blocks before failing. Zero disables it.
+    pub gc_candidates: u64,
+    /// The number of levels of the Lotus trace to return, the message itself being the first.
+    /// Frames at the last level are returned without their subcalls. Zero means unlimited, and
+    /// compact traces are never truncated.
+    pub trace_depth_limit: u64,
+    /// Return the distinct (actor code, method) pairs invoked by the message. Invocations are found
+    /// in the execution trace, so this is only set if the machine is tracing.
+    pub invoked_methods: bool,
+    /// Look the receiver up before and after the message: its kind, the name and params codec of
+    /// the invoked method, and whether the message created it.
+    pub receiver_info: bool,
+    /// Compute the sender's balance margin, from its balance before the message.
+    pub balance_margin: bool,
+    /// Count the distinct actors involved in the message.
+    pub actors_touched: bool,
+    /// Check the signature of a signed message against the sender's key before applying it. Only
+    /// used by `fvm_machine_execute_signed_message`.
+    pub verify_signature: bool,
+}
+
+/// The options of `fvm_machine_execute_batch`.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FvmBatchOptions {
+    /// The options every message of the batch is applied with.
+    pub execute: FvmExecuteOptions,
+    /// Return the IDs of the actors the batch created.
+    pub created_actors: bool,
+    /// Revert the whole batch if a message doesn't succeed, instead of applying every message.
+    /// Excludes the options computing state roots (`state_roots`, `state_proofs`, and
+    /// `gc_candidates`), the state tree can't be flushed while the batch can still be reverted.
+    pub all_or_nothing: bool,
+    /// Return the gas used by the messages of the batch, added up by receiver.
+    pub receiver_gas: bool,
+}
+
+/// A network version machines can be created for.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FvmSupportedNetworkVersion {
+    pub network_version: u64,
+    /// Where the actors come from if no manifest is passed: `Builtin` if the library bundles them
+    /// for this version, `FromState` otherwise.
+    pub actor_source: FvmActorSource,
+}
+
+/// The first field in which two trace frames differ.
+#[derive_ReprC]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FvmTraceField {
+    NoDivergence,
+    Message,
+    ExitCode,
+    GasUsed,
+    ReturnData,
+    Error,
+    /// The frames have a different number of subcalls (but all common subcalls are equal).
+    Subcalls,
+}
+
+impl Default for FvmTraceField {
+    fn default() -> Self {
+        FvmTraceField::NoDivergence
+    }
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmCompareTracesResponse {
+    /// The subcall indices leading from the root to the first differing frame.
+    pub path: c_slice::Box<u64>,
+    pub field: FvmTraceField,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineGetBlockResponse {
+    pub found: bool,
+    pub block: c_slice::Box<u8>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineFlushResponse {
+    pub state_root: c_slice::Box<u8>,
+    /// True if nothing changed since the last flush, and the cached root was returned.
+    pub was_noop: bool,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineExecuteAndFlushResponse {
+    pub execution: FvmMachineExecuteResponse,
+    /// The state root the machine was flushed to after the message.
+    pub state_root: c_slice::Box<u8>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineExecuteForRootResponse {
+    pub exit_code: u64,
+    /// The state root the machine was flushed to after the message.
+    pub state_root: c_slice::Box<u8>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineExecuteWithAccessListResponse {
+    pub exit_code: u64,
+    /// The state root the machine was flushed to after the message.
+    pub state_root: c_slice::Box<u8>,
+    /// The CBOR encoded `[actors, cids_read, cids_written]` lists of what the message accessed.
+    pub access_list: c_slice::Box<u8>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineExecuteBatchResponse {
+    /// The results of the messages, in order.
+    pub results: c_slice::Box<FvmMachineExecuteResponse>,
+    /// The IDs of the actors created by the batch and still there after it, in increasing order.
+    /// Only set if requested with `created_actors`.
+    pub created_actors: c_slice::Box<u64>,
+    /// Whether an `all_or_nothing` batch was reverted, because the message at `failed_index`
+    /// didn't succeed. `results` then ends with that message's result.
+    pub failed: bool,
+    pub failed_index: u64,
+    /// The gas used by the messages applied, added up by receiver ID, in increasing ID order.
+    /// Receivers are resolved right after their message, those that don't resolve are reported
+    /// as `u64::MAX`. Only set if requested with `receiver_gas`.
+    pub receiver_gas: c_slice::Box<FvmReceiverGas>,
+}
+
+/// The gas used by the messages of a batch sent to a receiver.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FvmReceiverGas {
+    pub receiver_id: u64,
+    pub gas_used: u64,
+}
+
+/// An execution result read back from its serialized form.
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmExecutionResult {
+    pub exit_code: u64,
+    pub return_val: Option<c_slice::Box<u8>>,
+    pub gas_used: u64,
+    pub exec_trace: Option<c_slice::Box<u8>>,
+    pub state_root: c_slice::Box<u8>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmValidateCarResponse {
+    /// True if the CAR is well-formed and has a single root.
+    pub valid: bool,
+    pub root_count: u64,
+    pub roots: c_slice::Box<c_slice::Box<u8>>,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmMachineActorNonceResponse {
+    pub found: bool,
+    pub nonce: u64,
+}
+
+#[derive_ReprC]
+#[repr(C)]
+#[derive(Default)]
+pub struct FvmBlockstoreBenchmarkResponse {
+    /// The number of puts, gets, and has checks performed, in total.
+    pub operations: u64,
+    pub put_ns: u64,
+    pub get_ns: u64,
+    pub has_ns: u64,
+    pub ops_per_second: u64,
 }
@@ -1,21 +1,63 @@
 use std::fs::File;
+use std::io::{self, Write};
 use std::os::unix::io::FromRawFd;
-use std::sync::Once;
+use std::str::FromStr;
+use std::sync::{Mutex, Once};
 
-use anyhow::anyhow;
+use anyhow::{anyhow, bail};
+use flexi_logger::writers::LogWriter;
+use flexi_logger::{DeferredNow, LogSpecification, Logger, LoggerHandle};
+use lazy_static::lazy_static;
+use log::{LevelFilter, Record};
 use safer_ffi::prelude::*;
 
 use super::types::{
     catch_panic_response, catch_panic_response_no_log, GpuDeviceResponse, InitLogFdResponse,
+    SetLogLevelResponse,
 };
 
 /// Protects the init off the logger.
 static LOG_INIT: Once = Once::new();
 
+lazy_static! {
+    /// The handle of the running logger.
+    static ref LOG_HANDLE: Mutex<Option<LoggerHandle>> = Mutex::new(None);
+}
+
+/// Formats log messages the way fil_logger did: `<time> <level> <module> > <message>`.
+fn log_format(w: &mut dyn Write, now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+    write!(
+        w,
+        "{} {} {} > {}",
+        now.now().format("%Y-%m-%dT%H:%M:%S%.3f"),
+        record.level(),
+        record.module_path().unwrap_or("<unnamed>"),
+        record.args()
+    )
+}
+
+/// A logger filtering messages by `spec`.
+fn logger(spec: LogSpecification) -> Logger {
+    Logger::with(spec).format(log_format)
+}
+
+/// A logger filtering messages by `RUST_LOG`, logging at the info level if it isn't set.
+fn default_logger() -> Logger {
+    logger(LogSpecification::env_or_parse("info").unwrap_or_else(|_| LogSpecification::info()))
+}
+
+/// Starts the logger, keeping its handle alive. Starting fails if the host process already
+/// installed a logger, which is then left alone.
+fn start(logger: Logger) {
+    if let Ok(handle) = logger.start() {
+        *LOG_HANDLE.lock().unwrap() = Some(handle);
+    }
+}
+
 /// Ensures the logger is initialized.
 pub fn init_log() {
     LOG_INIT.call_once(|| {
-        fil_logger::init();
+        start(default_logger());
     });
 }
 /// Initialize the logger with a file to log into
@@ -26,12 +68,41 @@ pub fn init_log_with_file(file: File) -> Option<()> {
         None
     } else {
         LOG_INIT.call_once(|| {
-            fil_logger::init_with_file(file);
+            start(default_logger().log_to_writer(Box::new(FileWriter(Mutex::new(file)))));
         });
         Some(())
     }
 }
 
+/// Writes log messages to a file, one per line.
+struct FileWriter(Mutex<File>);
+
+impl LogWriter for FileWriter {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+        let mut file = self.0.lock().unwrap();
+        log_format(&mut *file, now, record)?;
+        writeln!(file)
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.0.lock().unwrap().flush()
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        LevelFilter::Trace
+    }
+}
+
+/// Replaces the filter of the logger behind `handle` with `level`, for every module.
+fn set_level(handle: &mut LoggerHandle, level: &str) -> anyhow::Result<LevelFilter> {
+    let filter =
+        LevelFilter::from_str(level).map_err(|_| anyhow!("invalid log level: {}", level))?;
+    let spec = LogSpecification::parse(level)
+        .map_err(|err| anyhow!("invalid log level {}: {}", level, err))?;
+    handle.set_new_spec(spec);
+    Ok(filter)
+}
+
 /// Returns an array of strings containing the device names that can be used.
 #[ffi_export]
 pub fn get_gpu_devices() -> repr_c::Box<GpuDeviceResponse> {
@@ -65,11 +136,33 @@ pub fn init_log_fd(log_fd: libc::c_int) -> repr_c::Box<InitLogFdResponse> {
     })
 }
 
+/// Sets the log level ("off", "error", "warn", "info", "debug" or "trace") at runtime, replacing
+/// the filter the logger was started with (from `RUST_LOG`) for every module.
+#[ffi_export]
+pub fn set_log_level(level: c_slice::Ref<u8>) -> repr_c::Box<SetLogLevelResponse> {
+    catch_panic_response("set_log_level", || {
+        let level = std::str::from_utf8(&level)?;
+        let mut handle = LOG_HANDLE.lock().unwrap();
+        let handle = match &mut *handle {
+            Some(handle) => handle,
+            None => bail!("the logger isn't initialized, or another logger is installed"),
+        };
+        log::set_max_level(set_level(handle, level)?);
+        Ok(())
+    })
+}
+
 #[cfg(test)]
 mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use flexi_logger::writers::LogWriter;
+    use flexi_logger::{DeferredNow, LogSpecification};
+    use log::{Level, LevelFilter, Log, Record};
 
-    use crate::util::api::get_gpu_devices;
-    use crate::util::types::destroy_gpu_device_response;
+    use crate::util::api::{get_gpu_devices, logger, set_level, set_log_level};
+    use crate::util::types::{destroy_gpu_device_response, destroy_set_log_level_response};
 
     #[test]
     #[allow(clippy::needless_collect)]
@@ -89,6 +182,75 @@ mod tests {
         destroy_gpu_device_response(resp);
     }
 
+    #[test]
+    fn test_set_log_level() {
+        let previous = log::max_level();
+
+        let resp = set_log_level(b"warn"[..].into());
+        assert!(resp.error_msg.is_empty());
+        destroy_set_log_level_response(resp);
+        assert_eq!(log::max_level(), log::LevelFilter::Warn);
+        assert!(log::Level::Info > log::max_level());
+        assert!(log::Level::Warn <= log::max_level());
+
+        let resp = set_log_level(b"loud"[..].into());
+        assert!(!resp.error_msg.is_empty());
+        destroy_set_log_level_response(resp);
+        assert_eq!(log::max_level(), log::LevelFilter::Warn);
+
+        log::set_max_level(previous);
+    }
+
+    /// Captures the messages logged to it.
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl LogWriter for Capture {
+        fn write(&self, _now: &mut DeferredNow, record: &Record) -> io::Result<()> {
+            self.0.lock().unwrap().push(record.args().to_string());
+            Ok(())
+        }
+
+        fn flush(&self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn max_log_level(&self) -> LevelFilter {
+            LevelFilter::Trace
+        }
+    }
+
+    #[test]
+    fn test_set_level() {
+        let previous = log::max_level();
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let (logger, mut handle) = logger(LogSpecification::info())
+            .log_to_writer(Box::new(Capture(captured.clone())))
+            .build()
+            .unwrap();
+        let log_debug = |logger: &dyn Log| {
+            logger.log(
+                &Record::builder()
+                    .level(Level::Debug)
+                    .target("filcrypto")
+                    .args(format_args!("a debug message"))
+                    .build(),
+            )
+        };
+
+        log_debug(&*logger);
+        assert!(captured.lock().unwrap().is_empty());
+
+        assert_eq!(set_level(&mut handle, "debug").unwrap(), LevelFilter::Debug);
+        log_debug(&*logger);
+        assert_eq!(
+            *captured.lock().unwrap(),
+            vec!["a debug message".to_string()]
+        );
+
+        assert!(set_level(&mut handle, "loud").is_err());
+        log::set_max_level(previous);
+    }
+
     #[test]
     #[ignore]
     #[cfg(target_os = "linux")]
@@ -149,6 +149,13 @@ pub fn destroy_init_log_fd_response(ptr: repr_c::Box<InitLogFdResponse>) {
     drop(ptr)
 }
 
+pub type SetLogLevelResponse = Result<()>;
+
+#[ffi_export]
+pub fn destroy_set_log_level_response(ptr: repr_c::Box<SetLogLevelResponse>) {
+    drop(ptr)
+}
+
 /// Catch panics and return an error response
 pub fn catch_panic_response<F, T>(name: &str, callback: F) -> repr_c::Box<Result<T>>
 where